@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use py_marshal::{
+    read::{marshal_load_ex, MarshalLoadExOptions},
+    ObjArena,
+};
+
+#[path = "../src/fixtures.rs"]
+mod fixtures;
+
+fn bench_input(c: &mut Criterion, name: &str, input: &[u8], opts: MarshalLoadExOptions) {
+    c.bench_function(name, |b| {
+        b.iter_batched_ref(
+            ObjArena::new,
+            |arena| {
+                marshal_load_ex(arena, input, opts).unwrap();
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn read_benchmarks(c: &mut Criterion) {
+    let py37 = MarshalLoadExOptions {
+        has_posonlyargcount: false,
+        ..MarshalLoadExOptions::default()
+    };
+    let opts = MarshalLoadExOptions::default();
+    bench_input(c, "many_codeobjects", &fixtures::many_codeobjects(5000), py37);
+    bench_input(c, "large_dict", &fixtures::large_dict(10_000), opts);
+    let unvalidated = MarshalLoadExOptions {
+        validate_strings: false,
+        ..opts
+    };
+    bench_input(c, "large_dict_unvalidated", &fixtures::large_dict(10_000), unvalidated);
+    bench_input(c, "nested_list", &fixtures::nested_list(500), opts);
+    bench_input(c, "big_long", &fixtures::big_long(10_000), opts);
+}
+
+criterion_group!(benches, read_benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,147 @@
+//! Sharing of equal immutable objects, so the serializer can write them once and refer back to
+//! them, like CPython does.
+use crate::Obj;
+#[cfg(feature = "std")]
+use crate::{Code, Int, ObjArena};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+/// Identifies the storage an object points to, or its value for objects stored inline.
+///
+/// Two objects with the same identity are always equal, and are written the same way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Identity(u8, u64, u64);
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn identity(x: &Obj) -> Identity {
+    fn ptr<T: ?Sized>(x: &T) -> u64 {
+        (x as *const T).cast::<u8>() as usize as u64
+    }
+    match *x {
+        Obj::None => Identity(0, 0, 0),
+        Obj::StopIteration => Identity(1, 0, 0),
+        Obj::Ellipsis => Identity(2, 0, 0),
+        Obj::Bool(x) => Identity(3, u64::from(x), 0),
+        Obj::Long(x) => Identity(4, ptr(x), 0),
+        Obj::Float(x) => Identity(5, x.to_bits(), 0),
+        Obj::Complex(x) => Identity(6, x.re.to_bits(), x.im.to_bits()),
+        Obj::Bytes(x) => Identity(7, ptr(x), x.len() as u64),
+        Obj::String(x) => Identity(8, ptr(x), x.len() as u64),
+        Obj::Tuple(x) => Identity(9, ptr(x), x.len() as u64),
+        Obj::List(x) => Identity(10, ptr(x), x.len() as u64),
+        Obj::Dict(x) => Identity(11, ptr(x), x.len() as u64),
+        Obj::Set(x) => Identity(12, ptr(x), x.len() as u64),
+        Obj::FrozenSet(x) => Identity(13, ptr(x), x.len() as u64),
+        Obj::Code(x) => Identity(14, ptr(x), 0),
+        Obj::RawString(x) => Identity(15, ptr(x), x.len() as u64),
+        Obj::Unknown { tag } => Identity(16, u64::from(tag), 0),
+    }
+}
+
+/// The identity of a code object's tuple of names. Empty tuples all share an identity, as they
+/// do in CPython.
+#[cfg(feature = "std")]
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn names_identity(x: &[&str]) -> Identity {
+    Identity(9, x.as_ptr() as usize as u64, x.len() as u64)
+}
+
+#[cfg(feature = "std")]
+pub(crate) struct Deduper<'a> {
+    arena: &'a ObjArena,
+    strings: HashMap<&'a str, &'a str>,
+    bytes: HashMap<&'a [u8], &'a [u8]>,
+    longs: HashMap<&'a Int, &'a Int>,
+    names: HashMap<Vec<&'a str>, &'a [&'a str]>,
+    /// Keyed by whether it's a frozenset, and the (already deduplicated) elements
+    tuples: HashMap<(bool, Vec<Identity>), &'a [Obj<'a>]>,
+}
+#[cfg(feature = "std")]
+impl<'a> Deduper<'a> {
+    pub fn new(arena: &'a ObjArena) -> Self {
+        Self {
+            arena,
+            strings: HashMap::new(),
+            bytes: HashMap::new(),
+            longs: HashMap::new(),
+            names: HashMap::new(),
+            tuples: HashMap::new(),
+        }
+    }
+
+    pub fn dedup(&mut self, x: Obj<'a>) -> Obj<'a> {
+        match x {
+            Obj::Long(x) => Obj::Long(self.longs.entry(x).or_insert(x)),
+            Obj::Bytes(x) => Obj::Bytes(self.bytes.entry(x).or_insert(x)),
+            Obj::RawString(x) => Obj::RawString(self.bytes.entry(x).or_insert(x)),
+            Obj::String(x) => Obj::String(self.string(x)),
+            Obj::Tuple(x) => Obj::Tuple(self.tuple(x, false)),
+            Obj::FrozenSet(x) => Obj::FrozenSet(self.tuple(x, true)),
+            // Mutable, so never shared, but their contents can be
+            Obj::List(x) => Obj::List(self.slice(x)),
+            Obj::Set(x) => Obj::Set(self.slice(x)),
+            Obj::Dict(x) => Obj::Dict(
+                self.arena.alloc_slice_copy(
+                    &x.iter().map(|&(k, v)| (self.dedup(k), self.dedup(v))).collect::<Vec<_>>(),
+                ),
+            ),
+            Obj::Code(x) => Obj::Code(self.arena.alloc(self.code(x))),
+            Obj::None
+            | Obj::StopIteration
+            | Obj::Ellipsis
+            | Obj::Bool(_)
+            | Obj::Float(_)
+            | Obj::Complex(_)
+            | Obj::Unknown { .. } => x,
+        }
+    }
+
+    fn string(&mut self, x: &'a str) -> &'a str {
+        self.strings.entry(x).or_insert(x)
+    }
+
+    fn slice(&mut self, x: &'a [Obj<'a>]) -> &'a [Obj<'a>] {
+        let elems: Vec<_> = x.iter().map(|&x| self.dedup(x)).collect();
+        self.arena.alloc_slice_copy(&elems)
+    }
+
+    fn tuple(&mut self, x: &'a [Obj<'a>], frozen: bool) -> &'a [Obj<'a>] {
+        let elems: Vec<_> = x.iter().map(|&x| self.dedup(x)).collect();
+        let key = (frozen, elems.iter().map(identity).collect());
+        let arena = self.arena;
+        self.tuples
+            .entry(key)
+            .or_insert_with(|| arena.alloc_slice_copy(&elems))
+    }
+
+    fn names(&mut self, x: &'a [&'a str]) -> &'a [&'a str] {
+        let names: Vec<_> = x.iter().map(|x| self.string(x)).collect();
+        let arena = self.arena;
+        self.names
+            .entry(names)
+            .or_insert_with_key(|names| arena.alloc_slice_copy(names))
+    }
+
+    fn code(&mut self, x: &'a Code<'a>) -> Code<'a> {
+        Code {
+            argcount: x.argcount,
+            posonlyargcount: x.posonlyargcount,
+            kwonlyargcount: x.kwonlyargcount,
+            nlocals: x.nlocals,
+            stacksize: x.stacksize,
+            flags: x.flags,
+            code: self.bytes.entry(x.code).or_insert(x.code),
+            consts: self.tuple(x.consts, false),
+            names: self.names(x.names),
+            varnames: self.names(x.varnames),
+            freevars: self.names(x.freevars),
+            cellvars: self.names(x.cellvars),
+            filename: self.string(x.filename),
+            name: self.string(x.name),
+            qualname: self.string(x.qualname),
+            firstlineno: x.firstlineno,
+            lnotab: self.bytes.entry(x.lnotab).or_insert(x.lnotab),
+            exceptiontable: self.bytes.entry(x.exceptiontable).or_insert(x.exceptiontable),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Decoding of [`Code::code`](crate::Code::code) into instructions, like Python's `dis` module.
+//!
+//! Handles both the 2-byte wordcode used since Python 3.6, and Python 2.7's bytecode, where
+//! instructions with an argument take 3 bytes.
+use crate::{
+    opcode::{self, ArgKind, Opcode, EXTENDED_ARG, EXTENDED_ARG_PY27, HAVE_ARGUMENT},
+    Code, MarshalVersion,
+};
+use core::convert::TryFrom;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    /// Byte offset into the code
+    pub offset: usize,
+    pub code: u8,
+    /// `None` if `code` isn't a known opcode for the version.
+    pub opcode: Option<&'static Opcode>,
+    /// The full argument, including any `EXTENDED_ARG` prefixes. `None` for opcodes below
+    /// [`HAVE_ARGUMENT`].
+    pub arg: Option<u32>,
+}
+impl Instruction {
+    #[must_use]
+    pub fn name(&self) -> Option<&'static str> {
+        self.opcode.map(|op| op.name)
+    }
+}
+
+/// Iterator returned by [`disassemble`].
+#[derive(Clone, Debug)]
+pub struct Instructions<'a> {
+    version: MarshalVersion,
+    code: &'a [u8],
+    offset: usize,
+    extended_arg: u32,
+}
+impl Iterator for Instructions<'_> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Instruction> {
+        if self.version == MarshalVersion::Py27 {
+            return self.next_py27();
+        }
+        let unit = self.code.get(self.offset..self.offset + 2)?;
+        let (code, arg) = (unit[0], unit[1]);
+        let offset = self.offset;
+        self.offset += 2;
+        let arg = if code >= HAVE_ARGUMENT {
+            Some(self.extended_arg << 8 | u32::from(arg))
+        } else {
+            None
+        };
+        // Like CPython, the prefix only applies to the instruction right after it
+        self.extended_arg = if code == EXTENDED_ARG { arg.unwrap_or(0) } else { 0 };
+        Some(Instruction {
+            offset,
+            code,
+            opcode: opcode::opcode(self.version, code),
+            arg,
+        })
+    }
+}
+
+impl Instructions<'_> {
+    fn next_py27(&mut self) -> Option<Instruction> {
+        let offset = self.offset;
+        let code = *self.code.get(offset)?;
+        let arg = if code >= HAVE_ARGUMENT {
+            let arg = self.code.get(offset + 1..offset + 3)?;
+            self.offset += 3;
+            Some(self.extended_arg << 16 | u32::from(u16::from_le_bytes([arg[0], arg[1]])))
+        } else {
+            self.offset += 1;
+            None
+        };
+        self.extended_arg = if code == EXTENDED_ARG_PY27 { arg.unwrap_or(0) } else { 0 };
+        Some(Instruction {
+            offset,
+            code,
+            opcode: opcode::opcode(self.version, code),
+            arg,
+        })
+    }
+}
+
+/// Decodes `code`, as compiled by `version`. `EXTENDED_ARG` prefixes are yielded too, like
+/// `dis.get_instructions`; their value is already folded into the next instruction's
+/// [`arg`](Instruction::arg). A truncated instruction at the end is ignored.
+#[must_use]
+pub fn disassemble(version: MarshalVersion, code: &[u8]) -> Instructions<'_> {
+    Instructions {
+        version,
+        code,
+        offset: 0,
+        extended_arg: 0,
+    }
+}
+
+impl<'a> Code<'a> {
+    /// The identifier an instruction's argument refers to, e.g. the global loaded by
+    /// `LOAD_GLOBAL`, or `None` if the opcode doesn't take a name or `arg` is out of range.
+    ///
+    /// Like `dis`, this knows which table each opcode indexes, including two Python 3.11
+    /// changes: the low bit of `LOAD_GLOBAL`'s argument is a flag, and cell and free variables
+    /// are numbered after the locals, with cells that are also arguments only counted once.
+    /// Python 3.12 also uses the low bit of `LOAD_ATTR`'s argument, and the low two bits of
+    /// `LOAD_SUPER_ATTR`'s, as flags.
+    #[must_use]
+    pub fn resolve_name(&self, opcode: u8, arg: u32, version: MarshalVersion) -> Option<&'a str> {
+        let op = opcode::opcode(version, opcode)?;
+        let py311 = version >= MarshalVersion::Py311;
+        let mut index = usize::try_from(arg).ok()?;
+        match op.arg? {
+            ArgKind::Name => {
+                let py312 = version >= MarshalVersion::Py312;
+                match op.name {
+                    "LOAD_GLOBAL" if py311 => index >>= 1,
+                    "LOAD_ATTR" if py312 => index >>= 1,
+                    "LOAD_SUPER_ATTR" if py312 => index >>= 2,
+                    _ => {}
+                }
+                self.names.get(index).copied()
+            }
+            ArgKind::Local => self.varnames.get(index).copied(),
+            ArgKind::Free => {
+                if py311 {
+                    let cells = self.cellvars.iter().filter(|x| !self.varnames.contains(x));
+                    self.varnames.iter().chain(cells).chain(self.freevars).nth(index).copied()
+                } else {
+                    self.cellvars.iter().chain(self.freevars).nth(index).copied()
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Decodes [`exceptiontable`](Code::exceptiontable), which is empty before Python 3.11.
+    #[must_use]
+    pub fn exception_table(&self) -> ExceptionTable<'a> {
+        ExceptionTable {
+            table: self.exceptiontable,
+        }
+    }
+}
+
+/// A range of instructions covered by an exception handler, like `dis`'s
+/// `_ExceptionTableEntry`. Offsets are in bytes, like [`Instruction::offset`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionTableEntry {
+    pub start: usize,
+    /// Exclusive
+    pub end: usize,
+    /// Where the handler starts
+    pub target: usize,
+    /// Stack depth to unwind to before jumping to the handler
+    pub depth: u32,
+    /// Whether the offset of the instruction that raised is pushed too
+    pub lasti: bool,
+}
+
+/// Iterator returned by [`Code::exception_table`].
+///
+/// Each entry is four varints: big-endian groups of 6 bits, with `0x40` set on every byte but
+/// the last (and `0x80` on the first byte of an entry, which is only there to find entries
+/// when searching). They're the start and length in code units, the target in code units,
+/// and the depth shifted left by one with `lasti` in the low bit. A truncated or overflowing
+/// entry at the end is ignored, like `dis` does.
+#[derive(Clone, Debug)]
+pub struct ExceptionTable<'a> {
+    table: &'a [u8],
+}
+impl ExceptionTable<'_> {
+    fn varint(&mut self) -> Option<usize> {
+        let mut value = 0_usize;
+        loop {
+            let (&b, rest) = self.table.split_first()?;
+            self.table = rest;
+            value = value.checked_mul(64)? | usize::from(b & 0x3f);
+            if b & 0x40 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn entry(&mut self) -> Option<ExceptionTableEntry> {
+        let start = self.varint()?.checked_mul(2)?;
+        let len = self.varint()?.checked_mul(2)?;
+        let target = self.varint()?.checked_mul(2)?;
+        let depth_lasti = self.varint()?;
+        Some(ExceptionTableEntry {
+            start,
+            end: start.checked_add(len)?,
+            target,
+            depth: u32::try_from(depth_lasti >> 1).ok()?,
+            lasti: depth_lasti & 1 != 0,
+        })
+    }
+}
+impl Iterator for ExceptionTable<'_> {
+    type Item = ExceptionTableEntry;
+
+    fn next(&mut self) -> Option<ExceptionTableEntry> {
+        let entry = self.entry();
+        if entry.is_none() {
+            self.table = &[];
+        }
+        entry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disassemble, ExceptionTableEntry};
+    use crate::{CodeBuilder, MarshalVersion};
+
+    #[test]
+    fn test_disassemble() {
+        // LOAD_CONST 1; RETURN_VALUE
+        let instructions: Vec<_> = disassemble(MarshalVersion::Py38, b"d\x01S\x00").collect();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].name(), Some("LOAD_CONST"));
+        assert_eq!(instructions[0].arg, Some(1));
+        assert_eq!(instructions[1].name(), Some("RETURN_VALUE"));
+        assert_eq!(instructions[1].offset, 2);
+        assert_eq!(instructions[1].arg, None);
+    }
+
+    #[test]
+    fn test_extended_arg() {
+        // EXTENDED_ARG 1; EXTENDED_ARG 2; LOAD_CONST 3; LOAD_CONST 4
+        let code = b"\x90\x01\x90\x02d\x03d\x04";
+        let args: Vec<_> = disassemble(MarshalVersion::Py38, code)
+            .map(|x| (x.name().unwrap(), x.arg.unwrap()))
+            .collect();
+        assert_eq!(
+            args,
+            [
+                ("EXTENDED_ARG", 0x01),
+                ("EXTENDED_ARG", 0x0102),
+                ("LOAD_CONST", 0x01_0203),
+                ("LOAD_CONST", 0x04),
+            ]
+        );
+        // Instructions without an argument still reset the prefix
+        let code = b"\x90\x01\x01\x00d\x05";
+        let last = disassemble(MarshalVersion::Py311, code).last().unwrap();
+        assert_eq!(last.arg, Some(5));
+    }
+
+    #[test]
+    fn test_disassemble_py27() {
+        // EXTENDED_ARG 1; LOAD_CONST 2; RETURN_VALUE
+        let code = b"\x91\x01\x00d\x02\x00S";
+        let args: Vec<_> = disassemble(MarshalVersion::Py27, code)
+            .map(|x| (x.offset, x.name().unwrap(), x.arg))
+            .collect();
+        assert_eq!(
+            args,
+            [
+                (0, "EXTENDED_ARG", Some(1)),
+                (3, "LOAD_CONST", Some(0x01_0002)),
+                (6, "RETURN_VALUE", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_name() {
+        let code = CodeBuilder::new()
+            .with_names(&["print"])
+            .with_varnames(&["x", "y"])
+            .with_cellvars(&["y", "c"])
+            .with_freevars(&["f"])
+            .finish();
+        let (load_global, load_fast, load_deref, load_const) = (116, 124, 136, 100);
+        assert_eq!(code.resolve_name(load_global, 0, MarshalVersion::Py310), Some("print"));
+        assert_eq!(code.resolve_name(load_fast, 1, MarshalVersion::Py310), Some("y"));
+        assert_eq!(code.resolve_name(load_deref, 1, MarshalVersion::Py310), Some("c"));
+        assert_eq!(code.resolve_name(load_deref, 2, MarshalVersion::Py310), Some("f"));
+        assert_eq!(code.resolve_name(load_deref, 3, MarshalVersion::Py310), None);
+        assert_eq!(code.resolve_name(load_const, 0, MarshalVersion::Py310), None);
+
+        // The low bit says whether to push a NULL first
+        assert_eq!(code.resolve_name(load_global, 1, MarshalVersion::Py311), Some("print"));
+        // x, y (also a cell), c, f
+        let load_deref = 137;
+        assert_eq!(code.resolve_name(load_deref, 1, MarshalVersion::Py311), Some("y"));
+        assert_eq!(code.resolve_name(load_deref, 2, MarshalVersion::Py311), Some("c"));
+        assert_eq!(code.resolve_name(load_deref, 3, MarshalVersion::Py311), Some("f"));
+
+        let (load_attr, load_super_attr) = (106, 141);
+        assert_eq!(code.resolve_name(load_attr, 1, MarshalVersion::Py311), None);
+        assert_eq!(code.resolve_name(load_attr, 1, MarshalVersion::Py312), Some("print"));
+        assert_eq!(code.resolve_name(load_super_attr, 3, MarshalVersion::Py312), Some("print"));
+    }
+    #[test]
+    fn test_exception_table() {
+        // try: return g(a) / except E: return lambda: a, compiled by Python 3.12
+        let code = CodeBuilder::new()
+            .with_exceptiontable(b"\x83\x0a\x0e\x00\x8e\x0e\x1f\x03\x9e\x01\x1f\x03")
+            .finish();
+        let entry = |start, end, target, depth, lasti| ExceptionTableEntry {
+            start,
+            end,
+            target,
+            depth,
+            lasti,
+        };
+        assert_eq!(
+            code.exception_table().collect::<Vec<_>>(),
+            [entry(6, 26, 28, 0, false), entry(28, 56, 62, 1, true), entry(60, 62, 62, 1, true)]
+        );
+        // A two-byte start (1 << 6 | 2), then an entry cut short
+        let code = CodeBuilder::new().with_exceptiontable(b"\xc1\x02\x01\x02\x03\x81").finish();
+        assert_eq!(code.exception_table().collect::<Vec<_>>(), [entry(66 * 2, 67 * 2, 4, 1, true)]);
+        assert_eq!(CodeBuilder::new().finish().exception_table().count(), 0);
+    }
+}
@@ -0,0 +1,53 @@
+//! Generators for marshalled inputs, shared by the tests and the benchmarks.
+
+/// A tuple of `n` copies of the `test_exceptions` code object from CPython 3.7's
+/// `test_marshal.py`, all but the first written as refs. Needs `has_posonlyargcount: false`.
+#[must_use]
+pub fn many_codeobjects(n: u32) -> Vec<u8> {
+    let mut out = vec![b'('];
+    out.extend_from_slice(&n.to_le_bytes());
+    if n == 0 {
+        return out;
+    }
+    out.extend_from_slice(b"\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00C\x00\x00\x00s \x00\x00\x00t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00)\x01N)\x05\xda\x07marshal\xda\x05loads\xda\x05dumps\xda\rStopIteration\xda\x0bassertEqual)\x02\xda\x04self\xda\x03new\xa9\x00r\x08\x00\x00\x00\xda\x08<string>\xda\x0ftest_exceptions\x03\x00\x00\x00s\x04\x00\x00\x00\x00\x01\x10\x01");
+    for _ in 1..n {
+        out.extend_from_slice(b"r\x00\x00\x00\x00");
+    }
+    out
+}
+
+/// A dict mapping `"key{i}"` to `i` for `i` in `0..n`.
+#[must_use]
+pub fn large_dict(n: u32) -> Vec<u8> {
+    let mut out = vec![b'{'];
+    for i in 0..n {
+        let key = format!("key{}", i);
+        out.push(b'z');
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(key.len() as u8);
+        out.extend_from_slice(key.as_bytes());
+        out.push(b'i');
+        out.extend_from_slice(&i.to_le_bytes());
+    }
+    out.push(b'0');
+    out
+}
+
+/// `depth` single-element lists nested inside each other, around a `None`.
+#[must_use]
+pub fn nested_list(depth: usize) -> Vec<u8> {
+    let mut out = b"[\x01\x00\x00\x00".repeat(depth);
+    out.push(b'N');
+    out
+}
+
+/// A positive long of `digits` 15-bit digits, all set to `0x7fff`.
+#[must_use]
+pub fn big_long(digits: u32) -> Vec<u8> {
+    let mut out = vec![b'l'];
+    out.extend_from_slice(&digits.to_le_bytes());
+    for _ in 0..digits {
+        out.extend_from_slice(&0x7fff_u16.to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,179 @@
+//! Python's `hash()`, for the objects whose hash doesn't depend on the process.
+//!
+//! See `Python/pyhash.c`, `Objects/longobject.c` and `Objects/tupleobject.c` in CPython.
+use crate::{Int, Obj};
+use num_traits::ToPrimitive;
+
+/// Numeric hashes are reduced modulo this Mersenne prime, so equal ints and floats hash equally.
+const MODULUS: u64 = (1 << BITS) - 1;
+const BITS: u32 = 61;
+const INF: i64 = 314_159;
+const IMAG: u64 = 1_000_003;
+/// `hash(None)` since Python 3.12; before, it was based on the address of `None`.
+const NONE: i64 = 0xfca8_6420;
+
+const XXPRIME_1: u64 = 11_400_714_785_074_694_791;
+const XXPRIME_2: u64 = 14_029_467_366_897_019_727;
+const XXPRIME_5: u64 = 2_870_177_450_012_600_261;
+
+impl Obj<'_> {
+    /// What Python's `hash()` returns for this object, or `None` if it's unhashable or its hash
+    /// isn't deterministic.
+    ///
+    /// Ints, bools, floats, complexes and tuples of those hash like Python 3.8+ on a 64-bit
+    /// platform, e.g. to cross-check the iteration order of a set. `None` hashes like Python
+    /// 3.12+, where it became a constant. Strs and bytes use SipHash keyed with a per-process
+    /// random seed, so only empty ones (always 0) get a hash here. `nan`s, `Ellipsis` and
+    /// `StopIteration` hash by address, and code objects aren't covered.
+    #[must_use]
+    pub fn hash_value(&self) -> Option<i64> {
+        match *self {
+            Obj::None => Some(NONE),
+            Obj::Bool(x) => Some(i64::from(x)),
+            Obj::Long(x) => Some(hash_int(x)),
+            Obj::Float(x) => hash_float(x),
+            Obj::Complex(x) => {
+                let combined = (hash_float(x.re)? as u64)
+                    .wrapping_add(IMAG.wrapping_mul(hash_float(x.im)? as u64));
+                Some(fix_minus_one(combined as i64))
+            }
+            Obj::Bytes(&[]) | Obj::String("") | Obj::RawString(&[]) => Some(0),
+            Obj::Tuple(x) => hash_tuple(x),
+            _ => None,
+        }
+    }
+}
+
+/// -1 is an error code in CPython, so no hash is ever -1.
+fn fix_minus_one(hash: i64) -> i64 {
+    if hash == -1 {
+        -2
+    } else {
+        hash
+    }
+}
+
+/// The hash is the value modulo [`MODULUS`], with the sign of the value.
+fn hash_int(x: &Int) -> i64 {
+    // Rounds towards zero, so the remainder has the sign of `x`
+    let hash = (x % Int::from(MODULUS)).to_i64().unwrap_or(0);
+    fix_minus_one(hash)
+}
+
+/// `_Py_HashDouble`: the hash of the rational the float is equal to, so that it matches the
+/// hash of an equal int.
+#[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn hash_float(x: f64) -> Option<i64> {
+    if x.is_nan() {
+        return None;
+    }
+    if x.is_infinite() {
+        return Some(if x > 0.0 { INF } else { -INF });
+    }
+    let (mut m, mut e) = frexp(x);
+    let negative = m < 0.0;
+    m = m.abs();
+    let mut hash = 0_u64;
+    while m != 0.0 {
+        hash = ((hash << 28) & MODULUS) | hash >> (BITS - 28);
+        m *= 268_435_456.0; // 2**28
+        e -= 28;
+        let y = m as u64; // The integer part
+        m -= y as f64;
+        hash += y;
+        if hash >= MODULUS {
+            hash -= MODULUS;
+        }
+    }
+    // Multiply by 2**e, which is a rotation modulo a Mersenne prime
+    let e = e.rem_euclid(BITS as i32) as u32;
+    hash = ((hash << e) & MODULUS) | hash >> (BITS - e);
+    let hash = hash as i64;
+    Some(fix_minus_one(if negative { -hash } else { hash }))
+}
+
+/// Splits `x` into a mantissa in `[0.5, 1)` (with the sign of `x`) and a power of 2, like C's
+/// `frexp`. Only for finite `x`.
+#[allow(clippy::cast_possible_truncation)]
+fn frexp(x: f64) -> (f64, i32) {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    if x == 0.0 {
+        (x, 0)
+    } else if exponent == 0 {
+        // Subnormal, so scale it into the normal range first
+        let (m, e) = frexp(x * f64::from_bits(0x4350_0000_0000_0000)); // 2**54
+        (m, e - 54)
+    } else {
+        (f64::from_bits(bits & !(0x7ff << 52) | 1022 << 52), exponent - 1022)
+    }
+}
+
+/// The xxHash-based combination used since Python 3.8.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn hash_tuple(x: &[Obj]) -> Option<i64> {
+    let mut acc = XXPRIME_5;
+    for elem in x {
+        let lane = elem.hash_value()? as u64;
+        acc = acc.wrapping_add(lane.wrapping_mul(XXPRIME_2));
+        acc = acc.rotate_left(31);
+        acc = acc.wrapping_mul(XXPRIME_1);
+    }
+    acc = acc.wrapping_add(x.len() as u64 ^ (XXPRIME_5 ^ 3_527_539));
+    Some(if acc == u64::MAX { 1_546_275_796 } else { acc as i64 })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Obj, ObjArena};
+    use crate::Int;
+    use num_complex::Complex;
+    use num_traits::pow;
+
+    #[test]
+    fn test_hash_value() {
+        let arena = ObjArena::new();
+        let int = |x: i64| Obj::from_i64(&arena, x);
+        let big = |x: Int| Obj::from_bigint(&arena, x);
+        // From Python 3.12's hash()
+        let cases = [
+            (Obj::None, Some(4_238_894_112)),
+            (Obj::Bool(true), Some(1)),
+            (int(0), Some(0)),
+            (int(-1), Some(-2)),
+            (int(-2), Some(-2)),
+            (int(i64::MAX), Some(3)),
+            (big(pow(Int::from(2), 61) - 1), Some(0)),
+            (big(-pow(Int::from(2), 100)), Some(-549_755_813_888)),
+            (big(Int::from(i128::MAX)), Some(31)),
+            (big(Int::from(i128::MIN)), Some(-32)),
+            (Obj::Float(1.0), Some(1)),
+            (Obj::Float(-0.0), Some(0)),
+            (Obj::Float(1.5), Some(1_152_921_504_606_846_977)),
+            (Obj::Float(-3.25), Some(-576_460_752_303_423_491)),
+            (Obj::Float(1e300), Some(1_224_995_262_755_759_164)),
+            (Obj::Float(5e-324), Some(16_777_216)),
+            (Obj::Float(0.1), Some(230_584_300_921_369_408)),
+            (Obj::Float(f64::INFINITY), Some(314_159)),
+            (Obj::Float(f64::NEG_INFINITY), Some(-314_159)),
+            (Obj::Float(f64::NAN), None),
+            (Obj::Complex(Complex::new(1.0, 2.0)), Some(2_000_007)),
+            (Obj::Complex(Complex::new(0.0, -1.0)), Some(-2_000_006)),
+            (Obj::Bytes(b""), Some(0)),
+            (Obj::String(""), Some(0)),
+            (Obj::String("a"), None),
+            (Obj::Tuple(&[]), Some(5_740_354_900_026_072_187)),
+            (Obj::Tuple(&[Obj::Bool(true), Obj::None]), Some(-1_278_408_582_678_055_408)),
+            (Obj::Tuple(&[Obj::Float(f64::NAN)]), None),
+            (Obj::List(&[]), None),
+        ];
+        for (obj, expected) in &cases {
+            assert_eq!(obj.hash_value(), *expected, "{:?}", obj);
+        }
+        // Equal numbers hash equally
+        assert_eq!(
+            big(pow(Int::from(2), 70)).hash_value(),
+            Obj::Float(2.0_f64.powi(70)).hash_value()
+        );
+    }
+}
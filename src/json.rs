@@ -0,0 +1,303 @@
+//! Conversion of [`Obj`] trees to JSON, tagging each value with its Python type where JSON
+//! can't tell them apart.
+//!
+//! Strings, bools, ints and finite floats map to plain JSON values. Everything else becomes
+//! `{"type": <Python type name>, "value": ...}`, e.g. `{"type": "tuple", "value": [1, 2]}`.
+//! Infinite and NaN floats, which JSON has no numbers for, are `{"type": "float", "value":
+//! "inf"}` and so on, with the same strings standing in for them inside a complex.
+//!
+//! A dict is a JSON object if all its keys are strs, or else a list of `[key, value]` pairs, so
+//! that e.g. `1` and `"1"` can both be keys.
+use crate::{set_order, Code, Int, Obj};
+use num_traits::ToPrimitive;
+use serde_json::{json, Map, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// A standard base64 string
+    Base64,
+    /// An array of numbers
+    Array,
+}
+
+/// What to do with ints that don't fit in a JSON number (`i64` or `u64`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BigIntEncoding {
+    /// `{"type": "int", "value": <decimal string>}`, so it can't be mistaken for a `str`
+    Tagged,
+    /// Use the decimal string, e.g. `"1267650600228229401496703205376"`
+    String,
+    /// Fail with [`JsonError::IntTooLarge`]
+    Error,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct JsonOptions {
+    pub bytes: BytesEncoding,
+    pub big_ints: BigIntEncoding,
+    /// Sort the elements of sets and frozensets, so the output doesn't depend on the order
+    /// CPython happened to write them in.
+    pub sort_sets: bool,
+}
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            bytes: BytesEncoding::Base64,
+            big_ints: BigIntEncoding::Tagged,
+            sort_sets: true,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JsonError {
+    #[error("Integer too large for a JSON number: {0}")]
+    IntTooLarge(Int),
+}
+
+/// # Errors
+/// See [`JsonError`].
+pub fn obj_to_json(obj: &Obj, opts: JsonOptions) -> Result<Value, JsonError> {
+    Ok(match *obj {
+        Obj::None => tagged("NoneType", Value::Null),
+        Obj::StopIteration => tagged("StopIteration", Value::Null),
+        Obj::Ellipsis => tagged("ellipsis", Value::Null),
+        Obj::Bool(x) => json!(x),
+        Obj::Long(x) => int_to_json(x, opts)?,
+        Obj::Float(x) if x.is_finite() => json!(x),
+        Obj::Float(x) => tagged("float", float_to_json(x)),
+        Obj::Complex(x) => tagged("complex", json!([float_to_json(x.re), float_to_json(x.im)])),
+        Obj::Bytes(x) => bytes_to_json(x, opts),
+        Obj::String(x) => json!(x),
+        Obj::RawString(x) => json!(String::from_utf8_lossy(x)),
+        Obj::Tuple(x) => tagged("tuple", objs_to_json(x.iter(), opts)?),
+        Obj::List(x) => tagged("list", objs_to_json(x.iter(), opts)?),
+        Obj::Set(x) => tagged("set", set_to_json(x, opts)?),
+        Obj::FrozenSet(x) => tagged("frozenset", set_to_json(x, opts)?),
+        Obj::Dict(x) => tagged("dict", dict_to_json(x, opts)?),
+        Obj::Code(x) => tagged("code", code_to_json(x, opts)?),
+        Obj::Unknown { tag } => tagged("unknown", json!(tag)),
+    })
+}
+
+fn tagged(type_name: &str, value: Value) -> Value {
+    json!({"type": type_name, "value": value})
+}
+
+/// A finite float as a number, or else its Python repr, e.g. `"-inf"`.
+fn float_to_json(x: f64) -> Value {
+    if x.is_finite() {
+        json!(x)
+    } else if x.is_nan() {
+        json!("nan")
+    } else if x > 0.0 {
+        json!("inf")
+    } else {
+        json!("-inf")
+    }
+}
+
+fn dict_to_json(x: &[(Obj, Obj)], opts: JsonOptions) -> Result<Value, JsonError> {
+    let mut map = Map::new();
+    for (key, value) in x {
+        let key = match *key {
+            Obj::String(key) => key.to_owned(),
+            Obj::RawString(key) => String::from_utf8_lossy(key).into_owned(),
+            _ => break,
+        };
+        // Invalid UTF-8 can make two RawStrings the same key
+        if map.insert(key, obj_to_json(value, opts)?).is_some() {
+            break;
+        }
+    }
+    if map.len() == x.len() {
+        return Ok(Value::Object(map));
+    }
+    // JSON keys have to be strings, so use pairs instead
+    x.iter()
+        .map(|(key, value)| Ok(json!([obj_to_json(key, opts)?, obj_to_json(value, opts)?])))
+        .collect()
+}
+
+fn int_to_json(x: &Int, opts: JsonOptions) -> Result<Value, JsonError> {
+    if let Some(x) = x.to_i64() {
+        Ok(json!(x))
+    } else if let Some(x) = x.to_u64() {
+        Ok(json!(x))
+    } else {
+        match opts.big_ints {
+            BigIntEncoding::Tagged => Ok(tagged("int", json!(x.to_string()))),
+            BigIntEncoding::String => Ok(json!(x.to_string())),
+            BigIntEncoding::Error => Err(JsonError::IntTooLarge(Int::clone(x))),
+        }
+    }
+}
+
+fn bytes_to_json(x: &[u8], opts: JsonOptions) -> Value {
+    let value = match opts.bytes {
+        BytesEncoding::Base64 => json!(base64::encode(x)),
+        BytesEncoding::Array => json!(x),
+    };
+    tagged("bytes", value)
+}
+
+fn objs_to_json<'a>(
+    objs: impl Iterator<Item = &'a Obj<'a>>,
+    opts: JsonOptions,
+) -> Result<Value, JsonError> {
+    objs.map(|x| obj_to_json(x, opts)).collect()
+}
+
+fn set_to_json(x: &[Obj], opts: JsonOptions) -> Result<Value, JsonError> {
+    if opts.sort_sets {
+        let mut sorted: Vec<&Obj> = x.iter().collect();
+        sorted.sort_by(|a, b| set_order(a, b));
+        objs_to_json(sorted.into_iter(), opts)
+    } else {
+        objs_to_json(x.iter(), opts)
+    }
+}
+
+fn code_to_json(x: &Code, opts: JsonOptions) -> Result<Value, JsonError> {
+    Ok(json!({
+        "co_argcount": x.argcount,
+        "co_posonlyargcount": x.posonlyargcount,
+        "co_kwonlyargcount": x.kwonlyargcount,
+        "co_nlocals": x.nlocals,
+        "co_stacksize": x.stacksize,
+        "co_flags": x.flags.bits(),
+        "co_code": bytes_to_json(x.code, opts),
+        "co_consts": objs_to_json(x.consts.iter(), opts)?,
+        "co_names": x.names,
+        "co_varnames": x.varnames,
+        "co_freevars": x.freevars,
+        "co_cellvars": x.cellvars,
+        "co_filename": x.filename,
+        "co_name": x.name,
+        "co_qualname": x.qualname,
+        "co_firstlineno": x.firstlineno,
+        "co_lnotab": bytes_to_json(x.lnotab, opts),
+        "co_exceptiontable": bytes_to_json(x.exceptiontable, opts),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{obj_to_json, BigIntEncoding, BytesEncoding, JsonError, JsonOptions};
+    use crate::{Int, Obj, ObjArena};
+    use num_complex::Complex;
+    use num_traits::pow;
+    use serde_json::json;
+
+    #[test]
+    fn test_obj_to_json() {
+        let opts = JsonOptions::default();
+        let obj = Obj::Dict(&[
+            (Obj::String("a"), Obj::Tuple(&[Obj::None, Obj::Bool(true), Obj::Float(1.5)])),
+            (
+                Obj::String("s"),
+                Obj::FrozenSet(&[Obj::String("b"), Obj::Bytes(b"x"), Obj::String("a")]),
+            ),
+        ]);
+        assert_eq!(
+            obj_to_json(&obj, opts).unwrap(),
+            json!({"type": "dict", "value": {
+                "a": {"type": "tuple", "value": [{"type": "NoneType", "value": null}, true, 1.5]},
+                "s": {"type": "frozenset", "value": [{"type": "bytes", "value": "eA=="}, "a", "b"]},
+            }})
+        );
+        assert_eq!(
+            obj_to_json(
+                &Obj::Bytes(b"\x00\xff"),
+                JsonOptions {
+                    bytes: BytesEncoding::Array,
+                    ..opts
+                }
+            )
+            .unwrap(),
+            json!({"type": "bytes", "value": [0, 255]})
+        );
+    }
+
+    #[test]
+    fn test_non_str_keys() {
+        let arena = ObjArena::new();
+        let opts = JsonOptions::default();
+        // {1: "a", "1": "b"}, whose keys would collide as JSON object keys
+        let obj = Obj::Dict(&[
+            (Obj::from_i64(&arena, 1), Obj::String("a")),
+            (Obj::String("1"), Obj::String("b")),
+        ]);
+        assert_eq!(
+            obj_to_json(&obj, opts).unwrap(),
+            json!({"type": "dict", "value": [[1, "a"], ["1", "b"]]})
+        );
+        let obj = Obj::Dict(&[
+            (Obj::String("a"), Obj::Tuple(&[Obj::None, Obj::Bool(true), Obj::Float(1.5)])),
+            (Obj::Bool(false), Obj::Bytes(b"\x00\xff")),
+            (
+                Obj::String("s"),
+                Obj::FrozenSet(&[Obj::String("b"), Obj::Bytes(b"x"), Obj::String("a")]),
+            ),
+        ]);
+        assert_eq!(
+            obj_to_json(&obj, opts).unwrap(),
+            json!({"type": "dict", "value": [
+                ["a", {"type": "tuple", "value": [{"type": "NoneType", "value": null}, true, 1.5]}],
+                [false, {"type": "bytes", "value": "AP8="}],
+                ["s", {"type": "frozenset", "value": [{"type": "bytes", "value": "eA=="}, "a", "b"]}],
+            ]})
+        );
+        // Different invalid UTF-8 that reads back as the same key
+        let obj = Obj::Dict(&[(Obj::RawString(b"\xff"), Obj::None), (Obj::RawString(b"\xfe"), Obj::None)]);
+        assert_eq!(obj_to_json(&obj, opts).unwrap()["value"][1][0], json!("\u{fffd}"));
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        let opts = JsonOptions::default();
+        for &(x, expected) in &[(f64::NAN, "nan"), (f64::INFINITY, "inf"), (f64::NEG_INFINITY, "-inf")] {
+            assert_eq!(
+                obj_to_json(&Obj::Float(x), opts).unwrap(),
+                json!({"type": "float", "value": expected})
+            );
+        }
+        assert_eq!(
+            obj_to_json(&Obj::Complex(Complex::new(f64::INFINITY, 1.0)), opts).unwrap(),
+            json!({"type": "complex", "value": ["inf", 1.0]})
+        );
+        assert_eq!(
+            obj_to_json(&Obj::Complex(Complex::new(-0.5, f64::NAN)), opts).unwrap(),
+            json!({"type": "complex", "value": [-0.5, "nan"]})
+        );
+    }
+
+    #[test]
+    fn test_big_ints() {
+        let big = pow(Int::from(2), 100);
+        let obj = Obj::Long(&big);
+        let with = |big_ints| obj_to_json(&obj, JsonOptions { big_ints, ..JsonOptions::default() });
+        assert_eq!(
+            with(BigIntEncoding::Tagged).unwrap(),
+            json!({"type": "int", "value": "1267650600228229401496703205376"})
+        );
+        assert_eq!(
+            with(BigIntEncoding::String).unwrap(),
+            json!("1267650600228229401496703205376")
+        );
+        match with(BigIntEncoding::Error).unwrap_err() {
+            JsonError::IntTooLarge(x) => assert_eq!(x, big),
+        }
+        // Anything that fits in an i64 or u64 stays a number
+        for (x, expected) in &[
+            (Int::from(i64::MIN), json!(i64::MIN)),
+            (Int::from(u64::MAX), json!(u64::MAX)),
+        ] {
+            let opts = JsonOptions {
+                big_ints: BigIntEncoding::Error,
+                ..JsonOptions::default()
+            };
+            assert_eq!(obj_to_json(&Obj::Long(x), opts).unwrap(), *expected);
+        }
+    }
+}
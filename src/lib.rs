@@ -1,20 +1,79 @@
 // Ported from <https://github.com/python/cpython/blob/master/Python/marshal.c>
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+use alloc::{borrow::ToOwned, boxed::Box, collections::BTreeSet, format, string::String, vec, vec::Vec};
 use bitflags::bitflags;
-use num_bigint::BigInt;
 use num_complex::Complex;
 use num_derive::{FromPrimitive, ToPrimitive};
-use std::{
-    collections::{HashMap, HashSet},
-    convert::TryFrom,
-    fmt,
-    hash::{Hash, Hasher},
-    iter::FromIterator,
-    cell::Cell
-};
+use num_traits::ToPrimitive as _;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use core::{cmp::Ordering, convert::TryFrom, fmt, ops::ControlFlow};
+
+/// Python's `int`: arbitrary precision with the `bigint` feature (the default), otherwise an
+/// `i128`, and reading a larger int fails with
+/// [`IntegerTooLarge`](read::errors::Error::IntegerTooLarge).
+#[cfg(feature = "bigint")]
+pub type Int = num_bigint::BigInt;
+#[cfg(not(feature = "bigint"))]
+pub type Int = i128;
+
+/// Owns everything an [`Obj`] points to.
+///
+/// Readers and constructors take `&'a ObjArena` and return `Obj<'a>`, so objects stay valid for
+/// as long as the arena is borrowed, and are all freed together when it's dropped. The `alloc*`
+/// methods let callers put their own data next to parsed objects, e.g. to build a replacement
+/// constant.
+#[derive(Default)]
+pub struct ObjArena {
+    bump: bumpalo::Bump,
+    // Unlike `bump`, this runs destructors, so the digits of big ints aren't leaked
+    bigints: typed_arena::Arena<Int>,
+}
+impl ObjArena {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accepts `Copy` types, since the arena never runs destructors; use
+    /// [`alloc_bigint`](Self::alloc_bigint) for ints.
+    pub fn alloc<T: Copy>(&self, val: T) -> &mut T {
+        self.bump.alloc(val)
+    }
+
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &mut [T] {
+        self.bump.alloc_slice_copy(src)
+    }
 
-pub type ObjArena = bumpalo::Bump;
+    pub fn alloc_str(&self, src: &str) -> &mut str {
+        self.bump.alloc_str(src)
+    }
+
+    pub fn alloc_bigint(&self, val: Int) -> &mut Int {
+        self.bigints.alloc(val)
+    }
+
+    pub(crate) fn alloc_slice_fill_iter<T: Copy, I>(&self, iter: I) -> &mut [T]
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.bump.alloc_slice_fill_iter(iter)
+    }
+}
+impl fmt::Debug for ObjArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ObjArena")
+            .field("allocated_bytes", &self.bump.allocated_bytes())
+            .field("bigints", &self.bigints.len())
+            .finish()
+    }
+}
 
 #[derive(FromPrimitive, ToPrimitive, Debug, Copy, Clone)]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 #[repr(u8)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 enum Type {
@@ -33,6 +92,7 @@ enum Type {
     Long               = b'l',
     String             = b's',
     Interned           = b't',
+    StringRef          = b'R',
     Ref                = b'r',
     Tuple              = b'(',
     List               = b'[',
@@ -48,28 +108,44 @@ enum Type {
     ShortAscii         = b'z',
     ShortAsciiInterned = b'Z',
 }
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 impl Type {
     const FLAG_REF: u8 = b'\x80';
 }
 
-struct Depth<'a>(&'a Cell<usize>);
+/// Counts how many handles are alive, like an `Rc`'s strong count, but with the counter
+/// allocated in the arena.
+#[cfg(feature = "std")]
+struct Depth<'a>(&'a Cell<usize>, usize);
+#[cfg(feature = "std")]
 impl<'a> Depth<'a> {
-    const MAX: usize = 900;
-
+    /// Handles beyond `max` nested clones are refused.
     #[must_use]
-    pub fn new(arena: &'a ObjArena) -> Self {
-        Self(Cell::new())
+    pub fn new(arena: &'a ObjArena, max: usize) -> Self {
+        Self(arena.bump.alloc(Cell::new(1)), max)
+    }
+
+    /// Handles alive besides the original, i.e. how deeply nested the current object is.
+    pub fn nesting(&self) -> usize {
+        self.0.get() - 1
     }
 
     pub fn try_clone(&self) -> Option<Self> {
-        if self.0.get() > Self::MAX {
+        if self.0.get() > self.1 {
             None
         } else {
-            self.cell.set(self.cell.get() + 1);
-            Some(Self(self.cell))
+            self.0.set(self.0.get() + 1);
+            Some(Self(self.0, self.1))
         }
     }
 }
+#[cfg(feature = "std")]
+impl Drop for Depth<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+#[cfg(feature = "std")]
 impl<'a> fmt::Debug for Depth<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         f.debug_tuple("Depth")
@@ -77,7 +153,54 @@ impl<'a> fmt::Debug for Depth<'a> {
             .finish()
     }
 }
+
+/// A Python version, for the parts of the marshal format and bytecode that differ between them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MarshalVersion {
+    Py27,
+    Py37,
+    Py38,
+    Py39,
+    Py310,
+    Py311,
+    Py312,
+}
+
+/// Range of magic numbers (including development releases) used by each version, from
+/// `importlib/_bootstrap_external.py`.
+const MAGIC_NUMBERS: &[(u16, u16, MarshalVersion)] = &[
+    (62171, 62211, MarshalVersion::Py27),
+    (3390, 3394, MarshalVersion::Py37),
+    (3400, 3413, MarshalVersion::Py38),
+    (3420, 3425, MarshalVersion::Py39),
+    (3430, 3439, MarshalVersion::Py310),
+    (3450, 3495, MarshalVersion::Py311),
+    (3500, 3531, MarshalVersion::Py312),
+];
+
+/// The version whose `.pyc` files start with `magic`, for callers that parse the header
+/// themselves; pass it to `MarshalLoadExOptions::for_version` to read the code object that
+/// follows. Takes either the 16-bit magic number or the whole first word of the file, which
+/// ends in `\r\n` (read as a little-endian `u32`).
+#[must_use]
+pub fn magic_to_marshal_version(magic: u32) -> Option<MarshalVersion> {
+    let magic = if magic >> 16 == 0x0a0d { magic & 0xffff } else { magic };
+    let magic = u16::try_from(magic).ok()?;
+    MAGIC_NUMBERS
+        .iter()
+        .find(|&&(first, last, _)| (first..=last).contains(&magic))
+        .map(|&(_, _, version)| version)
+}
+
 bitflags! {
+    /// `co_flags`, as of Python 3.14. Python 3.11 and 3.12 didn't add any: the flags they set
+    /// are the ones up to `ASYNC_GENERATOR`, plus the `__future__` ones. The 3.13 and 3.14 flags
+    /// are here too, since they're the ones a newer `.pyc` is most likely to have, and without
+    /// a name they'd only show up in [`CodeFlags::unknown_bits`].
+    ///
+    /// The `CO_FAST_*` constants from 3.11 on aren't `co_flags`: they're the per-variable kinds
+    /// in `co_localspluskinds`, which the reader uses to split the variable names into
+    /// `varnames`, `cellvars` and `freevars`.
     #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
     pub struct CodeFlags: u32 {
         const OPTIMIZED                   = 0x1;
@@ -86,26 +209,66 @@ bitflags! {
         const VARKEYWORDS                 = 0x8;
         const NESTED                     = 0x10;
         const GENERATOR                  = 0x20;
+        /// Up to Python 3.10, set when there are no cell or free variables. Unused since 3.11.
         const NOFREE                     = 0x40;
+        /// Python 3.5+.
         const COROUTINE                  = 0x80;
+        /// Python 3.5+.
         const ITERABLE_COROUTINE        = 0x100;
+        /// Python 3.6+.
         const ASYNC_GENERATOR           = 0x200;
         // TODO: old versions
+        /// Python 2 only, and has no effect since 2.3.
         const GENERATOR_ALLOWED        = 0x1000;
+        // Python 3.8.3 moved the CO_FUTURE_* flags up by 4 bits (bpo-39562), so these names are
+        // only accurate for code compiled by earlier versions. In 3.9 through 3.14, they're at
+        // 0x20000 through 0x1000000 instead.
+        /// Up to Python 3.8.2.
         const FUTURE_DIVISION          = 0x2000;
+        /// Up to Python 3.8.2.
         const FUTURE_ABSOLUTE_IMPORT   = 0x4000;
+        /// Up to Python 3.8.2.
         const FUTURE_WITH_STATEMENT    = 0x8000;
+        /// Up to Python 3.8.2.
         const FUTURE_PRINT_FUNCTION   = 0x10000;
+        /// Up to Python 3.8.2.
         const FUTURE_UNICODE_LITERALS = 0x20000;
+        /// Python 3.1 to 3.8.2.
         const FUTURE_BARRY_AS_BDFL    = 0x40000;
+        /// Python 3.5 to 3.8.2.
         const FUTURE_GENERATOR_STOP   = 0x80000;
+        /// Python 3.7 to 3.8.2.
         #[allow(clippy::unreadable_literal)]
         const FUTURE_ANNOTATIONS     = 0x100000;
+        /// Python 3.13+: `sys.monitoring` events are never fired for this code.
+        #[allow(clippy::unreadable_literal)]
+        const NO_MONITORING_EVENTS  = 0x2000000;
+        /// Python 3.14+: the first constant is the docstring.
+        #[allow(clippy::unreadable_literal)]
+        const HAS_DOCSTRING         = 0x4000000;
+        /// Python 3.14+: a function defined in a class body.
+        #[allow(clippy::unreadable_literal)]
+        const METHOD                = 0x8000000;
+    }
+}
+impl CodeFlags {
+    /// Like [`CodeFlags::from_bits_truncate`], but keeps any bits that don't correspond to a
+    /// known flag, so that flags from newer Python versions survive a load.
+    #[must_use]
+    pub fn from_bits_retain(bits: u32) -> Self {
+        // SAFETY: nothing relies on `CodeFlags` only containing known bits.
+        unsafe { Self::from_bits_unchecked(bits) }
+    }
+
+    /// Bits that are set but don't correspond to any flag known to this crate.
+    #[must_use]
+    pub fn unknown_bits(self) -> u32 {
+        self.bits() & !Self::all().bits()
     }
 }
 
 #[rustfmt::skip]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 pub struct Code<'a> {
     pub argcount:        u32,
@@ -115,54 +278,317 @@ pub struct Code<'a> {
     pub stacksize:       u32,
     pub flags:           CodeFlags,
     pub code:            &'a [u8],
-    pub consts:          &'a Obj<'a>,
-    pub names:           &'a str,
-    pub varnames:        &'a str,
-    pub freevars:        &'a str,
-    pub cellvars:        &'a str,
+    pub consts:          &'a [Obj<'a>],
+    pub names:           &'a [&'a str],
+    pub varnames:        &'a [&'a str],
+    pub freevars:        &'a [&'a str],
+    pub cellvars:        &'a [&'a str],
     pub filename:        &'a str,
     pub name:            &'a str,
+    /// Python 3.11+, empty before
+    pub qualname:        &'a str,
     pub firstlineno:     u32,
+    /// `co_linetable` in Python 3.10+, which has a different format but the same place
     pub lnotab:          &'a [u8],
+    /// Python 3.11+, empty before; decoded by `Code::exception_table` (with the `opcode` feature)
+    pub exceptiontable:  &'a [u8],
 }
+impl<'a> Code<'a> {
+    /// Deep-copies this code object into `arena`; see [`Obj::clone_into`].
+    #[must_use]
+    pub fn clone_into<'b>(&self, arena: &'b ObjArena) -> Code<'b> {
+        let clone_strs = |strs: &[&str]| -> &'b [&'b str] {
+            arena.alloc_slice_fill_iter(strs.iter().map(|s| &*arena.alloc_str(s)))
+        };
+        Code {
+            argcount: self.argcount,
+            posonlyargcount: self.posonlyargcount,
+            kwonlyargcount: self.kwonlyargcount,
+            nlocals: self.nlocals,
+            stacksize: self.stacksize,
+            flags: self.flags,
+            code: arena.alloc_slice_copy(self.code),
+            consts: arena.alloc_slice_fill_iter(self.consts.iter().map(|x| x.clone_into(arena))),
+            names: clone_strs(self.names),
+            varnames: clone_strs(self.varnames),
+            freevars: clone_strs(self.freevars),
+            cellvars: clone_strs(self.cellvars),
+            filename: arena.alloc_str(self.filename),
+            name: arena.alloc_str(self.name),
+            qualname: arena.alloc_str(self.qualname),
+            firstlineno: self.firstlineno,
+            lnotab: arena.alloc_slice_copy(self.lnotab),
+            exceptiontable: arena.alloc_slice_copy(self.exceptiontable),
+        }
+    }
+
+    pub fn consts_iter(&self) -> impl Iterator<Item = &'a Obj<'a>> {
+        self.consts.iter()
+    }
+
+    pub fn names_iter(&self) -> impl Iterator<Item = &'a str> {
+        self.names.iter().copied()
+    }
 
+    pub fn varnames_iter(&self) -> impl Iterator<Item = &'a str> {
+        self.varnames.iter().copied()
+    }
+
+    pub fn freevars_iter(&self) -> impl Iterator<Item = &'a str> {
+        self.freevars.iter().copied()
+    }
+
+    pub fn cellvars_iter(&self) -> impl Iterator<Item = &'a str> {
+        self.cellvars.iter().copied()
+    }
+
+    /// `(co_filename, co_name, co_firstlineno)`, which is where a traceback would say the
+    /// function is defined.
+    #[must_use]
+    pub fn location(&self) -> (&'a str, &'a str, u32) {
+        (self.filename, self.name, self.firstlineno)
+    }
+
+    /// The name to show for this function, e.g. in a profile.
+    ///
+    /// This is `co_qualname` (like `C.f.<locals>.g`) for code from Python 3.11+, and `co_name`
+    /// for older versions, which don't record it.
+    #[must_use]
+    pub fn full_name(&self) -> &'a str {
+        if self.qualname.is_empty() {
+            self.name
+        } else {
+            self.qualname
+        }
+    }
+
+    /// Calls `f` on every constant, recursing into the elements of tuple and frozenset
+    /// constants and into the constants of nested code objects (which are visited too).
+    pub fn walk_consts(&self, mut f: impl FnMut(&'a Obj<'a>)) {
+        fn walk<'a>(obj: &'a Obj<'a>, f: &mut impl FnMut(&'a Obj<'a>)) {
+            f(obj);
+            match *obj {
+                Obj::Tuple(x) | Obj::FrozenSet(x) => x.iter().for_each(|x| walk(x, f)),
+                Obj::Code(code) => code.consts.iter().for_each(|x| walk(x, f)),
+                _ => {}
+            }
+        }
+        self.consts.iter().for_each(|x| walk(x, &mut f));
+    }
+
+    /// Collects everything [`walk_consts`](Self::walk_consts) visits, in the same order.
+    #[must_use]
+    pub fn all_consts(&self) -> Vec<&'a Obj<'a>> {
+        let mut consts = Vec::new();
+        self.walk_consts(|x| consts.push(x));
+        consts
+    }
+
+    /// The docstring (`__doc__`) of a module or function.
+    ///
+    /// A function's first constant is always its docstring, or `None` if it doesn't have one. A
+    /// module's is only a docstring if it's then stored to `__doc__`, which comes first in its
+    /// names; otherwise it's just the first constant used. Class bodies store their qualified
+    /// name first, so their docstring isn't found. Comprehensions, generator expressions and
+    /// lambdas (named `<listcomp>`, `<genexpr>`, `<lambda>` and so on) can't have one, so their
+    /// first constant is never taken for it.
+    ///
+    /// Python 2.7 docstrings are `str`, so they're read as bytes; those are accepted if they're
+    /// valid UTF-8.
+    #[must_use]
+    pub fn docstring(&self) -> Option<&'a str> {
+        if self.name.starts_with('<') && self.name != "<module>" {
+            return None;
+        }
+        if !self.flags.contains(CodeFlags::OPTIMIZED) && self.names.first() != Some(&"__doc__") {
+            return None;
+        }
+        self.consts.first()?.try_as_str()
+    }
+
+    /// A copy of this code object with constant `index` replaced by `value`, or `None` if there's
+    /// no such constant. Constants of nested functions are in their own code objects, so patch
+    /// those first and then put the result back into the parent.
+    ///
+    /// To patch a `.pyc` file, copy its header unchanged (16 bytes, or 8 for Python 2.7; see
+    /// [`pyc::read_pyc_header`]) and then write the patched code with
+    /// [`write::marshal_dump_code`], using the `.pyc`'s version.
+    #[must_use]
+    pub fn with_const(&self, arena: &'a ObjArena, index: usize, value: Obj<'a>) -> Option<Self> {
+        if index >= self.consts.len() {
+            return None;
+        }
+        let consts = arena.alloc_slice_copy(self.consts);
+        consts[index] = value;
+        Some(Self { consts, ..*self })
+    }
+
+    /// Whether the two code objects do the same thing, ignoring where they came from.
+    ///
+    /// Compares everything except `filename`, `firstlineno` and `lnotab`, so the same function
+    /// compiled from two files (or at two places in one) compares equal. Constants are compared
+    /// like `==`, except that nested code objects, including those inside tuple constants, are
+    /// compared with `logic_eq` too.
+    #[must_use]
+    pub fn logic_eq(&self, other: &Code) -> bool {
+        fn const_eq(a: &Obj, b: &Obj) -> bool {
+            match (*a, *b) {
+                (Obj::Tuple(a), Obj::Tuple(b)) | (Obj::FrozenSet(a), Obj::FrozenSet(b)) => {
+                    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| const_eq(a, b))
+                }
+                (Obj::Code(a), Obj::Code(b)) => a.logic_eq(b),
+                (a, b) => a == b,
+            }
+        }
+        self.argcount == other.argcount
+            && self.posonlyargcount == other.posonlyargcount
+            && self.kwonlyargcount == other.kwonlyargcount
+            && self.nlocals == other.nlocals
+            && self.stacksize == other.stacksize
+            && self.flags == other.flags
+            && self.code == other.code
+            && self.consts.len() == other.consts.len()
+            && self.consts.iter().zip(other.consts).all(|(a, b)| const_eq(a, b))
+            && self.names == other.names
+            && self.varnames == other.varnames
+            && self.freevars == other.freevars
+            && self.cellvars == other.cellvars
+            && self.name == other.name
+            && self.qualname == other.qualname
+            && self.exceptiontable == other.exceptiontable
+    }
+}
+
+macro_rules! define_with {
+    ($with_fn:ident($field:ident: $ty:ty)) => {
+        #[must_use]
+        pub fn $with_fn(mut self, $field: $ty) -> Self {
+            self.0.$field = $field;
+            self
+        }
+    };
+}
+
+/// Builds a [`Code`] without spelling out every field.
+///
+/// Counts and `firstlineno` default to 0 and 1, flags to empty, bytes and tuples to empty,
+/// `filename` to `"<string>"`, `name` to `"<module>"` and `qualname` to empty (as read from
+/// Python < 3.11).
+#[derive(Clone, Copy, Debug)]
+pub struct CodeBuilder<'a>(Code<'a>);
+impl<'a> CodeBuilder<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Code {
+            argcount: 0,
+            posonlyargcount: 0,
+            kwonlyargcount: 0,
+            nlocals: 0,
+            stacksize: 0,
+            flags: CodeFlags::empty(),
+            code: &[],
+            consts: &[],
+            names: &[],
+            varnames: &[],
+            freevars: &[],
+            cellvars: &[],
+            filename: "<string>",
+            name: "<module>",
+            qualname: "",
+            firstlineno: 1,
+            lnotab: &[],
+            exceptiontable: &[],
+        })
+    }
+
+    define_with! { with_argcount       (argcount:        u32              ) }
+    define_with! { with_posonlyargcount(posonlyargcount: u32              ) }
+    define_with! { with_kwonlyargcount (kwonlyargcount:  u32              ) }
+    define_with! { with_nlocals        (nlocals:         u32              ) }
+    define_with! { with_stacksize      (stacksize:       u32              ) }
+    define_with! { with_flags          (flags:           CodeFlags        ) }
+    define_with! { with_code           (code:            &'a [u8]         ) }
+    define_with! { with_consts         (consts:          &'a [Obj<'a>]    ) }
+    define_with! { with_names          (names:           &'a [&'a str]    ) }
+    define_with! { with_varnames       (varnames:        &'a [&'a str]    ) }
+    define_with! { with_freevars       (freevars:        &'a [&'a str]    ) }
+    define_with! { with_cellvars       (cellvars:        &'a [&'a str]    ) }
+    define_with! { with_filename       (filename:        &'a str          ) }
+    define_with! { with_name           (name:            &'a str          ) }
+    define_with! { with_firstlineno    (firstlineno:     u32              ) }
+    define_with! { with_qualname       (qualname:        &'a str          ) }
+    define_with! { with_lnotab         (lnotab:          &'a [u8]         ) }
+    define_with! { with_exceptiontable (exceptiontable:  &'a [u8]         ) }
+
+    /// The finished code object, by value.
+    #[must_use]
+    pub fn finish(self) -> Code<'a> {
+        self.0
+    }
+
+    /// Allocates the finished code object in `arena`, ready to wrap in [`Obj::Code`].
+    pub fn build(self, arena: &'a ObjArena) -> &'a Code<'a> {
+        arena.alloc(self.0)
+    }
+}
+impl Default for CodeBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An immutable, arena-allocated marshal value.
+///
+/// Since every container is a finished slice, cycles can't be represented: a self-referential
+/// list such as `a = []; a.append(a)` is rejected by the reader with `InvalidRef`, because the
+/// inner ref points at a slot that is still being filled.
+///
+/// There are no locks inside, so nothing can be poisoned, and an `Obj` can be shared between
+/// threads for as long as its arena is borrowed.
 #[rustfmt::skip]
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 pub enum Obj<'a> {
     None,
     StopIteration,
     Ellipsis,
     Bool     (bool),
-    Long     (&'a BigInt),
+    Long     (&'a Int),
     Float    (f64),
-    Complex  (&'a f64),
+    Complex  (Complex<f64>),
+    /// Also what a `bytearray`, `memoryview` or `array.array` reads back as: marshal writes
+    /// anything supporting the buffer protocol as bytes, without its type or an array's
+    /// typecode.
     Bytes    (&'a [u8]),
     String   (&'a str),
+    /// A str read with [`validate_strings`](read::MarshalLoadExOptions::validate_strings) off,
+    /// so its bytes haven't been checked to be UTF-8. It's the same Python value as a `String`
+    /// with those bytes, and compares equal to one.
+    RawString(&'a [u8]),
     Tuple    (&'a [Obj<'a>]),
     List     (&'a [Obj<'a>]),
     Dict     (&'a [(Obj<'a>, Obj<'a>)]),
     Set      (&'a [Obj<'a>]),
     FrozenSet(&'a [Obj<'a>]),
     Code     (&'a Code<'a>),
+    /// Where reading stopped at a type code it didn't recognize, with
+    /// [`recovery`](read::MarshalLoadExOptions::recovery) on. `tag` is the byte as read,
+    /// including `FLAG_REF`. It isn't a Python value, and can't be written.
+    Unknown { tag: u8 },
     // etc.
 }
 macro_rules! define_extract {
     ($extract_fn:ident($variant:ident) -> ()) => {
         define_extract! { $extract_fn -> () { $variant => () } }
     };
-    ($extract_fn:ident($variant:ident) -> Arc<$ret:ty>) => {
-        define_extract! { $extract_fn -> Arc<$ret> { $variant(x) => x } }
-    };
-    ($extract_fn:ident($variant:ident) -> ArcRwLock<$ret:ty>) => {
-        define_extract! { $extract_fn -> ArcRwLock<$ret> { $variant(x) => x } }
-    };
     ($extract_fn:ident($variant:ident) -> $ret:ty) => {
         define_extract! { $extract_fn -> $ret { $variant(x) => x } }
     };
     ($extract_fn:ident -> $ret:ty { $variant:ident$(($($pat:pat),+))? => $expr:expr }) => {
+        /// `Obj` is `Copy`, so this works through a reference too, without consuming or
+        /// cloning anything: the result borrows from the arena, not from `self`.
         /// # Errors
-        /// Returns a reference to self if extraction fails
+        /// Returns `self` if it's a different type
         pub fn $extract_fn(self) -> Result<$ret, Self> {
             if let Self::$variant$(($($pat),+))? = self {
                 Ok($expr)
@@ -172,6 +598,20 @@ macro_rules! define_extract {
         }
     }
 }
+macro_rules! define_as {
+    ($as_fn:ident -> $ty:ident via $to_fn:ident) => {
+        #[doc = concat!("Narrows a bool or int to `", stringify!($ty), "`, returning `None` for")]
+        /// other types or if the value is out of range.
+        #[must_use]
+        pub fn $as_fn(&self) -> Option<$ty> {
+            match *self {
+                Self::Bool(x) => Some($ty::from(x)),
+                Self::Long(x) => x.$to_fn(),
+                _ => None,
+            }
+        }
+    };
+}
 macro_rules! define_is {
     ($is_fn:ident($variant:ident$(($($pat:pat),+))?)) => {
         /// # Errors
@@ -187,40 +627,686 @@ macro_rules! define_is {
     }
 }
 impl<'a> Obj<'a> {
-    define_extract! { extract_none          (None)          -> ()                                    }
-    define_extract! { extract_stop_iteration(StopIteration) -> ()                                    }
-    define_extract! { extract_bool          (Bool)          -> bool                                  }
-    define_extract! { extract_long          (Long)          -> &'a BigInt                           }
-    define_extract! { extract_float         (Float)         -> f64                                   }
-    define_extract! { extract_bytes         (Bytes)         -> &'a [u8]                          }
-    define_extract! { extract_string        (String)        -> &'a String                           }
-    define_extract! { extract_tuple         (Tuple)         -> &'a [Self]                        }
-    define_extract! { extract_list          (List)          -> &'a [Self]                  }
-    define_extract! { extract_dict          (Dict)          -> &'a [(Obj<'a>, Self)] }
-    define_extract! { extract_set           (Set)           -> &'a [Obj<'a>]       }
-    define_extract! { extract_frozenset     (FrozenSet)     -> &'a [Obj<'a>]             }
-    define_extract! { extract_code          (Code)          -> &'a Code<'a>                             }
+    define_extract! { extract_none          (None)          -> ()                       }
+    define_extract! { extract_stop_iteration(StopIteration) -> ()                       }
+    define_extract! { extract_bool          (Bool)          -> bool                     }
+    define_extract! { extract_long          (Long)          -> &'a Int                  }
+    define_extract! { extract_float         (Float)         -> f64                      }
+    define_extract! { extract_complex       (Complex)       -> Complex<f64>             }
+    define_extract! { extract_bytes         (Bytes)         -> &'a [u8]                 }
+    define_extract! { extract_tuple         (Tuple)         -> &'a [Self]               }
+    define_extract! { extract_list          (List)          -> &'a [Self]               }
+    define_extract! { extract_dict          (Dict)          -> &'a [(Self, Self)]       }
+    define_extract! { extract_set           (Set)           -> &'a [Self]               }
+    define_extract! { extract_frozenset     (FrozenSet)     -> &'a [Self]               }
+    define_extract! { extract_code          (Code)          -> &'a Code<'a>             }
 
     define_is! { is_none          (None)          }
     define_is! { is_stop_iteration(StopIteration) }
     define_is! { is_bool          (Bool(_))       }
     define_is! { is_long          (Long(_))       }
     define_is! { is_float         (Float(_))      }
+    define_is! { is_complex       (Complex(_))    }
     define_is! { is_bytes         (Bytes(_))      }
-    define_is! { is_string        (String(_))     }
     define_is! { is_tuple         (Tuple(_))      }
     define_is! { is_list          (List(_))       }
     define_is! { is_dict          (Dict(_))       }
     define_is! { is_set           (Set(_))        }
     define_is! { is_frozenset     (FrozenSet(_))  }
     define_is! { is_code          (Code(_))       }
+
+    /// Also accepts a [`RawString`](Self::RawString), validating it now.
+    /// # Errors
+    /// Returns `self` if it's a different type, or a `RawString` that isn't UTF-8
+    pub fn extract_string(self) -> Result<&'a str, Self> {
+        match self {
+            Self::String(x) => Ok(x),
+            Self::RawString(x) => core::str::from_utf8(x).map_err(|_| self),
+            _ => Err(self),
+        }
+    }
+
+    /// True for a [`RawString`](Self::RawString) too, whether or not it's valid UTF-8.
+    #[must_use]
+    pub fn is_string(&self) -> bool {
+        matches!(self, Self::String(_) | Self::RawString(_))
+    }
+
+    /// Iterates over the elements of a tuple, list, set or frozenset.
+    ///
+    /// Returns `None` for any other type, including dicts; use [`Obj::dict_entries`] or
+    /// [`Obj::get_item`] for those.
+    #[must_use]
+    pub fn iter(&self) -> Option<impl Iterator<Item = &'a Obj<'a>>> {
+        match *self {
+            Self::Tuple(x) | Self::List(x) | Self::Set(x) | Self::FrozenSet(x) => Some(x.iter()),
+            _ => None,
+        }
+    }
+
+    /// The elements of a set or frozenset, in an order that doesn't depend on the one they
+    /// were written in, for output that has to be stable, e.g. for diffing.
+    ///
+    /// Bools, bytes, strs, ints, floats, frozensets and tuples are grouped by type, in that
+    /// order, and then sorted by value. Anything else comes first, in its original order.
+    ///
+    /// Returns `None` if `self` isn't a set or frozenset.
+    #[must_use]
+    pub fn sorted_set_elements(&self) -> Option<Vec<Obj<'a>>> {
+        match *self {
+            Self::Set(x) | Self::FrozenSet(x) => {
+                let mut sorted = x.to_vec();
+                sorted.sort_by(set_order);
+                Some(sorted)
+            }
+            _ => None,
+        }
+    }
+
+    /// Python's `len()`: the number of elements of a tuple, list, set, frozenset or dict, bytes
+    /// in a bytes object, or code points in a str, rather than the bytes of its UTF-8.
+    ///
+    /// Returns `None` for types that don't have a length.
+    #[must_use]
+    pub fn len(&self) -> Option<usize> {
+        // Every code point starts with a byte that isn't a continuation byte (0b10xxxxxx),
+        // which also holds for the lone surrogates a RawString may contain
+        let code_points = |x: &[u8]| x.iter().filter(|&&b| b & 0xc0 != 0x80).count();
+        match *self {
+            Self::Tuple(x) | Self::List(x) | Self::Set(x) | Self::FrozenSet(x) => Some(x.len()),
+            Self::Dict(x) => Some(x.len()),
+            Self::Bytes(x) => Some(x.len()),
+            Self::String(x) => Some(code_points(x.as_bytes())),
+            Self::RawString(x) => Some(code_points(x)),
+            _ => None,
+        }
+    }
+
+    /// Whether [`len`](Self::len) is 0, or `None` for types that don't have a length.
+    #[must_use]
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Iterates over the key-value pairs of a dict, in the order they were read.
+    ///
+    /// Returns `None` if `self` isn't a dict.
+    #[must_use]
+    pub fn dict_entries(&self) -> Option<impl Iterator<Item = (&'a Obj<'a>, &'a Obj<'a>)>> {
+        if let Self::Dict(x) = *self {
+            Some(x.iter().map(|(k, v)| (k, v)))
+        } else {
+            None
+        }
+    }
+
+    /// Calls `visit` on this object and everything under it, in pre-order: a container comes
+    /// before its elements, each dict key before its value, and a code object before its
+    /// constants. Stops as soon as `visit` returns [`ControlFlow::Break`], and returns that.
+    ///
+    /// Objects can't contain themselves, since a container is only allocated once its elements
+    /// are, but they can share containers, as marshal refs do. A shared container is visited
+    /// wherever it appears, but only walked into the first time, so the walk takes time
+    /// proportional to the size of the marshal data, rather than of the tree it describes.
+    pub fn walk(&self, mut visit: impl FnMut(&Obj<'a>) -> ControlFlow<()>) -> ControlFlow<()> {
+        fn walk<'a>(
+            x: &Obj<'a>,
+            visit: &mut impl FnMut(&Obj<'a>) -> ControlFlow<()>,
+            walked: &mut BTreeSet<dedup::Identity>,
+        ) -> ControlFlow<()> {
+            visit(x)?;
+            match *x {
+                Obj::Tuple(_) | Obj::List(_) | Obj::Dict(_) | Obj::Set(_) | Obj::FrozenSet(_)
+                | Obj::Code(_) => {
+                    if !walked.insert(dedup::identity(x)) {
+                        return ControlFlow::Continue(());
+                    }
+                }
+                _ => return ControlFlow::Continue(()),
+            }
+            match *x {
+                Obj::Tuple(x) | Obj::List(x) | Obj::Set(x) | Obj::FrozenSet(x) => {
+                    for x in x {
+                        walk(x, visit, walked)?;
+                    }
+                }
+                Obj::Dict(x) => {
+                    for (key, value) in x {
+                        walk(key, visit, walked)?;
+                        walk(value, visit, walked)?;
+                    }
+                }
+                Obj::Code(x) => {
+                    for x in x.consts {
+                        walk(x, visit, walked)?;
+                    }
+                }
+                _ => {}
+            }
+            ControlFlow::Continue(())
+        }
+        walk(self, &mut visit, &mut BTreeSet::new())
+    }
+
+    pub fn from_i64(arena: &'a ObjArena, v: i64) -> Self {
+        Self::from_bigint(arena, Int::from(v))
+    }
+
+    pub fn from_u64(arena: &'a ObjArena, v: u64) -> Self {
+        Self::from_bigint(arena, Int::from(v))
+    }
+
+    pub fn from_bigint(arena: &'a ObjArena, v: Int) -> Self {
+        Self::Long(arena.alloc_bigint(v))
+    }
+
+    pub fn from_str(arena: &'a ObjArena, s: &str) -> Self {
+        Self::String(arena.alloc_str(s))
+    }
+
+    pub fn from_bytes(arena: &'a ObjArena, b: &[u8]) -> Self {
+        Self::Bytes(arena.alloc_slice_copy(b))
+    }
+
+    /// Builds a dict, checking that every key [is hashable](Self::is_hashable), as the reader
+    /// does. Otherwise a bad key would only be noticed by whoever reads the marshalled data.
+    /// # Errors
+    /// Returns [`Unhashable`](read::errors::Error::Unhashable) with the first unhashable key.
+    #[cfg(feature = "std")]
+    pub fn dict_from_pairs(
+        arena: &'a ObjArena,
+        pairs: Vec<(Self, Self)>,
+    ) -> Result<Self, read::errors::Error<'a>> {
+        if let Some(&(key, _)) = pairs.iter().find(|(key, _)| !key.is_hashable()) {
+            return Err(read::errors::Error::Unhashable(key));
+        }
+        Ok(Self::Dict(arena.alloc_slice_fill_iter(pairs)))
+    }
+
+    /// Deep-copies this object into `arena`, so it can outlive the arena it was read into.
+    ///
+    /// Objects that were shared through refs become separate copies.
+    #[must_use]
+    pub fn clone_into<'b>(&self, arena: &'b ObjArena) -> Obj<'b> {
+        let clone_slice = |objs: &[Obj]| -> &'b [Obj<'b>] {
+            arena.alloc_slice_fill_iter(objs.iter().map(|x| x.clone_into(arena)))
+        };
+        match *self {
+            Self::None => Obj::None,
+            Self::StopIteration => Obj::StopIteration,
+            Self::Ellipsis => Obj::Ellipsis,
+            Self::Bool(x) => Obj::Bool(x),
+            Self::Long(x) => Obj::Long(arena.alloc_bigint(Int::clone(x))),
+            Self::Float(x) => Obj::Float(x),
+            Self::Complex(x) => Obj::Complex(x),
+            Self::Bytes(x) => Obj::Bytes(arena.alloc_slice_copy(x)),
+            Self::String(x) => Obj::String(arena.alloc_str(x)),
+            Self::RawString(x) => Obj::RawString(arena.alloc_slice_copy(x)),
+            Self::Tuple(x) => Obj::Tuple(clone_slice(x)),
+            Self::List(x) => Obj::List(clone_slice(x)),
+            Self::Dict(x) => Obj::Dict(arena.alloc_slice_fill_iter(
+                x.iter().map(|(k, v)| (k.clone_into(arena), v.clone_into(arena))),
+            )),
+            Self::Set(x) => Obj::Set(clone_slice(x)),
+            Self::FrozenSet(x) => Obj::FrozenSet(clone_slice(x)),
+            Self::Code(x) => Obj::Code(arena.alloc(x.clone_into(arena))),
+            Self::Unknown { tag } => Obj::Unknown { tag },
+        }
+    }
+
+    /// Deep-copies this object into a new arena that is never freed, so it can be used without
+    /// keeping any arena around.
+    ///
+    /// The memory is only returned to the OS when the process exits, and every call leaks
+    /// another copy, so this is meant for short-lived tools that read a file or two. Anything
+    /// long-running should keep the [`ObjArena`] alive instead, or use [`clone_into`](Self::clone_into).
+    #[must_use]
+    pub fn leak(&self) -> Obj<'static> {
+        self.clone_into(Box::leak(Box::new(ObjArena::new())))
+    }
+
+    /// Looks up `key` in a dict, like Python's `d[key]`.
+    ///
+    /// Returns `None` if `self` isn't a dict or doesn't contain `key`.
+    #[must_use]
+    pub fn get_item(&self, key: &Obj) -> Option<Self> {
+        if let Self::Dict(x) = self {
+            x.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+        } else {
+            None
+        }
+    }
+
+    /// Indexes into a tuple or list, like Python's `x[i]` (without negative indices).
+    ///
+    /// Returns `None` if `self` isn't a tuple or list, or if `i` is out of range.
+    #[must_use]
+    pub fn get_index(&self, i: usize) -> Option<Self> {
+        match self {
+            Self::Tuple(x) | Self::List(x) => x.get(i).cloned(),
+            _ => None,
+        }
+    }
+
+    define_as! { as_i64   -> i64   via to_i64   }
+    define_as! { as_u32   -> u32   via to_u32   }
+    define_as! { as_usize -> usize via to_usize }
+
+    /// Widens a bool or int to an [`Int`], like Python's `int(x)` (`True` is `1`).
+    ///
+    /// Returns `None` for every other type; use [`extract_long`](Self::extract_long) to accept
+    /// only ints.
+    #[must_use]
+    pub fn as_bigint(&self) -> Option<Int> {
+        match *self {
+            Self::Bool(x) => Some(Int::from(u8::from(x))),
+            Self::Long(x) => Some(Int::clone(x)),
+            _ => None,
+        }
+    }
+
+    /// The text of a str, or of bytes that happen to be valid UTF-8, like Python 2 `str`
+    /// constants usually are.
+    ///
+    /// Returns `None` for binary bytes and every other type, so those aren't mislabeled as text.
+    #[must_use]
+    pub fn try_as_str(&self) -> Option<&'a str> {
+        match *self {
+            Self::String(x) => Some(x),
+            Self::Bytes(x) | Self::RawString(x) => core::str::from_utf8(x).ok(),
+            _ => None,
+        }
+    }
+
+    /// The Python type's name, like `type(x).__name__`.
+    #[must_use]
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::None => "NoneType",
+            Self::StopIteration => "type",
+            Self::Ellipsis => "ellipsis",
+            Self::Bool(_) => "bool",
+            Self::Long(_) => "int",
+            Self::Float(_) => "float",
+            Self::Complex(_) => "complex",
+            Self::Bytes(_) => "bytes",
+            Self::String(_) | Self::RawString(_) => "str",
+            Self::Tuple(_) => "tuple",
+            Self::List(_) => "list",
+            Self::Dict(_) => "dict",
+            Self::Set(_) => "set",
+            Self::FrozenSet(_) => "frozenset",
+            Self::Code(_) => "code",
+            Self::Unknown { .. } => "unknown",
+        }
+    }
+
+    /// Whether this can be a set element or dict key, mirroring CPython: lists, dicts, sets, and
+    /// tuples containing any of them, can't be.
+    ///
+    /// The reader rejects anything else with [`Unhashable`](read::errors::Error::Unhashable).
+    #[must_use]
+    pub fn is_hashable(&self) -> bool {
+        match self {
+            Self::List(_) | Self::Dict(_) | Self::Set(_) => false,
+            Self::Tuple(x) => x.iter().all(Self::is_hashable),
+            _ => true,
+        }
+    }
+
+    /// Whether Python code could mutate this object in place, so it can't safely be shared.
+    ///
+    /// Only the object itself is considered, so a tuple is immutable even if it holds a list.
+    #[must_use]
+    pub fn is_immutable(&self) -> bool {
+        !matches!(self, Self::List(_) | Self::Dict(_) | Self::Set(_))
+    }
+
+    /// Rebuilds this object so that equal strings, bytes, ints, tuples and frozensets share
+    /// storage, which lets the serializer write them once and use refs for the rest, like CPython.
+    ///
+    /// Lists, sets and dicts are never merged, since they're mutable, but their contents are.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn dedup(&self, arena: &'a ObjArena) -> Self {
+        dedup::Deduper::new(arena).dedup(*self)
+    }
+
+    /// How many bytes [`marshal_dumps`](write::marshal_dumps) would write for this object at
+    /// marshal format `version`; see [`write::marshal_size`].
+    /// # Errors
+    /// Returns [`io::ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput) for objects
+    /// too large for the format.
+    #[cfg(feature = "std")]
+    pub fn marshal_size(&self, version: u8) -> std::io::Result<usize> {
+        write::marshal_size(self, version)
+    }
+
+    /// The multi-line form of the `Debug` repr, same as `format!("{:#?}", self)`.
+    #[must_use]
+    pub fn pretty(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+/// Returned by the `TryFrom<Obj>` conversions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TypeMismatch {
+    pub expected: &'static str,
+    /// The Python type name of the object, from [`Obj::type_name`]
+    pub actual: &'static str,
+}
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected {}, got {}", self.expected, self.actual)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for TypeMismatch {}
+
+macro_rules! impl_try_from_obj {
+    ($ty:ty, $expected:literal, |$x:ident| $convert:expr) => {
+        impl TryFrom<&Obj<'_>> for $ty {
+            type Error = TypeMismatch;
+
+            fn try_from($x: &Obj) -> Result<Self, TypeMismatch> {
+                $convert.ok_or_else(|| TypeMismatch {
+                    expected: $expected,
+                    actual: $x.type_name(),
+                })
+            }
+        }
+        impl TryFrom<Obj<'_>> for $ty {
+            type Error = TypeMismatch;
+
+            fn try_from(x: Obj) -> Result<Self, TypeMismatch> {
+                Self::try_from(&x)
+            }
+        }
+    };
+}
+impl_try_from_obj!(i64, "an int that fits in an i64", |x| x.as_i64());
+impl_try_from_obj!(f64, "float", |x| x.extract_float().ok());
+impl_try_from_obj!(bool, "bool", |x| x.extract_bool().ok());
+impl_try_from_obj!(String, "str", |x| x.extract_string().ok().map(str::to_owned));
+impl_try_from_obj!(Vec<u8>, "bytes", |x| x.extract_bytes().ok().map(<[u8]>::to_vec));
+
+impl From<bool> for Obj<'_> {
+    fn from(x: bool) -> Self {
+        Self::Bool(x)
+    }
+}
+impl From<f64> for Obj<'_> {
+    fn from(x: f64) -> Self {
+        Self::Float(x)
+    }
+}
+impl From<Complex<f64>> for Obj<'_> {
+    fn from(x: Complex<f64>) -> Self {
+        Self::Complex(x)
+    }
+}
+impl<'a> From<&'a Int> for Obj<'a> {
+    fn from(x: &'a Int) -> Self {
+        Self::Long(x)
+    }
+}
+impl<'a> From<&'a str> for Obj<'a> {
+    fn from(x: &'a str) -> Self {
+        Self::String(x)
+    }
+}
+impl<'a> From<&'a [u8]> for Obj<'a> {
+    fn from(x: &'a [u8]) -> Self {
+        Self::Bytes(x)
+    }
+}
+
+/// Values that [`py_obj!`] accepts as leaves. Ints need to be allocated, so unlike [`From`],
+/// this takes an arena.
+pub trait IntoObj<'a> {
+    fn into_obj(self, arena: &'a ObjArena) -> Obj<'a>;
+}
+impl<'a> IntoObj<'a> for Obj<'a> {
+    fn into_obj(self, _: &'a ObjArena) -> Obj<'a> {
+        self
+    }
+}
+macro_rules! impl_into_obj {
+    ($($ty:ty => |$x:ident, $arena:ident| $convert:expr;)*) => {$(
+        impl<'a> IntoObj<'a> for $ty {
+            fn into_obj(self, $arena: &'a ObjArena) -> Obj<'a> {
+                let $x = self;
+                $convert
+            }
+        }
+    )*};
+}
+impl_into_obj! {
+    i32 => |x, arena| Obj::from_i64(arena, x.into());
+    i64 => |x, arena| Obj::from_i64(arena, x);
+    u64 => |x, arena| Obj::from_u64(arena, x);
+    bool => |x, _arena| Obj::Bool(x);
+    f64 => |x, _arena| Obj::Float(x);
+    Complex<f64> => |x, _arena| Obj::Complex(x);
+    &'a str => |x, _arena| Obj::String(x);
+    &'a [u8] => |x, _arena| Obj::Bytes(x);
+}
+impl<'a, const N: usize> IntoObj<'a> for &'a [u8; N] {
+    fn into_obj(self, _: &'a ObjArena) -> Obj<'a> {
+        Obj::Bytes(self)
+    }
+}
+
+/// Builds an [`Obj`] with Python-like syntax, allocating in the arena given first, e.g.
+/// `py_obj!(&arena; (1, "a", [None, True], {b"k": -2.5}, {1, 2}))`.
+///
+/// Tuples, lists, dicts and sets nest as in Python, and `(x)` is just `x`, so a 1-tuple needs a
+/// trailing comma. `None`, `True`, `False` and `Ellipsis` are recognized, and any other single
+/// token, optionally negated, goes through [`IntoObj`]: literals, or a variable holding an
+/// `Obj` to splice in. Anything longer needs parentheses. Empty braces are a dict.
+#[macro_export]
+macro_rules! py_obj {
+    (@items $arena:expr; [$($out:expr,)*];) => { [$($out,)*] };
+    (@items $arena:expr; [$($out:expr,)*]; - $x:tt $(, $($rest:tt)*)?) => {
+        $crate::py_obj!(@items $arena; [$($out,)* $crate::py_obj!($arena; -$x),]; $($($rest)*)?)
+    };
+    (@items $arena:expr; [$($out:expr,)*]; $x:tt $(, $($rest:tt)*)?) => {
+        $crate::py_obj!(@items $arena; [$($out,)* $crate::py_obj!($arena; $x),]; $($($rest)*)?)
+    };
+    (@entries $arena:expr; [$($out:expr,)*];) => { [$($out,)*] };
+    (@entries $arena:expr; [$($out:expr,)*]; $k:tt : - $v:tt $(, $($rest:tt)*)?) => {
+        $crate::py_obj!(
+            @entries $arena;
+            [$($out,)* ($crate::py_obj!($arena; $k), $crate::py_obj!($arena; -$v)),];
+            $($($rest)*)?
+        )
+    };
+    (@entries $arena:expr; [$($out:expr,)*]; $k:tt : $v:tt $(, $($rest:tt)*)?) => {
+        $crate::py_obj!(
+            @entries $arena;
+            [$($out,)* ($crate::py_obj!($arena; $k), $crate::py_obj!($arena; $v)),];
+            $($($rest)*)?
+        )
+    };
+    ($arena:expr; None) => { $crate::Obj::None };
+    ($arena:expr; True) => { $crate::Obj::Bool(true) };
+    ($arena:expr; False) => { $crate::Obj::Bool(false) };
+    ($arena:expr; Ellipsis) => { $crate::Obj::Ellipsis };
+    ($arena:expr; ($x:tt)) => { $crate::py_obj!($arena; $x) };
+    ($arena:expr; (- $x:tt)) => { $crate::py_obj!($arena; -$x) };
+    ($arena:expr; ($($items:tt)*)) => {
+        $crate::Obj::Tuple($arena.alloc_slice_copy(&$crate::py_obj!(@items $arena; []; $($items)*)))
+    };
+    ($arena:expr; [$($items:tt)*]) => {
+        $crate::Obj::List($arena.alloc_slice_copy(&$crate::py_obj!(@items $arena; []; $($items)*)))
+    };
+    ($arena:expr; {$k:tt : $($rest:tt)*}) => {
+        $crate::Obj::Dict(
+            $arena.alloc_slice_copy(&$crate::py_obj!(@entries $arena; []; $k : $($rest)*))
+        )
+    };
+    ($arena:expr; {}) => { $crate::Obj::Dict(&[]) };
+    ($arena:expr; {$($items:tt)*}) => {
+        $crate::Obj::Set($arena.alloc_slice_copy(&$crate::py_obj!(@items $arena; []; $($items)*)))
+    };
+    ($arena:expr; - $x:tt) => { $crate::IntoObj::into_obj(-$x, $arena) };
+    ($arena:expr; $x:tt) => { $crate::IntoObj::into_obj($x, $arena) };
+}
+
+/// Compares floats the way Python's containment checks (`x in [y]`, dict lookup) do.
+///
+/// Those test `x is y or x == y`. Decoded values carry no identity, so NaNs are assumed to be the
+/// same object and compare equal, while `0.0 == -0.0` as usual.
+#[must_use]
+pub fn py_float_eq(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// A total order over the hashable values that can appear in sets. It only needs to be
+/// deterministic: values Python can't compare are grouped by type, and sorted first.
+pub(crate) fn set_order(a: &Obj, b: &Obj) -> Ordering {
+    fn rank(x: &Obj) -> u8 {
+        match x {
+            Obj::Bool(_) => 1,
+            Obj::Bytes(_) => 2,
+            Obj::String(_) | Obj::RawString(_) => 3,
+            Obj::Long(_) => 4,
+            Obj::Float(_) => 5,
+            Obj::FrozenSet(_) => 6,
+            Obj::Tuple(_) => 7,
+            _ => 0,
+        }
+    }
+    fn sorted<'a>(x: &'a [Obj<'a>]) -> Vec<&'a Obj<'a>> {
+        let mut x: Vec<_> = x.iter().collect();
+        x.sort_by(|a, b| set_order(a, b));
+        x
+    }
+    fn lexicographic(a: &[&Obj], b: &[&Obj]) -> Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| set_order(a, b))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+    match (a, b) {
+        (Obj::Bool(a), Obj::Bool(b)) => a.cmp(b),
+        (Obj::Bytes(a), Obj::Bytes(b)) => a.cmp(b),
+        (Obj::String(a), Obj::String(b)) => a.cmp(b),
+        (Obj::String(_) | Obj::RawString(_), Obj::String(_) | Obj::RawString(_)) => {
+            str_bytes(a).cmp(str_bytes(b))
+        }
+        (Obj::Long(a), Obj::Long(b)) => a.cmp(b),
+        (Obj::Float(a), Obj::Float(b)) => a.total_cmp(b),
+        (Obj::FrozenSet(a), Obj::FrozenSet(b)) => lexicographic(&sorted(a), &sorted(b)),
+        (Obj::Tuple(a), Obj::Tuple(b)) => lexicographic(
+            &a.iter().collect::<Vec<_>>(),
+            &b.iter().collect::<Vec<_>>(),
+        ),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn str_bytes<'a>(x: &Obj<'a>) -> &'a [u8] {
+    match *x {
+        Obj::String(x) => x.as_bytes(),
+        Obj::RawString(x) => x,
+        _ => &[],
+    }
 }
+
+/// Structural equality, with floats compared by [`py_float_eq`]. Unlike Python, values of
+/// different types are never equal, e.g. `True != 1` and `(1,) != [1]`.
+impl PartialEq for Obj<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (Self::None, Self::None)
+            | (Self::StopIteration, Self::StopIteration)
+            | (Self::Ellipsis, Self::Ellipsis) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Long(a), Self::Long(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => py_float_eq(a, b),
+            (Self::Complex(a), Self::Complex(b)) => {
+                py_float_eq(a.re, b.re) && py_float_eq(a.im, b.im)
+            }
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::RawString(a), Self::RawString(b)) => a == b,
+            (Self::String(a), Self::RawString(b)) | (Self::RawString(b), Self::String(a)) => {
+                a.as_bytes() == b
+            }
+            (Self::Tuple(a), Self::Tuple(b))
+            | (Self::List(a), Self::List(b))
+            | (Self::Set(a), Self::Set(b))
+            | (Self::FrozenSet(a), Self::FrozenSet(b)) => a == b,
+            (Self::Dict(a), Self::Dict(b)) => a == b,
+            (Self::Code(a), Self::Code(b)) => a == b,
+            (Self::Unknown { tag: a }, Self::Unknown { tag: b }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Compares the values two objects decode to, ignoring how they happened to be written.
+///
+/// Layout differences (refs, interning, short vs long string encodings) are already invisible
+/// once decoded, so this is [`PartialEq`] except that the elements of sets, frozensets and dicts
+/// may appear in any order. Useful for checking that two `.pyc` files are equivalent.
+#[must_use]
+pub fn obj_semantic_eq(a: &Obj, b: &Obj) -> bool {
+    match (*a, *b) {
+        (Obj::Tuple(a), Obj::Tuple(b)) | (Obj::List(a), Obj::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| obj_semantic_eq(a, b))
+        }
+        (Obj::Set(a), Obj::Set(b)) | (Obj::FrozenSet(a), Obj::FrozenSet(b)) => {
+            unordered_eq(a, b, obj_semantic_eq)
+        }
+        (Obj::Dict(a), Obj::Dict(b)) => unordered_eq(a, b, |(ak, av), (bk, bv)| {
+            obj_semantic_eq(ak, bk) && obj_semantic_eq(av, bv)
+        }),
+        (Obj::Code(a), Obj::Code(b)) => {
+            Code {
+                consts: &[],
+                ..*a
+            } == Code {
+                consts: &[],
+                ..*b
+            } && obj_semantic_eq(&Obj::Tuple(a.consts), &Obj::Tuple(b.consts))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// Whether each element of `a` can be paired with a distinct, equal element of `b`.
+fn unordered_eq<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut used = vec![false; b.len()];
+    a.iter().all(|a| {
+        let found = b
+            .iter()
+            .zip(&mut used)
+            .find(|(b, used)| !**used && eq(a, b));
+        match found {
+            Some((_, used)) => {
+                *used = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
 /// Should mostly match Python's repr
 ///
-/// # Float, Complex
+/// # Float
 /// - Uses `float('...')` instead of `...` for nan, inf, and -inf.
 /// - Uses Rust's float-to-decimal conversion.
 ///
+/// # Complex
+/// - Matches Python exactly, e.g. `(inf+nanj)` and `1e+16j`.
+///
 /// # Bytes, String
 /// - Always uses double-quotes
 /// - Escapes both kinds of quotes
@@ -228,6 +1314,13 @@ impl<'a> Obj<'a> {
 /// # Code
 /// - Uses named arguments for readability
 /// - lnotab is formatted as bytes(...) with a list of integers, instead of a bytes literal
+///
+/// # Unknown
+/// Not a Python value, so shown as `<unknown type code 0x..>`.
+///
+/// # Alternate
+/// `{:#?}` puts each element of a container and each field of a code object on its own
+/// indented line; see [`Obj::pretty`].
 impl fmt::Debug for Obj<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -241,18 +1334,21 @@ impl fmt::Debug for Obj<'_> {
             &Self::Complex(x) => python_complex_repr(f, x),
             Self::Bytes(x) => python_bytes_repr(f, x),
             Self::String(x) => python_string_repr(f, x),
+            Self::RawString(x) => python_string_repr(f, &String::from_utf8_lossy(x)),
             Self::Tuple(x) => python_tuple_repr(f, x),
-            Self::List(x) => f.debug_list().entries(x.read().unwrap().iter()).finish(),
-            Self::Dict(x) => f.debug_map().entries(x.read().unwrap().iter()).finish(),
-            Self::Set(x) => f.debug_set().entries(x.read().unwrap().iter()).finish(),
+            Self::List(x) => f.debug_list().entries(x.iter()).finish(),
+            Self::Dict(x) => f.debug_map().entries(x.iter().map(|(k, v)| (k, v))).finish(),
+            Self::Set(x) => f.debug_set().entries(x.iter()).finish(),
             Self::FrozenSet(x) => python_frozenset_repr(f, x),
             Self::Code(x) => python_code_repr(f, x),
+            Self::Unknown { tag } => write!(f, "<unknown type code {:#04x}>", tag),
         }
     }
 }
 fn python_float_repr_full(f: &mut fmt::Formatter, x: f64) -> fmt::Result {
     python_float_repr_core(f, x)?;
-    if x.fract() == 0. {
+    // Not `fract`, which needs std. Both are NaN for infinities.
+    if x % 1. == 0. {
         write!(f, ".0")?;
     };
     Ok(())
@@ -275,19 +1371,59 @@ fn python_float_repr_core(f: &mut fmt::Formatter, x: f64) -> fmt::Result {
     }
 }
 fn python_complex_repr(f: &mut fmt::Formatter, x: Complex<f64>) -> fmt::Result {
+    // Like CPython's complex_repr: a positive zero real part is left out
     if x.re == 0. && x.re.is_sign_positive() {
-        python_float_repr_core(f, x.im)?;
-        write!(f, "j")?;
+        python_float_repr_short(f, x.im, false)?;
+        write!(f, "j")
     } else {
         write!(f, "(")?;
-        python_float_repr_core(f, x.re)?;
-        if x.im >= 0. || x.im.is_nan() {
-            write!(f, "+")?;
+        python_float_repr_short(f, x.re, false)?;
+        python_float_repr_short(f, x.im, true)?;
+        write!(f, "j)")
+    }
+}
+/// Like Python's `repr(x)`, minus the `.0` of whole numbers, which is how complex numbers
+/// show their parts: `nan` and `inf` are bare, and exponents are used for very large or small
+/// magnitudes. With `sign`, positive numbers (and NaNs) get a `+`.
+fn python_float_repr_short(f: &mut fmt::Formatter, x: f64, sign: bool) -> fmt::Result {
+    if x.is_nan() {
+        return write!(f, "{}nan", if sign { "+" } else { "" });
+    }
+    if x.is_sign_negative() {
+        write!(f, "-")?;
+    } else if sign {
+        write!(f, "+")?;
+    }
+    if x.is_infinite() {
+        return write!(f, "inf");
+    }
+    // The shortest digits that round-trip, like Python's, as `d.ddde<exp>`
+    let sci = format!("{:e}", x.abs());
+    let (mantissa, exp) = sci.split_at(sci.find('e').unwrap_or(sci.len()));
+    let exp: i32 = exp[1..].parse().unwrap_or(0);
+    let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+    // Python switches to exponents when the decimal point would be this far out
+    let decpt = exp + 1;
+    if decpt <= -4 || decpt > 16 {
+        let (first, rest) = digits.split_at(1);
+        write!(f, "{}", first)?;
+        if !rest.is_empty() {
+            write!(f, ".{}", rest)?;
         }
-        python_float_repr_core(f, x.im)?;
-        write!(f, "j)")?;
-    };
-    Ok(())
+        write!(f, "e{}{:02}", if exp < 0 { '-' } else { '+' }, exp.abs())
+    } else if decpt <= 0 {
+        #[allow(clippy::cast_sign_loss)]
+        let zeros = "0".repeat((-decpt) as usize);
+        write!(f, "0.{}{}", zeros, digits)
+    } else {
+        #[allow(clippy::cast_sign_loss)]
+        let decpt = decpt as usize;
+        if digits.len() <= decpt {
+            write!(f, "{}{}", digits, "0".repeat(decpt - digits.len()))
+        } else {
+            write!(f, "{}.{}", &digits[..decpt], &digits[decpt..])
+        }
+    }
 }
 fn python_bytes_repr(f: &mut fmt::Formatter, x: &[u8]) -> fmt::Result {
     write!(f, "b\"")?;
@@ -305,22 +1441,23 @@ fn python_bytes_repr(f: &mut fmt::Formatter, x: &[u8]) -> fmt::Result {
     Ok(())
 }
 fn python_string_repr(f: &mut fmt::Formatter, x: &str) -> fmt::Result {
-    let original = format!("{:?}", x);
-    let mut last_end = 0;
-    // Note: the behavior is arbitrary if there are improper escapes.
-    for (start, _) in original.match_indices("\\u{") {
-        f.write_str(&original[last_end..start])?;
-        let len = original[start..].find('}').ok_or(fmt::Error)? + 1;
-        let end = start + len;
-        match len - 4 {
-            0..=2 => write!(f, "\\x{:0>2}", &original[start + 3..end - 1])?,
-            3..=4 => write!(f, "\\u{:0>4}", &original[start + 3..end - 1])?,
-            5..=8 => write!(f, "\\U{:0>8}", &original[start + 3..end - 1])?,
-            _ => panic!("Internal error: length of unicode escape = {} > 8", len),
-        }
-        last_end = end;
-    }
-    f.write_str(&original[last_end..])?;
+    write!(f, "\"")?;
+    for c in x.chars() {
+        match c {
+            '\t' => write!(f, "\\t")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\'' | '"' | '\\' => write!(f, "\\{}", c)?,
+            // Rust's idea of which characters need escaping is close enough to Python's.
+            _ if c.escape_debug().len() == 1 => write!(f, "{}", c)?,
+            _ => match u32::from(c) {
+                n @ 0..=0xff => write!(f, "\\x{:02x}", n)?,
+                n @ 0x100..=0xffff => write!(f, "\\u{:04x}", n)?,
+                n => write!(f, "\\U{:08x}", n)?,
+            },
+        }
+    }
+    write!(f, "\"")?;
     Ok(())
 }
 fn python_tuple_repr<'a>(f: &mut fmt::Formatter, x: &[Obj<'a>]) -> fmt::Result {
@@ -343,24 +1480,79 @@ fn python_frozenset_repr<'a>(f: &mut fmt::Formatter, x: &[Obj<'a>]) -> fmt::Resu
     Ok(())
 }
 fn python_code_repr(f: &mut fmt::Formatter, x: &Code) -> fmt::Result {
-    write!(f, "code(argcount={:?}, posonlyargcount={:?}, kwonlyargcount={:?}, nlocals={:?}, stacksize={:?}, flags={:?}, code={:?}, consts={:?}, names={:?}, varnames={:?}, freevars={:?}, cellvars={:?}, filename={:?}, name={:?}, firstlineno={:?}, lnotab=bytes({:?}))", x.argcount, x.posonlyargcount, x.kwonlyargcount, x.nlocals, x.stacksize, x.flags, Obj::Bytes(Arc::clone(&x.code)), x.consts, x.names, x.varnames, x.freevars, x.cellvars, x.filename, x.name, x.firstlineno, &x.lnotab)
+    if f.alternate() {
+        return python_code_repr_pretty(f, x);
+    }
+    write!(f, "code(argcount={:?}, posonlyargcount={:?}, kwonlyargcount={:?}, nlocals={:?}, stacksize={:?}, flags={:?}, code=", x.argcount, x.posonlyargcount, x.kwonlyargcount, x.nlocals, x.stacksize, x.flags)?;
+    python_bytes_repr(f, x.code)?;
+    write!(f, ", consts={:?}, names={:?}, varnames={:?}, freevars={:?}, cellvars={:?}, filename={:?}, name={:?}, qualname={:?}, firstlineno={:?}, lnotab=bytes({:?}), exceptiontable=bytes({:?}))", x.consts, x.names, x.varnames, x.freevars, x.cellvars, x.filename, x.name, x.qualname, x.firstlineno, &x.lnotab, &x.exceptiontable)
 }
-
-fn python_tuple_hashable_repr<'a>(f: &mut fmt::Formatter, x: &[Obj<'a>]) -> fmt::Result {
-    if x.is_empty() {
-        f.write_str("()") // Otherwise this would get formatted into an empty string
-    } else {
-        let mut debug_tuple = f.debug_tuple("");
-        for o in x.iter() {
-            debug_tuple.field(&o);
-        }
-        debug_tuple.finish()
+/// Formats with [`python_bytes_repr`], for use where a `dyn Debug` is needed.
+struct BytesRepr<'a>(&'a [u8]);
+impl fmt::Debug for BytesRepr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        python_bytes_repr(f, self.0)
+    }
+}
+fn python_code_repr_pretty(f: &mut fmt::Formatter, x: &Code) -> fmt::Result {
+    let lnotab = format!("bytes({:?})", x.lnotab);
+    let exceptiontable = format!("bytes({:?})", x.exceptiontable);
+    let fields: [(&str, &dyn fmt::Debug); 18] = [
+        ("argcount", &x.argcount),
+        ("posonlyargcount", &x.posonlyargcount),
+        ("kwonlyargcount", &x.kwonlyargcount),
+        ("nlocals", &x.nlocals),
+        ("stacksize", &x.stacksize),
+        ("flags", &x.flags),
+        ("code", &BytesRepr(x.code)),
+        ("consts", &x.consts),
+        ("names", &x.names),
+        ("varnames", &x.varnames),
+        ("freevars", &x.freevars),
+        ("cellvars", &x.cellvars),
+        ("filename", &x.filename),
+        ("name", &x.name),
+        ("qualname", &x.qualname),
+        ("firstlineno", &x.firstlineno),
+        ("lnotab", &format_args!("{}", lnotab)),
+        ("exceptiontable", &format_args!("{}", exceptiontable)),
+    ];
+    writeln!(f, "code(")?;
+    for (name, value) in &fields {
+        // Nested values are formatted on their own, then indented one more level
+        let value = format!("{:#?}", value).replace('\n', "\n    ");
+        writeln!(f, "    {}={},", name, value)?;
     }
+    write!(f, ")")
 }
 
 #[cfg(test)]
 mod test;
 
+mod hash;
+
+#[cfg(feature = "std")]
 mod utils;
 
+mod dedup;
+
+#[cfg(feature = "std")]
 pub mod read;
+
+#[cfg(feature = "std")]
+pub mod write;
+
+#[cfg(feature = "std")]
+pub mod pyc;
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "opcode")]
+pub mod opcode;
+
+#[cfg(feature = "opcode")]
+pub mod dis;
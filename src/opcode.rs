@@ -0,0 +1,984 @@
+//! Opcode tables for the bytecode in [`Code::code`](crate::Code::code), which changes between
+//! Python versions.
+//!
+//! Generated from each version's `dis.opmap`. Specialized instructions (3.11's adaptive opcodes)
+//! and 3.12's instrumented ones are left out, since CPython replaces them with their generic
+//! form before marshalling.
+use crate::MarshalVersion;
+
+/// Opcodes from here on take an argument. The same in every supported version.
+pub const HAVE_ARGUMENT: u8 = 90;
+/// Prefix that supplies the high bytes of the next instruction's argument, since Python 3.6.
+pub const EXTENDED_ARG: u8 = 144;
+/// [`EXTENDED_ARG`] in Python 2.7.
+pub const EXTENDED_ARG_PY27: u8 = 145;
+
+/// What an instruction's argument refers to, from the `dis.has*` lists.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Index into `co_consts`
+    Const,
+    /// Index into `co_names`
+    Name,
+    /// Index into `co_varnames`
+    Local,
+    /// Index into `co_cellvars` followed by `co_freevars`
+    Free,
+    /// Jump target, relative to the next instruction
+    JumpRelative,
+    /// Jump target, from the start of the code
+    JumpAbsolute,
+    /// Index into `dis.cmp_op`
+    Compare,
+    /// A count, flags, or anything else without a table to look in
+    Plain,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub code: u8,
+    pub name: &'static str,
+    /// `None` for opcodes below `HAVE_ARGUMENT`, whose argument byte is ignored.
+    pub arg: Option<ArgKind>,
+}
+impl Opcode {
+    #[must_use]
+    pub fn has_arg(&self) -> bool {
+        self.arg.is_some()
+    }
+}
+
+const fn op(code: u8, name: &'static str, arg: Option<ArgKind>) -> Opcode {
+    Opcode { code, name, arg }
+}
+
+/// All known opcodes for `version`, sorted by [`Opcode::code`].
+#[must_use]
+pub fn opcodes(version: MarshalVersion) -> &'static [Opcode] {
+    match version {
+        MarshalVersion::Py27 => &PY27,
+        MarshalVersion::Py37 => &PY37,
+        MarshalVersion::Py38 => &PY38,
+        MarshalVersion::Py39 => &PY39,
+        MarshalVersion::Py310 => &PY310,
+        MarshalVersion::Py311 => &PY311,
+        MarshalVersion::Py312 => &PY312,
+    }
+}
+
+#[must_use]
+pub fn opcode(version: MarshalVersion, code: u8) -> Option<&'static Opcode> {
+    let table = opcodes(version);
+    table.binary_search_by_key(&code, |op| op.code).ok().map(|i| &table[i])
+}
+
+/// Like `dis.opname[code]`, but `None` for unused opcodes instead of `"<code>"`.
+#[must_use]
+pub fn opcode_name(version: MarshalVersion, code: u8) -> Option<&'static str> {
+    opcode(version, code).map(|op| op.name)
+}
+
+/// Like [`opcode`], but by name.
+#[must_use]
+pub fn opcode_by_name(version: MarshalVersion, name: &str) -> Option<&'static Opcode> {
+    opcodes(version).iter().find(|op| op.name == name)
+}
+
+#[rustfmt::skip]
+static PY27: [Opcode; 119] = [
+    op(0, "STOP_CODE", None),
+    op(1, "POP_TOP", None),
+    op(2, "ROT_TWO", None),
+    op(3, "ROT_THREE", None),
+    op(4, "DUP_TOP", None),
+    op(5, "ROT_FOUR", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(13, "UNARY_CONVERT", None),
+    op(15, "UNARY_INVERT", None),
+    op(19, "BINARY_POWER", None),
+    op(20, "BINARY_MULTIPLY", None),
+    op(21, "BINARY_DIVIDE", None),
+    op(22, "BINARY_MODULO", None),
+    op(23, "BINARY_ADD", None),
+    op(24, "BINARY_SUBTRACT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_FLOOR_DIVIDE", None),
+    op(27, "BINARY_TRUE_DIVIDE", None),
+    op(28, "INPLACE_FLOOR_DIVIDE", None),
+    op(29, "INPLACE_TRUE_DIVIDE", None),
+    op(30, "SLICE+0", None),
+    op(31, "SLICE+1", None),
+    op(32, "SLICE+2", None),
+    op(33, "SLICE+3", None),
+    op(40, "STORE_SLICE+0", None),
+    op(41, "STORE_SLICE+1", None),
+    op(42, "STORE_SLICE+2", None),
+    op(43, "STORE_SLICE+3", None),
+    op(50, "DELETE_SLICE+0", None),
+    op(51, "DELETE_SLICE+1", None),
+    op(52, "DELETE_SLICE+2", None),
+    op(53, "DELETE_SLICE+3", None),
+    op(54, "STORE_MAP", None),
+    op(55, "INPLACE_ADD", None),
+    op(56, "INPLACE_SUBTRACT", None),
+    op(57, "INPLACE_MULTIPLY", None),
+    op(58, "INPLACE_DIVIDE", None),
+    op(59, "INPLACE_MODULO", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(62, "BINARY_LSHIFT", None),
+    op(63, "BINARY_RSHIFT", None),
+    op(64, "BINARY_AND", None),
+    op(65, "BINARY_XOR", None),
+    op(66, "BINARY_OR", None),
+    op(67, "INPLACE_POWER", None),
+    op(68, "GET_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "PRINT_ITEM", None),
+    op(72, "PRINT_NEWLINE", None),
+    op(73, "PRINT_ITEM_TO", None),
+    op(74, "PRINT_NEWLINE_TO", None),
+    op(75, "INPLACE_LSHIFT", None),
+    op(76, "INPLACE_RSHIFT", None),
+    op(77, "INPLACE_AND", None),
+    op(78, "INPLACE_XOR", None),
+    op(79, "INPLACE_OR", None),
+    op(80, "BREAK_LOOP", None),
+    op(81, "WITH_CLEANUP", None),
+    op(82, "LOAD_LOCALS", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "EXEC_STMT", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "POP_BLOCK", None),
+    op(88, "END_FINALLY", None),
+    op(89, "BUILD_CLASS", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(99, "DUP_TOPX", Some(ArgKind::Plain)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(113, "JUMP_ABSOLUTE", Some(ArgKind::JumpAbsolute)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpAbsolute)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpAbsolute)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(119, "CONTINUE_LOOP", Some(ArgKind::JumpAbsolute)),
+    op(120, "SETUP_LOOP", Some(ArgKind::JumpRelative)),
+    op(121, "SETUP_EXCEPT", Some(ArgKind::JumpRelative)),
+    op(122, "SETUP_FINALLY", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "CALL_FUNCTION", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(134, "MAKE_CLOSURE", Some(ArgKind::Plain)),
+    op(135, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(136, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(137, "STORE_DEREF", Some(ArgKind::Free)),
+    op(140, "CALL_FUNCTION_VAR", Some(ArgKind::Plain)),
+    op(141, "CALL_FUNCTION_KW", Some(ArgKind::Plain)),
+    op(142, "CALL_FUNCTION_VAR_KW", Some(ArgKind::Plain)),
+    op(143, "SETUP_WITH", Some(ArgKind::JumpRelative)),
+    op(145, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+];
+
+#[rustfmt::skip]
+static PY37: [Opcode; 119] = [
+    op(1, "POP_TOP", None),
+    op(2, "ROT_TWO", None),
+    op(3, "ROT_THREE", None),
+    op(4, "DUP_TOP", None),
+    op(5, "DUP_TOP_TWO", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(16, "BINARY_MATRIX_MULTIPLY", None),
+    op(17, "INPLACE_MATRIX_MULTIPLY", None),
+    op(19, "BINARY_POWER", None),
+    op(20, "BINARY_MULTIPLY", None),
+    op(22, "BINARY_MODULO", None),
+    op(23, "BINARY_ADD", None),
+    op(24, "BINARY_SUBTRACT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_FLOOR_DIVIDE", None),
+    op(27, "BINARY_TRUE_DIVIDE", None),
+    op(28, "INPLACE_FLOOR_DIVIDE", None),
+    op(29, "INPLACE_TRUE_DIVIDE", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(55, "INPLACE_ADD", None),
+    op(56, "INPLACE_SUBTRACT", None),
+    op(57, "INPLACE_MULTIPLY", None),
+    op(59, "INPLACE_MODULO", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(62, "BINARY_LSHIFT", None),
+    op(63, "BINARY_RSHIFT", None),
+    op(64, "BINARY_AND", None),
+    op(65, "BINARY_XOR", None),
+    op(66, "BINARY_OR", None),
+    op(67, "INPLACE_POWER", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(72, "YIELD_FROM", None),
+    op(73, "GET_AWAITABLE", None),
+    op(75, "INPLACE_LSHIFT", None),
+    op(76, "INPLACE_RSHIFT", None),
+    op(77, "INPLACE_AND", None),
+    op(78, "INPLACE_XOR", None),
+    op(79, "INPLACE_OR", None),
+    op(80, "BREAK_LOOP", None),
+    op(81, "WITH_CLEANUP_START", None),
+    op(82, "WITH_CLEANUP_FINISH", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "POP_BLOCK", None),
+    op(88, "END_FINALLY", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(113, "JUMP_ABSOLUTE", Some(ArgKind::JumpAbsolute)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpAbsolute)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpAbsolute)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(119, "CONTINUE_LOOP", Some(ArgKind::JumpAbsolute)),
+    op(120, "SETUP_LOOP", Some(ArgKind::JumpRelative)),
+    op(121, "SETUP_EXCEPT", Some(ArgKind::JumpRelative)),
+    op(122, "SETUP_FINALLY", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "CALL_FUNCTION", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(135, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(136, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(137, "STORE_DEREF", Some(ArgKind::Free)),
+    op(138, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(141, "CALL_FUNCTION_KW", Some(ArgKind::Plain)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(143, "SETUP_WITH", Some(ArgKind::JumpRelative)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(148, "LOAD_CLASSDEREF", Some(ArgKind::Free)),
+    op(149, "BUILD_LIST_UNPACK", Some(ArgKind::Plain)),
+    op(150, "BUILD_MAP_UNPACK", Some(ArgKind::Plain)),
+    op(151, "BUILD_MAP_UNPACK_WITH_CALL", Some(ArgKind::Plain)),
+    op(152, "BUILD_TUPLE_UNPACK", Some(ArgKind::Plain)),
+    op(153, "BUILD_SET_UNPACK", Some(ArgKind::Plain)),
+    op(154, "SETUP_ASYNC_WITH", Some(ArgKind::JumpRelative)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(158, "BUILD_TUPLE_UNPACK_WITH_CALL", Some(ArgKind::Plain)),
+    op(160, "LOAD_METHOD", Some(ArgKind::Name)),
+    op(161, "CALL_METHOD", Some(ArgKind::Plain)),
+];
+
+#[rustfmt::skip]
+static PY38: [Opcode; 120] = [
+    op(1, "POP_TOP", None),
+    op(2, "ROT_TWO", None),
+    op(3, "ROT_THREE", None),
+    op(4, "DUP_TOP", None),
+    op(5, "DUP_TOP_TWO", None),
+    op(6, "ROT_FOUR", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(16, "BINARY_MATRIX_MULTIPLY", None),
+    op(17, "INPLACE_MATRIX_MULTIPLY", None),
+    op(19, "BINARY_POWER", None),
+    op(20, "BINARY_MULTIPLY", None),
+    op(22, "BINARY_MODULO", None),
+    op(23, "BINARY_ADD", None),
+    op(24, "BINARY_SUBTRACT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_FLOOR_DIVIDE", None),
+    op(27, "BINARY_TRUE_DIVIDE", None),
+    op(28, "INPLACE_FLOOR_DIVIDE", None),
+    op(29, "INPLACE_TRUE_DIVIDE", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(53, "BEGIN_FINALLY", None),
+    op(54, "END_ASYNC_FOR", None),
+    op(55, "INPLACE_ADD", None),
+    op(56, "INPLACE_SUBTRACT", None),
+    op(57, "INPLACE_MULTIPLY", None),
+    op(59, "INPLACE_MODULO", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(62, "BINARY_LSHIFT", None),
+    op(63, "BINARY_RSHIFT", None),
+    op(64, "BINARY_AND", None),
+    op(65, "BINARY_XOR", None),
+    op(66, "BINARY_OR", None),
+    op(67, "INPLACE_POWER", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(72, "YIELD_FROM", None),
+    op(73, "GET_AWAITABLE", None),
+    op(75, "INPLACE_LSHIFT", None),
+    op(76, "INPLACE_RSHIFT", None),
+    op(77, "INPLACE_AND", None),
+    op(78, "INPLACE_XOR", None),
+    op(79, "INPLACE_OR", None),
+    op(81, "WITH_CLEANUP_START", None),
+    op(82, "WITH_CLEANUP_FINISH", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "POP_BLOCK", None),
+    op(88, "END_FINALLY", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(113, "JUMP_ABSOLUTE", Some(ArgKind::JumpAbsolute)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpAbsolute)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpAbsolute)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(122, "SETUP_FINALLY", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "CALL_FUNCTION", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(135, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(136, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(137, "STORE_DEREF", Some(ArgKind::Free)),
+    op(138, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(141, "CALL_FUNCTION_KW", Some(ArgKind::Plain)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(143, "SETUP_WITH", Some(ArgKind::JumpRelative)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(148, "LOAD_CLASSDEREF", Some(ArgKind::Free)),
+    op(149, "BUILD_LIST_UNPACK", Some(ArgKind::Plain)),
+    op(150, "BUILD_MAP_UNPACK", Some(ArgKind::Plain)),
+    op(151, "BUILD_MAP_UNPACK_WITH_CALL", Some(ArgKind::Plain)),
+    op(152, "BUILD_TUPLE_UNPACK", Some(ArgKind::Plain)),
+    op(153, "BUILD_SET_UNPACK", Some(ArgKind::Plain)),
+    op(154, "SETUP_ASYNC_WITH", Some(ArgKind::JumpRelative)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(158, "BUILD_TUPLE_UNPACK_WITH_CALL", Some(ArgKind::Plain)),
+    op(160, "LOAD_METHOD", Some(ArgKind::Name)),
+    op(161, "CALL_METHOD", Some(ArgKind::Plain)),
+    op(162, "CALL_FINALLY", Some(ArgKind::JumpRelative)),
+    op(163, "POP_FINALLY", Some(ArgKind::Plain)),
+];
+
+#[rustfmt::skip]
+static PY39: [Opcode; 119] = [
+    op(1, "POP_TOP", None),
+    op(2, "ROT_TWO", None),
+    op(3, "ROT_THREE", None),
+    op(4, "DUP_TOP", None),
+    op(5, "DUP_TOP_TWO", None),
+    op(6, "ROT_FOUR", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(16, "BINARY_MATRIX_MULTIPLY", None),
+    op(17, "INPLACE_MATRIX_MULTIPLY", None),
+    op(19, "BINARY_POWER", None),
+    op(20, "BINARY_MULTIPLY", None),
+    op(22, "BINARY_MODULO", None),
+    op(23, "BINARY_ADD", None),
+    op(24, "BINARY_SUBTRACT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_FLOOR_DIVIDE", None),
+    op(27, "BINARY_TRUE_DIVIDE", None),
+    op(28, "INPLACE_FLOOR_DIVIDE", None),
+    op(29, "INPLACE_TRUE_DIVIDE", None),
+    op(48, "RERAISE", None),
+    op(49, "WITH_EXCEPT_START", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(54, "END_ASYNC_FOR", None),
+    op(55, "INPLACE_ADD", None),
+    op(56, "INPLACE_SUBTRACT", None),
+    op(57, "INPLACE_MULTIPLY", None),
+    op(59, "INPLACE_MODULO", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(62, "BINARY_LSHIFT", None),
+    op(63, "BINARY_RSHIFT", None),
+    op(64, "BINARY_AND", None),
+    op(65, "BINARY_XOR", None),
+    op(66, "BINARY_OR", None),
+    op(67, "INPLACE_POWER", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(72, "YIELD_FROM", None),
+    op(73, "GET_AWAITABLE", None),
+    op(74, "LOAD_ASSERTION_ERROR", None),
+    op(75, "INPLACE_LSHIFT", None),
+    op(76, "INPLACE_RSHIFT", None),
+    op(77, "INPLACE_AND", None),
+    op(78, "INPLACE_XOR", None),
+    op(79, "INPLACE_OR", None),
+    op(82, "LIST_TO_TUPLE", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "POP_BLOCK", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(113, "JUMP_ABSOLUTE", Some(ArgKind::JumpAbsolute)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpAbsolute)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpAbsolute)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(117, "IS_OP", Some(ArgKind::Plain)),
+    op(118, "CONTAINS_OP", Some(ArgKind::Plain)),
+    op(121, "JUMP_IF_NOT_EXC_MATCH", Some(ArgKind::JumpAbsolute)),
+    op(122, "SETUP_FINALLY", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "CALL_FUNCTION", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(135, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(136, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(137, "STORE_DEREF", Some(ArgKind::Free)),
+    op(138, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(141, "CALL_FUNCTION_KW", Some(ArgKind::Plain)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(143, "SETUP_WITH", Some(ArgKind::JumpRelative)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(148, "LOAD_CLASSDEREF", Some(ArgKind::Free)),
+    op(154, "SETUP_ASYNC_WITH", Some(ArgKind::JumpRelative)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(160, "LOAD_METHOD", Some(ArgKind::Name)),
+    op(161, "CALL_METHOD", Some(ArgKind::Plain)),
+    op(162, "LIST_EXTEND", Some(ArgKind::Plain)),
+    op(163, "SET_UPDATE", Some(ArgKind::Plain)),
+    op(164, "DICT_MERGE", Some(ArgKind::Plain)),
+    op(165, "DICT_UPDATE", Some(ArgKind::Plain)),
+];
+
+#[rustfmt::skip]
+static PY310: [Opcode; 127] = [
+    op(1, "POP_TOP", None),
+    op(2, "ROT_TWO", None),
+    op(3, "ROT_THREE", None),
+    op(4, "DUP_TOP", None),
+    op(5, "DUP_TOP_TWO", None),
+    op(6, "ROT_FOUR", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(16, "BINARY_MATRIX_MULTIPLY", None),
+    op(17, "INPLACE_MATRIX_MULTIPLY", None),
+    op(19, "BINARY_POWER", None),
+    op(20, "BINARY_MULTIPLY", None),
+    op(22, "BINARY_MODULO", None),
+    op(23, "BINARY_ADD", None),
+    op(24, "BINARY_SUBTRACT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_FLOOR_DIVIDE", None),
+    op(27, "BINARY_TRUE_DIVIDE", None),
+    op(28, "INPLACE_FLOOR_DIVIDE", None),
+    op(29, "INPLACE_TRUE_DIVIDE", None),
+    op(30, "GET_LEN", None),
+    op(31, "MATCH_MAPPING", None),
+    op(32, "MATCH_SEQUENCE", None),
+    op(33, "MATCH_KEYS", None),
+    op(34, "COPY_DICT_WITHOUT_KEYS", None),
+    op(49, "WITH_EXCEPT_START", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(54, "END_ASYNC_FOR", None),
+    op(55, "INPLACE_ADD", None),
+    op(56, "INPLACE_SUBTRACT", None),
+    op(57, "INPLACE_MULTIPLY", None),
+    op(59, "INPLACE_MODULO", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(62, "BINARY_LSHIFT", None),
+    op(63, "BINARY_RSHIFT", None),
+    op(64, "BINARY_AND", None),
+    op(65, "BINARY_XOR", None),
+    op(66, "BINARY_OR", None),
+    op(67, "INPLACE_POWER", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(72, "YIELD_FROM", None),
+    op(73, "GET_AWAITABLE", None),
+    op(74, "LOAD_ASSERTION_ERROR", None),
+    op(75, "INPLACE_LSHIFT", None),
+    op(76, "INPLACE_RSHIFT", None),
+    op(77, "INPLACE_AND", None),
+    op(78, "INPLACE_XOR", None),
+    op(79, "INPLACE_OR", None),
+    op(82, "LIST_TO_TUPLE", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "POP_BLOCK", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(99, "ROT_N", Some(ArgKind::Plain)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpAbsolute)),
+    op(113, "JUMP_ABSOLUTE", Some(ArgKind::JumpAbsolute)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpAbsolute)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpAbsolute)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(117, "IS_OP", Some(ArgKind::Plain)),
+    op(118, "CONTAINS_OP", Some(ArgKind::Plain)),
+    op(119, "RERAISE", Some(ArgKind::Plain)),
+    op(121, "JUMP_IF_NOT_EXC_MATCH", Some(ArgKind::JumpAbsolute)),
+    op(122, "SETUP_FINALLY", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(129, "GEN_START", Some(ArgKind::Plain)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "CALL_FUNCTION", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(135, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(136, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(137, "STORE_DEREF", Some(ArgKind::Free)),
+    op(138, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(141, "CALL_FUNCTION_KW", Some(ArgKind::Plain)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(143, "SETUP_WITH", Some(ArgKind::JumpRelative)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(148, "LOAD_CLASSDEREF", Some(ArgKind::Free)),
+    op(152, "MATCH_CLASS", Some(ArgKind::Plain)),
+    op(154, "SETUP_ASYNC_WITH", Some(ArgKind::JumpRelative)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(160, "LOAD_METHOD", Some(ArgKind::Name)),
+    op(161, "CALL_METHOD", Some(ArgKind::Plain)),
+    op(162, "LIST_EXTEND", Some(ArgKind::Plain)),
+    op(163, "SET_UPDATE", Some(ArgKind::Plain)),
+    op(164, "DICT_MERGE", Some(ArgKind::Plain)),
+    op(165, "DICT_UPDATE", Some(ArgKind::Plain)),
+];
+
+#[rustfmt::skip]
+static PY311: [Opcode; 110] = [
+    op(0, "CACHE", None),
+    op(1, "POP_TOP", None),
+    op(2, "PUSH_NULL", None),
+    op(9, "NOP", None),
+    op(10, "UNARY_POSITIVE", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(30, "GET_LEN", None),
+    op(31, "MATCH_MAPPING", None),
+    op(32, "MATCH_SEQUENCE", None),
+    op(33, "MATCH_KEYS", None),
+    op(35, "PUSH_EXC_INFO", None),
+    op(36, "CHECK_EXC_MATCH", None),
+    op(37, "CHECK_EG_MATCH", None),
+    op(49, "WITH_EXCEPT_START", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(53, "BEFORE_WITH", None),
+    op(54, "END_ASYNC_FOR", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(70, "PRINT_EXPR", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(74, "LOAD_ASSERTION_ERROR", None),
+    op(75, "RETURN_GENERATOR", None),
+    op(82, "LIST_TO_TUPLE", None),
+    op(83, "RETURN_VALUE", None),
+    op(84, "IMPORT_STAR", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(86, "YIELD_VALUE", None),
+    op(87, "ASYNC_GEN_WRAP", None),
+    op(88, "PREP_RERAISE_STAR", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(99, "SWAP", Some(ArgKind::Plain)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(111, "JUMP_IF_FALSE_OR_POP", Some(ArgKind::JumpRelative)),
+    op(112, "JUMP_IF_TRUE_OR_POP", Some(ArgKind::JumpRelative)),
+    op(114, "POP_JUMP_FORWARD_IF_FALSE", Some(ArgKind::JumpRelative)),
+    op(115, "POP_JUMP_FORWARD_IF_TRUE", Some(ArgKind::JumpRelative)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(117, "IS_OP", Some(ArgKind::Plain)),
+    op(118, "CONTAINS_OP", Some(ArgKind::Plain)),
+    op(119, "RERAISE", Some(ArgKind::Plain)),
+    op(120, "COPY", Some(ArgKind::Plain)),
+    op(122, "BINARY_OP", Some(ArgKind::Plain)),
+    op(123, "SEND", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(128, "POP_JUMP_FORWARD_IF_NOT_NONE", Some(ArgKind::JumpRelative)),
+    op(129, "POP_JUMP_FORWARD_IF_NONE", Some(ArgKind::JumpRelative)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "GET_AWAITABLE", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(134, "JUMP_BACKWARD_NO_INTERRUPT", Some(ArgKind::JumpRelative)),
+    op(135, "MAKE_CELL", Some(ArgKind::Free)),
+    op(136, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(137, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(138, "STORE_DEREF", Some(ArgKind::Free)),
+    op(139, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(140, "JUMP_BACKWARD", Some(ArgKind::JumpRelative)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(148, "LOAD_CLASSDEREF", Some(ArgKind::Free)),
+    op(149, "COPY_FREE_VARS", Some(ArgKind::Plain)),
+    op(151, "RESUME", Some(ArgKind::Plain)),
+    op(152, "MATCH_CLASS", Some(ArgKind::Plain)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(160, "LOAD_METHOD", Some(ArgKind::Name)),
+    op(162, "LIST_EXTEND", Some(ArgKind::Plain)),
+    op(163, "SET_UPDATE", Some(ArgKind::Plain)),
+    op(164, "DICT_MERGE", Some(ArgKind::Plain)),
+    op(165, "DICT_UPDATE", Some(ArgKind::Plain)),
+    op(166, "PRECALL", Some(ArgKind::Plain)),
+    op(171, "CALL", Some(ArgKind::Plain)),
+    op(172, "KW_NAMES", Some(ArgKind::Const)),
+    op(173, "POP_JUMP_BACKWARD_IF_NOT_NONE", Some(ArgKind::JumpRelative)),
+    op(174, "POP_JUMP_BACKWARD_IF_NONE", Some(ArgKind::JumpRelative)),
+    op(175, "POP_JUMP_BACKWARD_IF_FALSE", Some(ArgKind::JumpRelative)),
+    op(176, "POP_JUMP_BACKWARD_IF_TRUE", Some(ArgKind::JumpRelative)),
+];
+
+#[rustfmt::skip]
+static PY312: [Opcode; 111] = [
+    op(0, "CACHE", None),
+    op(1, "POP_TOP", None),
+    op(2, "PUSH_NULL", None),
+    op(3, "INTERPRETER_EXIT", None),
+    op(4, "END_FOR", None),
+    op(5, "END_SEND", None),
+    op(9, "NOP", None),
+    op(11, "UNARY_NEGATIVE", None),
+    op(12, "UNARY_NOT", None),
+    op(15, "UNARY_INVERT", None),
+    op(17, "RESERVED", None),
+    op(25, "BINARY_SUBSCR", None),
+    op(26, "BINARY_SLICE", None),
+    op(27, "STORE_SLICE", None),
+    op(30, "GET_LEN", None),
+    op(31, "MATCH_MAPPING", None),
+    op(32, "MATCH_SEQUENCE", None),
+    op(33, "MATCH_KEYS", None),
+    op(35, "PUSH_EXC_INFO", None),
+    op(36, "CHECK_EXC_MATCH", None),
+    op(37, "CHECK_EG_MATCH", None),
+    op(49, "WITH_EXCEPT_START", None),
+    op(50, "GET_AITER", None),
+    op(51, "GET_ANEXT", None),
+    op(52, "BEFORE_ASYNC_WITH", None),
+    op(53, "BEFORE_WITH", None),
+    op(54, "END_ASYNC_FOR", None),
+    op(55, "CLEANUP_THROW", None),
+    op(60, "STORE_SUBSCR", None),
+    op(61, "DELETE_SUBSCR", None),
+    op(68, "GET_ITER", None),
+    op(69, "GET_YIELD_FROM_ITER", None),
+    op(71, "LOAD_BUILD_CLASS", None),
+    op(74, "LOAD_ASSERTION_ERROR", None),
+    op(75, "RETURN_GENERATOR", None),
+    op(83, "RETURN_VALUE", None),
+    op(85, "SETUP_ANNOTATIONS", None),
+    op(87, "LOAD_LOCALS", None),
+    op(89, "POP_EXCEPT", None),
+    op(90, "STORE_NAME", Some(ArgKind::Name)),
+    op(91, "DELETE_NAME", Some(ArgKind::Name)),
+    op(92, "UNPACK_SEQUENCE", Some(ArgKind::Plain)),
+    op(93, "FOR_ITER", Some(ArgKind::JumpRelative)),
+    op(94, "UNPACK_EX", Some(ArgKind::Plain)),
+    op(95, "STORE_ATTR", Some(ArgKind::Name)),
+    op(96, "DELETE_ATTR", Some(ArgKind::Name)),
+    op(97, "STORE_GLOBAL", Some(ArgKind::Name)),
+    op(98, "DELETE_GLOBAL", Some(ArgKind::Name)),
+    op(99, "SWAP", Some(ArgKind::Plain)),
+    op(100, "LOAD_CONST", Some(ArgKind::Const)),
+    op(101, "LOAD_NAME", Some(ArgKind::Name)),
+    op(102, "BUILD_TUPLE", Some(ArgKind::Plain)),
+    op(103, "BUILD_LIST", Some(ArgKind::Plain)),
+    op(104, "BUILD_SET", Some(ArgKind::Plain)),
+    op(105, "BUILD_MAP", Some(ArgKind::Plain)),
+    op(106, "LOAD_ATTR", Some(ArgKind::Name)),
+    op(107, "COMPARE_OP", Some(ArgKind::Compare)),
+    op(108, "IMPORT_NAME", Some(ArgKind::Name)),
+    op(109, "IMPORT_FROM", Some(ArgKind::Name)),
+    op(110, "JUMP_FORWARD", Some(ArgKind::JumpRelative)),
+    op(114, "POP_JUMP_IF_FALSE", Some(ArgKind::JumpRelative)),
+    op(115, "POP_JUMP_IF_TRUE", Some(ArgKind::JumpRelative)),
+    op(116, "LOAD_GLOBAL", Some(ArgKind::Name)),
+    op(117, "IS_OP", Some(ArgKind::Plain)),
+    op(118, "CONTAINS_OP", Some(ArgKind::Plain)),
+    op(119, "RERAISE", Some(ArgKind::Plain)),
+    op(120, "COPY", Some(ArgKind::Plain)),
+    op(121, "RETURN_CONST", Some(ArgKind::Const)),
+    op(122, "BINARY_OP", Some(ArgKind::Plain)),
+    op(123, "SEND", Some(ArgKind::JumpRelative)),
+    op(124, "LOAD_FAST", Some(ArgKind::Local)),
+    op(125, "STORE_FAST", Some(ArgKind::Local)),
+    op(126, "DELETE_FAST", Some(ArgKind::Local)),
+    op(127, "LOAD_FAST_CHECK", Some(ArgKind::Local)),
+    op(128, "POP_JUMP_IF_NOT_NONE", Some(ArgKind::JumpRelative)),
+    op(129, "POP_JUMP_IF_NONE", Some(ArgKind::JumpRelative)),
+    op(130, "RAISE_VARARGS", Some(ArgKind::Plain)),
+    op(131, "GET_AWAITABLE", Some(ArgKind::Plain)),
+    op(132, "MAKE_FUNCTION", Some(ArgKind::Plain)),
+    op(133, "BUILD_SLICE", Some(ArgKind::Plain)),
+    op(134, "JUMP_BACKWARD_NO_INTERRUPT", Some(ArgKind::JumpRelative)),
+    op(135, "MAKE_CELL", Some(ArgKind::Free)),
+    op(136, "LOAD_CLOSURE", Some(ArgKind::Free)),
+    op(137, "LOAD_DEREF", Some(ArgKind::Free)),
+    op(138, "STORE_DEREF", Some(ArgKind::Free)),
+    op(139, "DELETE_DEREF", Some(ArgKind::Free)),
+    op(140, "JUMP_BACKWARD", Some(ArgKind::JumpRelative)),
+    op(141, "LOAD_SUPER_ATTR", Some(ArgKind::Name)),
+    op(142, "CALL_FUNCTION_EX", Some(ArgKind::Plain)),
+    op(143, "LOAD_FAST_AND_CLEAR", Some(ArgKind::Local)),
+    op(144, "EXTENDED_ARG", Some(ArgKind::Plain)),
+    op(145, "LIST_APPEND", Some(ArgKind::Plain)),
+    op(146, "SET_ADD", Some(ArgKind::Plain)),
+    op(147, "MAP_ADD", Some(ArgKind::Plain)),
+    op(149, "COPY_FREE_VARS", Some(ArgKind::Plain)),
+    op(150, "YIELD_VALUE", Some(ArgKind::Plain)),
+    op(151, "RESUME", Some(ArgKind::Plain)),
+    op(152, "MATCH_CLASS", Some(ArgKind::Plain)),
+    op(155, "FORMAT_VALUE", Some(ArgKind::Plain)),
+    op(156, "BUILD_CONST_KEY_MAP", Some(ArgKind::Plain)),
+    op(157, "BUILD_STRING", Some(ArgKind::Plain)),
+    op(162, "LIST_EXTEND", Some(ArgKind::Plain)),
+    op(163, "SET_UPDATE", Some(ArgKind::Plain)),
+    op(164, "DICT_MERGE", Some(ArgKind::Plain)),
+    op(165, "DICT_UPDATE", Some(ArgKind::Plain)),
+    op(171, "CALL", Some(ArgKind::Plain)),
+    op(172, "KW_NAMES", Some(ArgKind::Const)),
+    op(173, "CALL_INTRINSIC_1", Some(ArgKind::Plain)),
+    op(174, "CALL_INTRINSIC_2", Some(ArgKind::Plain)),
+    op(175, "LOAD_FROM_DICT_OR_GLOBALS", Some(ArgKind::Name)),
+    op(176, "LOAD_FROM_DICT_OR_DEREF", Some(ArgKind::Free)),
+];
+
+#[cfg(test)]
+mod test {
+    use super::{
+        opcode, opcode_by_name, opcode_name, opcodes, ArgKind, EXTENDED_ARG, EXTENDED_ARG_PY27,
+        HAVE_ARGUMENT,
+    };
+    use crate::MarshalVersion;
+
+    #[test]
+    fn test_opcode_tables() {
+        let versions = [
+            MarshalVersion::Py37,
+            MarshalVersion::Py38,
+            MarshalVersion::Py39,
+            MarshalVersion::Py310,
+            MarshalVersion::Py311,
+            MarshalVersion::Py312,
+        ];
+        for &version in &versions {
+            let table = opcodes(version);
+            assert!(table.windows(2).all(|w| w[0].code < w[1].code), "{:?}", version);
+            assert!(table.iter().all(|op| op.has_arg() == (op.code >= HAVE_ARGUMENT)));
+            assert_eq!(opcode_name(version, EXTENDED_ARG), Some("EXTENDED_ARG"));
+        }
+        assert_eq!(opcode_name(MarshalVersion::Py27, EXTENDED_ARG_PY27), Some("EXTENDED_ARG"));
+        assert_eq!(opcode_name(MarshalVersion::Py27, 71), Some("PRINT_ITEM"));
+        assert_eq!(opcode_name(MarshalVersion::Py38, 100), Some("LOAD_CONST"));
+        assert_eq!(opcode_name(MarshalVersion::Py38, 160), Some("LOAD_METHOD"));
+        assert_eq!(opcode_name(MarshalVersion::Py38, 0), None);
+        assert_eq!(opcode_name(MarshalVersion::Py311, 0), Some("CACHE"));
+        assert_eq!(opcode_name(MarshalVersion::Py311, 151), Some("RESUME"));
+        assert_eq!(opcode_name(MarshalVersion::Py312, 121), Some("RETURN_CONST"));
+        assert_eq!(opcode_by_name(MarshalVersion::Py312, "PRECALL"), None);
+        assert_eq!(opcode_by_name(MarshalVersion::Py38, "RESUME"), None);
+        assert_eq!(opcode_by_name(MarshalVersion::Py37, "ROT_FOUR"), None);
+        assert_eq!(opcode_by_name(MarshalVersion::Py38, "ROT_FOUR").map(|op| op.code), Some(6));
+
+        let load_const = opcode(MarshalVersion::Py311, 100).unwrap();
+        assert_eq!(load_const.arg, Some(ArgKind::Const));
+        let pop_top = opcode(MarshalVersion::Py311, 1).unwrap();
+        assert!(!pop_top.has_arg());
+        let jump = opcode_by_name(MarshalVersion::Py38, "JUMP_ABSOLUTE").unwrap();
+        assert_eq!(jump.arg, Some(ArgKind::JumpAbsolute));
+    }
+}
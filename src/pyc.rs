@@ -0,0 +1,290 @@
+//! Reading and writing `.pyc` files: a header identifying the Python version, followed by a
+//! marshalled code object.
+//!
+//! See `_code_to_timestamp_pyc` in CPython's `importlib/_bootstrap_external.py`.
+use crate::{
+    read::{
+        errors::{Error, Result},
+        marshal_load_buffered, MarshalLoadExOptions,
+    },
+    write::{errors as write_errors, marshal_dump_code},
+    magic_to_marshal_version, Code, MarshalVersion, Obj, ObjArena,
+};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// How the import system decides whether a `.pyc` file is stale (PEP 552).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PycValidation {
+    /// Compared against the source file's modification time and size. Python 2.7 headers
+    /// don't record the size.
+    Timestamp { mtime: u32, source_size: Option<u32> },
+    /// Compared against a SipHash of the source, if `checked`. Unchecked files are never
+    /// considered stale.
+    Hash { source_hash: [u8; 8], checked: bool },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PycHeader {
+    pub magic: u16,
+    pub version: MarshalVersion,
+    pub validation: PycValidation,
+}
+
+const FLAG_HASH_BASED: u32 = 0b01;
+const FLAG_CHECK_SOURCE: u32 = 0b10;
+
+/// Reads the header, leaving `readable` at the start of the code object.
+/// # Errors
+/// [`Error::InvalidPycHeader`] if the magic number isn't followed by `\r\n` or the flags are
+/// invalid,
+/// [`Error::UnknownMagic`] for versions this crate doesn't know, or [`Error::Io`].
+pub fn read_pyc_header(mut readable: impl Read) -> Result<'static, PycHeader> {
+    let mut buf = [0; 4];
+    readable.read_exact(&mut buf)?;
+    if buf[2..] != *b"\r\n" {
+        return Err(Error::InvalidPycHeader);
+    }
+    let magic = u16::from_le_bytes([buf[0], buf[1]]);
+    let version = magic_to_marshal_version(magic.into()).ok_or(Error::UnknownMagic { magic })?;
+    let mut r_u32 = || -> Result<'static, u32> {
+        let mut buf = [0; 4];
+        readable.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    };
+    let validation = if version == MarshalVersion::Py27 {
+        PycValidation::Timestamp {
+            mtime: r_u32()?,
+            source_size: None,
+        }
+    } else {
+        match r_u32()? {
+            0 => PycValidation::Timestamp {
+                mtime: r_u32()?,
+                source_size: Some(r_u32()?),
+            },
+            flags if flags & !(FLAG_HASH_BASED | FLAG_CHECK_SOURCE) == 0
+                && flags & FLAG_HASH_BASED != 0 =>
+            {
+                let mut source_hash = [0; 8];
+                readable.read_exact(&mut source_hash)?;
+                PycValidation::Hash {
+                    source_hash,
+                    checked: flags & FLAG_CHECK_SOURCE != 0,
+                }
+            }
+            _ => return Err(Error::InvalidPycHeader),
+        }
+    };
+    Ok(PycHeader {
+        magic,
+        version,
+        validation,
+    })
+}
+
+/// Reads a `.pyc` file, using its header to pick the right options for the version that wrote
+/// it.
+/// # Errors
+/// See [`read_pyc_header`] and [`ErrorKind`](crate::read::errors::Error).
+pub fn marshal_load_pyc_path<'a>(arena: &'a ObjArena, path: impl AsRef<Path>) -> Result<'a, Obj<'a>> {
+    let mut file = File::open(path)?;
+    let header = read_pyc_header(&mut file)?;
+    marshal_load_buffered(arena, file, MarshalLoadExOptions::for_version(header.version))
+}
+
+/// Writes a `.pyc` file: the header for `magic` and `validation`, then `code`, laid out for the
+/// version `magic` belongs to. The inverse of [`read_pyc_header`] followed by reading the code.
+/// # Errors
+/// [`UnknownMagic`](write_errors::Error::UnknownMagic) for versions this crate doesn't know,
+/// [`MissingFieldForVersion`](write_errors::Error::MissingFieldForVersion) for a Python 3
+/// timestamp without a `source_size`,
+/// [`UnsupportedFieldForVersion`](write_errors::Error::UnsupportedFieldForVersion) for Python 2.7
+/// with a `source_size` or hash, which it doesn't have, or see [`marshal_dump_code`].
+pub fn write_pyc(
+    mut writable: impl Write,
+    magic: u16,
+    validation: PycValidation,
+    code: &Code,
+) -> write_errors::Result<()> {
+    use write_errors::Error;
+    let version = magic_to_marshal_version(magic.into()).ok_or(Error::UnknownMagic { magic })?;
+    let mut header = magic.to_le_bytes().to_vec();
+    header.extend_from_slice(b"\r\n");
+    let py27 = version == MarshalVersion::Py27;
+    match validation {
+        PycValidation::Timestamp { mtime, source_size } => {
+            if !py27 {
+                header.extend_from_slice(&0_u32.to_le_bytes());
+            }
+            header.extend_from_slice(&mtime.to_le_bytes());
+            match source_size {
+                Some(_) if py27 => {
+                    return Err(Error::UnsupportedFieldForVersion {
+                        field: "source_size",
+                        version,
+                    })
+                }
+                Some(size) => header.extend_from_slice(&size.to_le_bytes()),
+                None if py27 => {}
+                None => {
+                    return Err(Error::MissingFieldForVersion {
+                        field: "source_size",
+                        version,
+                    })
+                }
+            }
+        }
+        PycValidation::Hash { .. } if py27 => {
+            return Err(Error::UnsupportedFieldForVersion {
+                field: "source_hash",
+                version,
+            })
+        }
+        PycValidation::Hash {
+            source_hash,
+            checked,
+        } => {
+            let flags = FLAG_HASH_BASED | if checked { FLAG_CHECK_SOURCE } else { 0 };
+            header.extend_from_slice(&flags.to_le_bytes());
+            header.extend_from_slice(&source_hash);
+        }
+    }
+    writable.write_all(&header)?;
+    marshal_dump_code(code, version, writable)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{marshal_load_pyc_path, read_pyc_header, write_pyc, PycHeader, PycValidation};
+    use crate::{
+        read::{errors::Error, marshal_load_ex, MarshalLoadExOptions},
+        write::errors::Error as WriteError,
+        MarshalVersion, Obj, ObjArena,
+    };
+
+    // py_compile.compile() of "x = 1" by Python 3.8 and 2.7
+    const PYC_38: &[u8] = b"U\r\r\n\x00\x00\x00\x00y\xdb\xd2j\x06\x00\x00\x00\xe3\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00N)\x01\xda\x01x\xa9\x00r\x03\x00\x00\x00r\x03\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00\xf3\x00\x00\x00\x00";
+    const PYC_27: &[u8] = b"\x03\xf3\r\ny\xdb\xd2jc\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\n\x00\x00\x00d\x00\x00Z\x00\x00d\x01\x00S(\x02\x00\x00\x00i\x01\x00\x00\x00N(\x01\x00\x00\x00t\x01\x00\x00\x00x(\x00\x00\x00\x00(\x00\x00\x00\x00(\x00\x00\x00\x00s\x04\x00\x00\x00m.pyt\x08\x00\x00\x00<module>\x01\x00\x00\x00t\x00\x00\x00\x00";
+
+    #[test]
+    fn test_read_pyc_header() {
+        let mut input = PYC_38;
+        assert_eq!(
+            read_pyc_header(&mut input).unwrap(),
+            PycHeader {
+                magic: 3413,
+                version: MarshalVersion::Py38,
+                validation: PycValidation::Timestamp {
+                    mtime: 0x6ad2_db79,
+                    source_size: Some(6)
+                },
+            }
+        );
+        assert_eq!(input[0], 0xe3);
+        let mut input = PYC_27;
+        let header = read_pyc_header(&mut input).unwrap();
+        assert_eq!(header.version, MarshalVersion::Py27);
+        assert_eq!(
+            header.validation,
+            PycValidation::Timestamp {
+                mtime: 0x6ad2_db79,
+                source_size: None
+            }
+        );
+        assert_eq!(input[0], b'c');
+
+        // Compiled with PycInvalidationMode.CHECKED_HASH and UNCHECKED_HASH
+        let source_hash = *b"\x15\x06\xf0\x8f2\xbf?\xf3";
+        for (flags, checked) in &[(3, true), (1, false)] {
+            let input = [&b"U\r\r\n"[..], &[*flags, 0, 0, 0], &source_hash].concat();
+            assert_eq!(
+                read_pyc_header(&input[..]).unwrap().validation,
+                PycValidation::Hash {
+                    source_hash,
+                    checked: *checked
+                }
+            );
+        }
+        // Unknown flags, or checked without being hash-based
+        for &flags in &[2, 4] {
+            let input = [&b"U\r\r\n"[..], &[flags, 0, 0, 0], &source_hash].concat();
+            match read_pyc_header(&input[..]).unwrap_err() {
+                Error::InvalidPycHeader => {}
+                _ => panic!(),
+            }
+        }
+
+        match read_pyc_header(&b"\x00\x01\r\n"[..]).unwrap_err() {
+            Error::UnknownMagic { magic: 256 } => {}
+            _ => panic!(),
+        }
+        match read_pyc_header(&b"U\r\n\r"[..]).unwrap_err() {
+            Error::InvalidPycHeader => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_marshal_load_pyc_path() {
+        let arena = ObjArena::new();
+        for (name, pyc) in &[("py_marshal_test_38.pyc", PYC_38), ("py_marshal_test_27.pyc", PYC_27)] {
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, pyc).unwrap();
+            let result = marshal_load_pyc_path(&arena, &path);
+            std::fs::remove_file(&path).unwrap();
+            let code = result.unwrap().extract_code().unwrap();
+            assert_eq!(code.name, "<module>");
+            assert_eq!(code.filename, "m.py");
+            assert_eq!(code.names, ["x"]);
+        }
+    }
+
+    #[test]
+    fn test_write_pyc() {
+        let arena = ObjArena::new();
+        let timestamp = PycValidation::Timestamp {
+            mtime: 0x6ad2_db79,
+            source_size: Some(6),
+        };
+        let py27_timestamp = PycValidation::Timestamp {
+            mtime: 0x6ad2_db79,
+            source_size: None,
+        };
+        let hash = PycValidation::Hash {
+            source_hash: *b"\x15\x06\xf0\x8f2\xbf?\xf3",
+            checked: true,
+        };
+        for &(pyc, validation) in &[(PYC_38, timestamp), (PYC_27, py27_timestamp), (PYC_38, hash)] {
+            let mut input = pyc;
+            let header = read_pyc_header(&mut input).unwrap();
+            let opts = MarshalLoadExOptions::for_version(header.version);
+            let code = marshal_load_ex(&arena, input, opts).unwrap().extract_code().unwrap();
+
+            let mut out = Vec::new();
+            write_pyc(&mut out, header.magic, validation, code).unwrap();
+            let mut output = &out[..];
+            let written = read_pyc_header(&mut output).unwrap();
+            assert_eq!(written, PycHeader { validation, ..header });
+            assert_eq!(marshal_load_ex(&arena, output, opts).unwrap(), Obj::Code(code));
+        }
+        // Python 2.7 did no better than a timestamp
+        let code = crate::CodeBuilder::new().finish();
+        let mut out = Vec::new();
+        match write_pyc(&mut out, 62211, hash, &code).unwrap_err() {
+            WriteError::UnsupportedFieldForVersion { field: "source_hash", .. } => {}
+            err => panic!("{:?}", err),
+        }
+        match write_pyc(&mut out, 3413, py27_timestamp, &code).unwrap_err() {
+            WriteError::MissingFieldForVersion { field: "source_size", .. } => {}
+            err => panic!("{:?}", err),
+        }
+        match write_pyc(&mut out, 256, timestamp, &code).unwrap_err() {
+            WriteError::UnknownMagic { magic: 256 } => {}
+            err => panic!("{:?}", err),
+        }
+    }
+}
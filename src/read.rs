@@ -22,16 +22,82 @@ pub mod errors {
         Unhashable(crate::Obj<'a>),
         #[error("Internal type error for {0:?}")]
         TypeError(crate::Obj<'a>),
+        /// A field of a code object has the wrong type, which usually means it was read with the
+        /// wrong [`MarshalVersion`](crate::MarshalVersion), so the fields before it were misaligned.
+        #[error("Expected {expected} for {field}, got {}", .actual.type_name())]
+        CodeFieldType {
+            field: &'static str,
+            expected: &'static str,
+            actual: crate::Obj<'a>
+        },
+        /// A code object's leading fields are inconsistent, or above
+        /// [`max_code_count`](super::MarshalLoadExOptions::max_code_count), which usually means
+        /// it was read with the wrong [`MarshalVersion`](crate::MarshalVersion), so the fields
+        /// were misaligned.
+        #[error("Implausible {field} ({value}) in a code object; was it written by a Python version other than {version:?}?")]
+        LikelyVersionMismatch {
+            version: crate::MarshalVersion,
+            field: &'static str,
+            value: u32
+        },
         #[error("Invalid reference")]
         InvalidRef,
+        #[error("Unknown code flags: {flags:#x}")]
+        UnknownCodeFlags {
+            flags: u32
+        },
+        #[error("Interned string written without FLAG_REF")]
+        InconsistentInterning,
+        #[error("String or bytes of length {len} exceeds max_item_len ({max})")]
+        ItemTooLong {
+            len: usize,
+            max: usize
+        },
+        #[error("Collection of length {len} exceeds max_collection_len ({max})")]
+        CollectionTooLong {
+            len: usize,
+            max: usize
+        },
+        #[error("Allocation limit exceeded")]
+        AllocLimitExceeded,
+        #[error("Reference limit exceeded")]
+        RefLimitExceeded,
+        #[error("Input exceeds max_total_input ({max} bytes)")]
+        InputTooLarge {
+            max: u64
+        },
+        #[error("{remaining} trailing bytes after the object")]
+        TrailingData {
+            remaining: usize
+        },
+        #[error("Unknown .pyc magic number: {magic}")]
+        UnknownMagic {
+            magic: u16
+        },
+        #[error("Invalid .pyc header")]
+        InvalidPycHeader,
+        /// Only without the `bigint` feature, for ints that don't fit in an `i128`.
+        #[error("Integer too large")]
+        IntegerTooLarge,
+        #[error("Long digit count out of range: {n}")]
+        LongSizeOutOfRange {
+            n: i32
+        },
+        #[error("Length {len} does not fit in usize")]
+        LengthOverflow {
+            len: u32
+        },
         #[error(transparent)]
         Io(#[from] std::io::Error),
         #[error(transparent)]
         Utf8(#[from] std::str::Utf8Error),
         #[error(transparent)] // TODO: Is this redundant?
         StringUtf8(#[from] ::std::string::FromUtf8Error),
-        #[error("Unable to parse float: {0}")]
-        ParseFloat(#[from] std::num::ParseFloatError)
+        #[error("Unable to parse float {raw:?}: {source}")]
+        ParseFloat {
+            source: std::num::ParseFloatError,
+            raw: String
+        },
 
     }
 
@@ -39,28 +105,67 @@ pub mod errors {
 }
 
 use self::errors::*;
-use crate::{utils, Code, CodeFlags, Depth, Obj, ObjHashable, Type};
-use num_bigint::BigInt;
+use crate::{utils, Code, CodeBuilder, CodeFlags, Depth, Int, MarshalVersion, Obj, ObjArena, Type};
 use num_complex::Complex;
 use num_traits::{FromPrimitive, Zero};
 use std::{
-    collections::{HashMap, HashSet},
     convert::TryFrom,
-    io::Read,
+    fs::File,
+    io::{self, Read},
+    path::Path,
     str::FromStr,
+    task::Poll,
 };
 
 struct RFile<'a, R: Read> {
-    arena: &'a super::ObjArena<'a>,
+    arena: &'a ObjArena,
     depth: Depth<'a>,
     readable: R,
+    /// Takes the next `n` bytes straight from `readable` without copying them, if it's an
+    /// in-memory buffer that lives for `'a`; see [`marshal_loads_borrowed`].
+    borrow: Borrow<'a, R>,
     refs: Vec<Obj<'a>>,
-    has_posonlyargcount: bool,
+    /// Python 2.7's separate table of interned strings, indexed by `TYPE_STRINGREF`.
+    strings: Vec<&'a [u8]>,
+    /// Bytes charged against [`MarshalLoadExOptions::max_alloc`] so far.
+    allocated: usize,
+    /// Bytes charged against [`MarshalLoadExOptions::max_total_input`] so far.
+    consumed: u64,
+    /// One entry per type byte read, if [`MarshalLoadExOptions::track_ref_flags`] is set.
+    ref_flags: Option<Vec<bool>>,
+    /// Deepest nesting seen so far, for [`LoadStats::max_depth`].
+    max_depth_reached: usize,
+    /// The unknown type code read with [`MarshalLoadExOptions::recovery`] on, after which
+    /// everything being read is cut short.
+    recovered: Option<u8>,
+    opts: MarshalLoadExOptions,
+}
+
+type Borrow<'a, R> = fn(&mut R, usize) -> Option<&'a [u8]>;
+
+fn never_borrow<'a, R>(_: &mut R, _: usize) -> Option<&'a [u8]> {
+    None
+}
+
+/// Returns `None` if there are fewer than `n` bytes left, so reading falls back to copying
+/// and reports the `UnexpectedEof`.
+fn borrow_slice<'a>(input: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if n > input.len() {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(n);
+    *input = rest;
+    Some(bytes)
 }
 
+// Primitives are read straight from `readable`, with no read-ahead buffer of our own: that would
+// read past the end of the object, and callers rely on the stream being left right after it
+// (e.g. for a `.pyc` followed by other data). Reading from a slice or a `BufReader` already
+// copies from memory, which leaves little for a buffer here to win.
 macro_rules! define_r {
     ($ident:ident -> $ty:ty; $n:literal) => {
         fn $ident<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, $ty> {
+            charge_input(p, $n)?;
             let mut buf: [u8; $n] = [0; $n];
             p.readable.read_exact(&mut buf)?;
             Ok(<$ty>::from_le_bytes(buf))
@@ -71,13 +176,73 @@ macro_rules! define_r {
 define_r! { r_byte      -> u8 ; 1 }
 define_r! { r_short     -> u16; 2 }
 define_r! { r_long      -> u32; 4 }
-define_r! { r_long64    -> u64; 8 }
+// TYPE_INT64 is a signed little-endian 64-bit integer, so it's decoded as i64 directly
+define_r! { r_long64    -> i64; 8 }
 define_r! { r_float_bin -> f64; 8 }
 
+/// Reads a `u32` length prefix as a `usize`, refusing to silently truncate it on platforms
+/// where `usize` is narrower than 32 bits.
+fn r_size<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, usize> {
+    let n = r_long(p)?;
+    usize::try_from(n).map_err(|_| Error::LengthOverflow { len: n })
+}
+
+/// Counts `n` more bytes against `max_total_input`. Called before reading, so that a huge
+/// length is rejected without waiting for input that may never come.
+fn charge_input<'a>(p: &mut RFile<'a, impl Read>, n: usize) -> Result<'a, ()> {
+    p.consumed = p.consumed.saturating_add(n as u64);
+    match p.opts.max_total_input {
+        Some(max) if p.consumed > max => Err(Error::InputTooLarge { max }),
+        _ => Ok(()),
+    }
+}
+
+/// Counts `n` more bytes against `max_alloc`. Called before allocating, so that an oversized
+/// length is rejected without ever being trusted.
+fn charge_alloc<'a>(p: &mut RFile<'a, impl Read>, n: usize) -> Result<'a, ()> {
+    p.allocated = p.allocated.saturating_add(n);
+    match p.opts.max_alloc {
+        Some(max) if p.allocated > max => Err(Error::AllocLimitExceeded),
+        _ => Ok(()),
+    }
+}
+
+fn charge_objs<'a>(p: &mut RFile<'a, impl Read>, n: usize) -> Result<'a, ()> {
+    charge_alloc(p, n.saturating_mul(std::mem::size_of::<Obj>()))
+}
+
+fn check_collection_len<'a>(p: &RFile<'a, impl Read>, len: usize) -> Result<'a, ()> {
+    match p.opts.max_collection_len {
+        Some(max) if len > max => Err(Error::CollectionTooLong { len, max }),
+        _ => Ok(()),
+    }
+}
+
+fn push_ref<'a>(p: &mut RFile<'a, impl Read>, obj: Obj<'a>) -> Result<'a, usize> {
+    if p.opts.max_refs.is_some_and(|max| p.refs.len() >= max) {
+        return Err(Error::RefLimitExceeded);
+    }
+    p.refs.push(obj);
+    Ok(p.refs.len() - 1)
+}
+
 fn r_bytes<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, &'a [u8]> {
-    let buf = p.arena.as_bumpalo().alloc_slice_fill_copy(n, 0);
-    p.readable.read_exact(&mut buf)?;
-    Ok(&*buf)
+    if let Some(max) = p.opts.max_item_len.filter(|&max| n > max) {
+        return Err(Error::ItemTooLong { len: n, max });
+    }
+    charge_input(p, n)?;
+    charge_alloc(p, n)?;
+    if let Some(bytes) = (p.borrow)(&mut p.readable, n) {
+        return Ok(bytes);
+    }
+    // Don't trust `n` enough to allocate it up front: a bogus length should fail with
+    // `UnexpectedEof` once the input runs out, not attempt a multi-gigabyte allocation.
+    let mut buf = Vec::new();
+    (&mut p.readable).take(n as u64).read_to_end(&mut buf)?;
+    if buf.len() != n {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(p.arena.alloc_slice_copy(&buf))
 }
 
 fn r_string<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, &'a str> {
@@ -85,103 +250,197 @@ fn r_string<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, &'a str> {
     Ok(std::str::from_utf8(buf)?)
 }
 
-fn r_float_str<'a>(p: &mut RFile<impl Read>) -> Result<'a, f64> {
+fn r_str_obj<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, Obj<'a>> {
+    if p.opts.validate_strings {
+        Ok(Obj::String(r_string(n, p)?))
+    } else {
+        Ok(Obj::RawString(r_bytes(n, p)?))
+    }
+}
+
+fn r_float_str<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, f64> {
     let n = r_byte(p)?;
     let s = r_string(n as usize, p)?;
-    Ok(f64::from_str(&s)?)
+    if let Some(x) = parse_special_float(s) {
+        return Ok(x);
+    }
+    f64::from_str(s.trim()).map_err(|source| Error::ParseFloat {
+        source,
+        raw: s.to_owned(),
+    })
+}
+
+/// Handles the spellings of infinity and NaN that Python's `float()` accepts, plus the ones
+/// written by old CPython builds using the MSVC runtime (`1.#INF`, `-1.#IND`).
+fn parse_special_float(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (negative, unsigned) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let x = match &*unsigned.to_ascii_lowercase() {
+        "inf" | "infinity" | "1.#inf" => f64::INFINITY,
+        "nan" | "1.#ind" | "1.#qnan" | "1.#snan" => f64::NAN,
+        _ => return None,
+    };
+    // Like Python, keep the sign even for NaN
+    Some(if negative { -x } else { x })
 }
 
-// TODO: test
 /// May misbehave on 16-bit platforms.
-fn r_pylong<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, &'a BigInt> {
+fn r_pylong<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, &'a Int> {
     #[allow(clippy::cast_possible_wrap)]
     let n = r_long(p)? as i32;
     if n == 0 {
-        return Ok(p.arena.alloc(BigInt::zero()));
+        return Ok(p.arena.alloc_bigint(Int::zero()));
     };
-    #[allow(clippy::cast_sign_loss)]
-    let size = n.wrapping_abs() as u32;
-    let mut digits = Vec::<u16>::with_capacity(size as usize);
-    for _ in 0..size {
-        let d = r_short(p)?;
-        if d > (1 << 15) {
-            return Err(Error::DigitOutOfRange { digit: d });
+    // Like CPython, which reads the count as an i32 and requires its absolute value to fit
+    if n == i32::MIN {
+        return Err(Error::LongSizeOutOfRange { n });
+    }
+    let size = n.unsigned_abs();
+    charge_alloc(p, (size as usize).saturating_mul(2))?;
+    // The digits go straight into the result as they're read, so a bogus count runs into the end
+    // of the input rather than a huge allocation, and a real one isn't held in memory twice
+    let mut err = None;
+    let mut last = 0;
+    let digits = (0..size).map_while(|_| match r_short(p) {
+        // Like CPython, which needs each digit to be less than PyLong_MARSHAL_BASE
+        Ok(d) if d >= (1 << 15) => {
+            err = Some(Error::DigitOutOfRange { digit: d });
+            None
+        }
+        Ok(d) => {
+            last = d;
+            Some(d)
         }
-        digits.push(d);
+        Err(e) => {
+            err = Some(e);
+            None
+        }
+    });
+    let x = utils::int_from_pylong_digits(n < 0, digits);
+    if let Some(err) = err {
+        return Err(err);
+    }
+    if last == 0 {
+        return Err(Error::UnnormalizedLong);
     }
-    if digits[(size - 1) as usize] == 0 {
-        return Err(Error::UnnormalizedLong.into());
+    Ok(p.arena.alloc_bigint(x.ok_or(Error::IntegerTooLarge)?))
+}
+
+fn r_code_flags<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, CodeFlags> {
+    let flags = CodeFlags::from_bits_retain(r_long(p)?);
+    if p.opts.reject_unknown_code_flags && flags.unknown_bits() != 0 {
+        return Err(Error::UnknownCodeFlags {
+            flags: flags.bits(),
+        });
     }
-    Ok(p.arena.alloc(BigInt::from_biguint(
-        utils::sign_of(&n),
-        utils::biguint_from_pylong_digits(&digits),
-    )))
+    Ok(flags)
 }
 
+/// How many elements to reserve up front for a collection, whose length comes from the input.
+/// Past this the `Vec` grows as elements are actually read, like in [`r_bytes`].
+const MAX_PREALLOC: usize = 1024;
+
 fn r_vec<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, &'a [Obj<'a>]> {
-    let mut vec = Vec::with_capacity(n);
+    check_collection_len(p, n)?;
+    charge_objs(p, n)?;
+    let mut vec = Vec::with_capacity(n.min(MAX_PREALLOC));
     for _ in 0..n {
         vec.push(r_object_not_null(p)?);
+        if p.recovered.is_some() {
+            break;
+        }
+    }
+    Ok(p.arena.alloc_slice_fill_iter(vec))
+}
+
+fn r_hashable<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, Option<Obj<'a>>> {
+    match r_object(p)? {
+        Some(x) if !x.is_hashable() => Err(Error::Unhashable(x)),
+        x => Ok(x),
     }
-    Ok(p.arena.alloc(vec))
 }
 
 fn r_hashmap<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, &'a [(Obj<'a>, Obj<'a>)]> {
     let mut map = Vec::new();
     loop {
-        match r_object(p)? {
+        match r_hashable(p)? {
             None => break,
-            Some(key) => match r_object(p)? {
-                None => break, // TODO: Can we have key with no value??
-                Some(value) => {
-                    map.push((key, value))
+            Some(key) => {
+                // The value would have come after the unknown object, so it's unknown too
+                if let Some(tag) = p.recovered {
+                    map.push((key, Obj::Unknown { tag }));
+                    break;
                 }
-            },
+                match r_object(p)? {
+                    // Like CPython, drop a key without a value, and end the dict there
+                    None => break,
+                    Some(value) => {
+                        // The length isn't known up front, so check and charge per entry
+                        check_collection_len(p, map.len() + 1)?;
+                        charge_objs(p, 2)?;
+                        map.push((key, value));
+                        if p.recovered.is_some() {
+                            break;
+                        }
+                    }
+                }
+            }
         }
     }
-    Ok(map)
+    Ok(p.arena.alloc_slice_fill_iter(map))
 }
 
-fn r_hashset(n: usize, p: &mut RFile<impl Read>) -> Result<[ObjHashable<'a>]> {
-    let mut set = HashSet::new();
-    r_hashset_into(&mut set, n, p)?;
-    Ok(set)
-}
-fn r_hashset_into(
-    set: &mut HashSet<ObjHashable>,
-    n: usize,
-    p: &mut RFile<impl Read>,
-) -> Result<()> {
+fn r_hashset<'a>(n: usize, p: &mut RFile<'a, impl Read>) -> Result<'a, &'a [Obj<'a>]> {
+    check_collection_len(p, n)?;
+    charge_objs(p, n)?;
+    let mut set = Vec::with_capacity(n.min(MAX_PREALLOC));
     for _ in 0..n {
-        set.insert(
-            ObjHashable::try_from(&r_object_not_null(p)?)
-                .map_err(Error::Unhashable)?,
-        );
+        set.push(r_hashable(p)?.ok_or(Error::UnexpectedNull)?);
+        if p.recovered.is_some() {
+            break;
+        }
     }
-    Ok(())
+    Ok(p.arena.alloc_slice_fill_iter(set))
 }
 
 #[allow(clippy::too_many_lines)]
-fn r_object(p: &mut RFile<impl Read>) -> Result<Option<Obj>> {
+fn r_object<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, Option<Obj<'a>>> {
     let code: u8 = r_byte(p)?;
     let _depth_handle = p
         .depth
         .try_clone()
-        .map_or(Err(Error::RecursionLimitExceeded), Ok)?;
+        .ok_or(Error::RecursionLimitExceeded)?;
+    p.max_depth_reached = p.max_depth_reached.max(p.depth.nesting());
     let (flag, type_) = {
         let flag: bool = (code & Type::FLAG_REF) != 0;
         let type_u8: u8 = code & !Type::FLAG_REF;
-        let type_: Type =
-            Type::from_u8(type_u8).map_or(Err(Error::InvalidType { spec: type_u8 }), Ok)?;
+        let type_: Type = match Type::from_u8(type_u8) {
+            Some(type_) => type_,
+            None => return r_unknown(p, code),
+        };
         (flag, type_)
     };
-    let mut idx: Option<usize> = match type_ {
+    if let Some(ref_flags) = &mut p.ref_flags {
+        ref_flags.push(flag);
+    }
+    let py27 = p.opts.python_version == MarshalVersion::Py27;
+    // Python 2.7 has no FLAG_REF, and tracks interned strings separately
+    if p.opts.strict_interning
+        && !py27
+        && !flag
+        && matches!(type_, Type::Interned | Type::AsciiInterned | Type::ShortAsciiInterned)
+    {
+        return Err(Error::InconsistentInterning);
+    }
+    let idx: Option<usize> = match type_ {
         // R_REF/r_ref_reserve before reading contents
         // See https://github.com/sollyucko/py-marshal/issues/2
         Type::SmallTuple | Type::Tuple | Type::List | Type::Dict | Type::Set | Type::FrozenSet | Type::Code if flag => {
-            let i = p.refs.len();
-            p.refs.push(Obj::None);
-            Some(i)
+            Some(push_ref(p, Obj::None)?)
         }
         _ => None,
     };
@@ -193,9 +452,9 @@ fn r_object(p: &mut RFile<impl Read>) -> Result<Option<Obj>> {
         Type::Ellipsis => Some(Obj::Ellipsis),
         Type::False => Some(Obj::Bool(false)),
         Type::True => Some(Obj::Bool(true)),
-        Type::Int => Some(Obj::Long(Arc::new(BigInt::from(r_long(p)? as i32)))),
-        Type::Int64 => Some(Obj::Long(Arc::new(BigInt::from(r_long64(p)? as i64)))),
-        Type::Long => Some(Obj::Long(Arc::new(r_pylong(p)?))),
+        Type::Int => Some(Obj::Long(p.arena.alloc_bigint(Int::from(r_long(p)? as i32)))),
+        Type::Int64 => Some(Obj::Long(p.arena.alloc_bigint(Int::from(r_long64(p)?)))),
+        Type::Long => Some(Obj::Long(r_pylong(p)?)),
         Type::Float => Some(Obj::Float(r_float_str(p)?)),
         Type::BinaryFloat => Some(Obj::Float(r_float_bin(p)?)),
         Type::Complex => Some(Obj::Complex(Complex {
@@ -206,61 +465,43 @@ fn r_object(p: &mut RFile<impl Read>) -> Result<Option<Obj>> {
             re: r_float_bin(p)?,
             im: r_float_bin(p)?,
         })),
-        Type::String => Some(Obj::Bytes(Arc::new(r_bytes(r_long(p)? as usize, p)?))),
+        Type::String => Some(Obj::Bytes(r_bytes(r_size(p)?, p)?)),
+        // An interned Python 2 `str`, which is still bytes
+        Type::Interned if py27 => {
+            let x = r_bytes(r_size(p)?, p)?;
+            p.strings.push(x);
+            Some(Obj::Bytes(x))
+        }
+        Type::StringRef if py27 => {
+            let n = r_size(p)?;
+            Some(Obj::Bytes(p.strings.get(n).ok_or(Error::InvalidRef)?))
+        }
         Type::AsciiInterned | Type::Ascii | Type::Interned | Type::Unicode => {
-            Some(Obj::String(Arc::new(r_string(r_long(p)? as usize, p)?)))
+            let n = r_size(p)?;
+            Some(r_str_obj(n, p)?)
         }
         Type::ShortAsciiInterned | Type::ShortAscii => {
-            Some(Obj::String(Arc::new(r_string(r_byte(p)? as usize, p)?)))
-        }
-        Type::SmallTuple => Some(Obj::Tuple(Arc::new(r_vec(r_byte(p)? as usize, p)?))),
-        Type::Tuple => Some(Obj::Tuple(Arc::new(r_vec(r_long(p)? as usize, p)?))),
-        Type::List => Some(Obj::List(Arc::new(RwLock::new(r_vec(
-            r_long(p)? as usize,
-            p,
-        )?)))),
-        Type::Set => {
-            let set = Arc::new(RwLock::new(HashSet::new()));
-
-            if flag {
-                idx = Some(p.refs.len());
-                p.refs.push(Obj::Set(Arc::clone(&set)));
-            }
-
-            r_hashset_into(&mut *set.write().unwrap(), r_long(p)? as usize, p)?;
-            Some(Obj::Set(set))
-        }
-        Type::FrozenSet => Some(Obj::FrozenSet(Arc::new(r_hashset(r_long(p)? as usize, p)?))),
-        Type::Dict => Some(Obj::Dict(Arc::new(RwLock::new(r_hashmap(p)?)))),
-        Type::Code => Some(Obj::Code(Arc::new(Code {
-            argcount: r_long(p)?,
-            posonlyargcount: if p.has_posonlyargcount { r_long(p)? } else { 0 },
-            kwonlyargcount: r_long(p)?,
-            nlocals: r_long(p)?,
-            stacksize: r_long(p)?,
-            flags: CodeFlags::from_bits_truncate(r_long(p)?),
-            code: r_object_extract_bytes(p)?,
-            consts: r_object_extract_tuple(p)?,
-            names: r_object_extract_tuple_string(p)?,
-            varnames: r_object_extract_tuple_string(p)?,
-            freevars: r_object_extract_tuple_string(p)?,
-            cellvars: r_object_extract_tuple_string(p)?,
-            filename: r_object_extract_string(p)?,
-            name: r_object_extract_string(p)?,
-            firstlineno: r_long(p)?,
-            lnotab: r_object_extract_bytes(p)?,
-        }))),
+            let n = r_byte(p)? as usize;
+            Some(r_str_obj(n, p)?)
+        }
+        Type::SmallTuple => Some(Obj::Tuple(r_vec(r_byte(p)? as usize, p)?)),
+        Type::Tuple => Some(Obj::Tuple(r_vec(r_size(p)?, p)?)),
+        Type::List => Some(Obj::List(r_vec(r_size(p)?, p)?)),
+        Type::Set => Some(Obj::Set(r_hashset(r_size(p)?, p)?)),
+        Type::FrozenSet => Some(Obj::FrozenSet(r_hashset(r_size(p)?, p)?)),
+        Type::Dict => Some(Obj::Dict(r_hashmap(p)?)),
+        Type::Code => Some(Obj::Code(p.arena.alloc(r_code(p)?))),
 
         Type::Ref => {
-            let n = r_long(p)? as usize;
-            let result = p.refs.get(n).ok_or(Error::InvalidRef)?.clone();
+            let n = r_size(p)?;
+            let result = *p.refs.get(n).ok_or(Error::InvalidRef)?;
             if result.is_none() {
-                return Err(Error::InvalidRef.into());
+                return Err(Error::InvalidRef);
             } else {
                 Some(result)
             }
         }
-        Type::Unknown => return Err(Error::InvalidType { spec: Type::Unknown as u8 }.into()),
+        Type::Unknown | Type::StringRef => return r_unknown(p, code),
     };
     match (&retval, idx) {
         (None, _)
@@ -269,101 +510,919 @@ fn r_object(p: &mut RFile<impl Read>) -> Result<Option<Obj>> {
         | (Some(Obj::Ellipsis), _)
         | (Some(Obj::Bool(_)), _) => {}
         (Some(x), Some(i)) if flag => {
-            p.refs[i] = x.clone();
+            p.refs[i] = *x;
         }
         (Some(x), None) if flag => {
-            p.refs.push(x.clone());
+            push_ref(p, *x)?;
         }
         (Some(_), _) => {}
     };
     Ok(retval)
 }
 
-fn r_object_not_null(p: &mut RFile<impl Read>) -> Result<Obj> {
-    Ok(r_object(p)?.ok_or(Error::UnexpectedNull)?)
+/// There's no telling how long an unknown object is, so with `recovery` on, this is where
+/// reading stops.
+fn r_unknown<'a>(p: &mut RFile<'a, impl Read>, code: u8) -> Result<'a, Option<Obj<'a>>> {
+    if !p.opts.recovery {
+        return Err(Error::InvalidType {
+            spec: code & !Type::FLAG_REF,
+        });
+    }
+    p.recovered = Some(code);
+    Ok(Some(Obj::Unknown { tag: code }))
+}
+
+fn r_object_not_null<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, Obj<'a>> {
+    r_object(p)?.ok_or(Error::UnexpectedNull)
 }
-fn r_object_extract_string(p: &mut RFile<impl Read>) -> Result<Arc<String>> {
-    r_object_not_null(p)?
-        .extract_string()
-        .map_err(Error::TypeError)
+fn code_field_type<'a>(field: &'static str, expected: &'static str) -> impl Fn(Obj<'a>) -> Error<'a> {
+    move |actual| Error::CodeFieldType {
+        field,
+        expected,
+        actual,
+    }
 }
-fn r_object_extract_bytes(p: &mut RFile<impl Read>) -> Result<Arc<Vec<u8>>> {
-    Ok(r_object_not_null(p)?
-        .extract_bytes()
-        .map_err(Error::TypeError)?)
+// With `recovery`, a field whose type code is unknown is left empty
+fn r_object_extract_bytes<'a>(p: &mut RFile<'a, impl Read>, field: &'static str) -> Result<'a, &'a [u8]> {
+    match r_object_not_null(p)? {
+        Obj::Unknown { .. } => Ok(&[]),
+        x => x.extract_bytes().map_err(code_field_type(field, "bytes")),
+    }
 }
-fn r_object_extract_tuple(p: &mut RFile<impl Read>) -> Result<Arc<Vec<Obj>>> {
-    Ok(r_object_not_null(p)?
-        .extract_tuple()
-        .map_err(Error::TypeError)?)
+fn r_object_extract_tuple<'a>(p: &mut RFile<'a, impl Read>, field: &'static str) -> Result<'a, &'a [Obj<'a>]> {
+    match r_object_not_null(p)? {
+        Obj::Unknown { .. } => Ok(&[]),
+        x => x.extract_tuple().map_err(code_field_type(field, "tuple")),
+    }
 }
-fn r_object_extract_tuple_string(p: &mut RFile<impl Read>) -> Result<Vec<Arc<String>>> {
-    Ok(r_object_extract_tuple(p)?
+fn r_object_extract_tuple_string<'a>(p: &mut RFile<'a, impl Read>, field: &'static str) -> Result<'a, &'a [&'a str]> {
+    let py27 = p.opts.python_version == MarshalVersion::Py27;
+    let strings = r_object_extract_tuple(p, field)?
         .iter()
-        .map(|x| {
-            x.clone()
-                .extract_string()
-                .map_err(Error::TypeError)
-        })
-        .collect::<Result<Vec<Arc<String>>>>()?)
+        .filter(|x| !matches!(x, Obj::Unknown { .. }))
+        .map(|&x| extract_name(x, py27).map_err(|err| in_field(err, field, "tuple of str")))
+        .collect::<Result<Vec<&str>>>()?;
+    Ok(p.arena.alloc_slice_copy(&strings))
+}
+fn r_object_extract_name<'a>(p: &mut RFile<'a, impl Read>, field: &'static str) -> Result<'a, &'a str> {
+    let py27 = p.opts.python_version == MarshalVersion::Py27;
+    match r_object_not_null(p)? {
+        Obj::Unknown { .. } => Ok(""),
+        x => extract_name(x, py27).map_err(|err| in_field(err, field, "str")),
+    }
+}
+/// Python 2 names and filenames are `str`, which is read as bytes.
+fn extract_name<'a>(x: Obj<'a>, py27: bool) -> Result<'a, &'a str> {
+    match x {
+        Obj::Bytes(x) if py27 => Ok(std::str::from_utf8(x)?),
+        Obj::RawString(x) => Ok(std::str::from_utf8(x)?),
+        x => x.extract_string().map_err(Error::TypeError),
+    }
+}
+/// Says which code object field an [`extract_name`] error came from.
+fn in_field<'a>(err: Error<'a>, field: &'static str, expected: &'static str) -> Error<'a> {
+    match err {
+        Error::TypeError(actual) => code_field_type(field, expected)(actual),
+        err => err,
+    }
+}
+
+fn r_code<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, Code<'a>> {
+    let py27 = p.opts.python_version == MarshalVersion::Py27;
+    let py311 = p.opts.python_version >= MarshalVersion::Py311;
+    let argcount = r_long(p)?;
+    // Python 2.7 has neither keyword-only nor positional-only arguments
+    let posonlyargcount = if p.opts.has_posonlyargcount && !py27 { r_long(p)? } else { 0 };
+    let kwonlyargcount = if py27 { 0 } else { r_long(p)? };
+    // Python 3.11 dropped co_nlocals, since it's the number of locals in co_localsplusnames
+    let nlocals = if py311 { 0 } else { r_long(p)? };
+    let stacksize = r_long(p)?;
+    let flags = r_code_flags(p)?;
+    check_code_header(p, &[
+        ("co_argcount", argcount),
+        ("co_posonlyargcount", posonlyargcount),
+        ("co_kwonlyargcount", kwonlyargcount),
+        ("co_nlocals", nlocals),
+        ("co_stacksize", stacksize),
+    ])?;
+    if posonlyargcount > argcount {
+        // co_argcount includes the positional-only arguments
+        return Err(version_mismatch(p, "co_posonlyargcount", posonlyargcount));
+    }
+    if flags.bits() & RESERVED_CODE_FLAGS != 0 {
+        return Err(version_mismatch(p, "co_flags", flags.bits()));
+    }
+    let mut code = Code {
+        argcount,
+        posonlyargcount,
+        kwonlyargcount,
+        nlocals,
+        stacksize,
+        flags,
+        ..CodeBuilder::new().finish()
+    };
+    // With `recovery`, an unknown type code ends the code object early
+    macro_rules! fields {
+        ($($field:ident: $read:expr,)*) => {$(
+            if p.recovered.is_some() {
+                return Ok(code);
+            }
+            code.$field = $read;
+        )*};
+    }
+    if py311 {
+        fields! {
+            code: r_object_extract_bytes(p, "co_code")?,
+            consts: r_object_extract_tuple(p, "co_consts")?,
+            names: r_object_extract_tuple_string(p, "co_names")?,
+        }
+        let localsplusnames = r_object_extract_tuple_string(p, "co_localsplusnames")?;
+        if p.recovered.is_some() {
+            return Ok(code);
+        }
+        let localspluskinds = r_object_extract_bytes(p, "co_localspluskinds")?;
+        if p.recovered.is_none() {
+            split_localsplus(p, &mut code, localsplusnames, localspluskinds)?;
+        }
+        fields! {
+            filename: r_object_extract_name(p, "co_filename")?,
+            name: r_object_extract_name(p, "co_name")?,
+            qualname: r_object_extract_name(p, "co_qualname")?,
+            firstlineno: r_long(p)?,
+            lnotab: r_object_extract_bytes(p, "co_linetable")?,
+            exceptiontable: r_object_extract_bytes(p, "co_exceptiontable")?,
+        }
+        return Ok(code);
+    }
+    fields! {
+        code: r_object_extract_bytes(p, "co_code")?,
+        consts: r_object_extract_tuple(p, "co_consts")?,
+        names: r_object_extract_tuple_string(p, "co_names")?,
+        varnames: r_object_extract_tuple_string(p, "co_varnames")?,
+        freevars: r_object_extract_tuple_string(p, "co_freevars")?,
+        cellvars: r_object_extract_tuple_string(p, "co_cellvars")?,
+        filename: r_object_extract_name(p, "co_filename")?,
+        name: r_object_extract_name(p, "co_name")?,
+        firstlineno: r_long(p)?,
+        lnotab: r_object_extract_bytes(p, "co_lnotab")?,
+    }
+    Ok(code)
+}
+
+// `co_localspluskinds` bits, from Python 3.11 on. They describe a variable, so they aren't
+// `CodeFlags`.
+pub(crate) const CO_FAST_LOCAL: u8 = 0x20;
+pub(crate) const CO_FAST_CELL: u8 = 0x40;
+pub(crate) const CO_FAST_FREE: u8 = 0x80;
+
+/// Python 3.11 keeps locals, cell and free variables in one tuple, with a byte of
+/// `CO_FAST_*` flags for each; an argument that's also a cell is only listed once.
+fn split_localsplus<'a>(
+    p: &RFile<'a, impl Read>,
+    code: &mut Code<'a>,
+    names: &'a [&'a str],
+    kinds: &[u8],
+) -> Result<'a, ()> {
+    if names.len() != kinds.len() {
+        let len = u32::try_from(kinds.len()).unwrap_or(u32::MAX);
+        return Err(version_mismatch(p, "co_localspluskinds", len));
+    }
+    let of_kind = |kind: u8| -> &'a [&'a str] {
+        let names: Vec<_> = names
+            .iter()
+            .zip(kinds)
+            .filter(|&(_, k)| k & kind != 0)
+            .map(|(&x, _)| x)
+            .collect();
+        p.arena.alloc_slice_copy(&names)
+    };
+    code.varnames = of_kind(CO_FAST_LOCAL);
+    code.cellvars = of_kind(CO_FAST_CELL);
+    code.freevars = of_kind(CO_FAST_FREE);
+    code.nlocals = u32::try_from(code.varnames.len()).unwrap_or(u32::MAX);
+    Ok(())
+}
+
+/// `co_flags` bits that no version of CPython has used.
+const RESERVED_CODE_FLAGS: u32 = 0xe000_0000;
+
+fn check_code_header<'a>(p: &RFile<'a, impl Read>, counts: &[(&'static str, u32)]) -> Result<'a, ()> {
+    let max = match p.opts.max_code_count {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    match counts.iter().find(|&&(_, value)| value > max) {
+        Some(&(field, value)) => Err(version_mismatch(p, field, value)),
+        None => Ok(()),
+    }
 }
 
-fn read_object(p: &mut RFile<impl Read>) -> Result<Obj> {
+fn version_mismatch<'a>(p: &RFile<'a, impl Read>, field: &'static str, value: u32) -> Error<'a> {
+    Error::LikelyVersionMismatch {
+        version: p.opts.python_version,
+        field,
+        value,
+    }
+}
+
+fn read_object<'a>(p: &mut RFile<'a, impl Read>) -> Result<'a, Obj<'a>> {
     r_object_not_null(p)
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct MarshalLoadExOptions {
+    /// Python version that wrote the data. Apart from `has_posonlyargcount`, only
+    /// [`MarshalVersion::Py27`] and 3.11+ change how it's read. Code objects use the 2.7
+    /// layout, or the 3.11 one (the same in 3.12), whose `co_localsplusnames` is split into
+    /// `varnames`, `cellvars` and `freevars`. Python 2.7's interned strings are `str`s, read as
+    /// [`Obj::Bytes`] like `TYPE_STRING`.
+    pub python_version: MarshalVersion,
     pub has_posonlyargcount: bool,
+    /// Return [`Error::UnknownCodeFlags`] instead of keeping `co_flags` bits that
+    /// [`CodeFlags`] doesn't model.
+    pub reject_unknown_code_flags: bool,
+    /// Deepest nesting accepted before [`Error::RecursionLimitExceeded`].
+    pub max_depth: usize,
+    /// Byte budget for strings, bytes, long digits and container slots; exceeding it returns
+    /// [`Error::AllocLimitExceeded`].
+    pub max_alloc: Option<usize>,
+    /// Return [`Error::InconsistentInterning`] for interned strings written without `FLAG_REF`.
+    /// CPython (marshal version 3 and later) always puts interned strings in the ref table,
+    /// so anything else means the ref indices won't line up when re-serialized.
+    pub strict_interning: bool,
+    /// Record which objects had `FLAG_REF` set; see [`marshal_load_tracked`].
+    pub track_ref_flags: bool,
+    /// Longest string or bytes object accepted before [`Error::ItemTooLong`].
+    pub max_item_len: Option<usize>,
+    /// Most elements (or dict entries) accepted in one tuple, list, set or dict before
+    /// [`Error::CollectionTooLong`].
+    pub max_collection_len: Option<usize>,
+    /// Most entries allowed in the ref table before [`Error::RefLimitExceeded`]. Slots reserved
+    /// for containers that are still being read count too.
+    pub max_refs: Option<usize>,
+    /// Return [`Error::TrailingData`] if anything is left in the input after the top-level
+    /// object, which catches truncated or concatenated files. Not compatible with
+    /// [`marshal_load_sequence`] or [`marshal_load_remaining`], which expect more data.
+    pub require_eof: bool,
+    /// Check that strs are UTF-8 as they're read. Turning this off reads them as
+    /// [`Obj::RawString`] instead, which is faster when most strings are never looked at, e.g.
+    /// when scanning a corpus for code objects; [`Obj::extract_string`] validates them on
+    /// demand. Code object names are always validated.
+    pub validate_strings: bool,
+    /// Most bytes of input read for one object before [`Error::InputTooLarge`]. Lengths are
+    /// checked before their contents are read, so a peer on a socket can't announce a huge
+    /// string and then keep the reader waiting for it (use a read timeout against one that
+    /// just stops sending).
+    pub max_total_input: Option<u64>,
+    /// Instead of failing with [`Error::InvalidType`], read an unknown type code as
+    /// [`Obj::Unknown`] and stop there: with no way to tell how long its payload is, nothing
+    /// after it can be read. Containers it's in are cut short, ending with it, and code objects
+    /// keep the [`CodeBuilder`](crate::CodeBuilder) defaults for their remaining fields. This
+    /// is for salvaging what comes before a corrupt byte, e.g. in a damaged `.pyc`.
+    pub recovery: bool,
+    /// Largest `co_argcount`, `co_nlocals`, `co_stacksize` or other count accepted in a code
+    /// object before [`Error::LikelyVersionMismatch`]. CPython has no such limit, so it's off by
+    /// default, but real code stays far below [`PLAUSIBLE_CODE_COUNT`], while a field misaligned
+    /// by reading with the wrong version usually holds part of a type code and a length.
+    pub max_code_count: Option<u32>,
 }
-/// Assume latest version
+
+/// A [`max_code_count`](MarshalLoadExOptions::max_code_count) that no real code object
+/// reaches, used by [`MarshalLoadExOptions::untrusted`] and [`MarshalLoadExOptions::fuzz_safe`].
+pub const PLAUSIBLE_CODE_COUNT: u32 = 0xffff;
+/// Reads data from Python 3.8 through 3.10. That's a compatibility default, kept when newer
+/// versions were added: 3.11 changed the code object layout, so it would misread older `.pyc`
+/// files. Use [`MarshalLoadExOptions::for_version`] for anything else.
 impl Default for MarshalLoadExOptions {
     fn default() -> Self {
         Self {
+            python_version: MarshalVersion::Py310,
             has_posonlyargcount: true,
+            reject_unknown_code_flags: false,
+            max_depth: 900,
+            max_alloc: None,
+            max_item_len: None,
+            max_collection_len: None,
+            strict_interning: false,
+            track_ref_flags: false,
+            max_refs: None,
+            require_eof: false,
+            validate_strings: true,
+            max_total_input: None,
+            recovery: false,
+            max_code_count: None,
+        }
+    }
+}
+impl MarshalLoadExOptions {
+    /// The defaults, adjusted for data written by `version`.
+    #[must_use]
+    pub fn for_version(version: MarshalVersion) -> Self {
+        Self {
+            python_version: version,
+            has_posonlyargcount: version >= MarshalVersion::Py38,
+            ..Self::default()
+        }
+    }
+
+    /// Use this for anything you didn't produce yourself, e.g. data from the network or an
+    /// upload. The limits leave room for any real module, but keep a hostile input to tens of
+    /// megabytes and shallow recursion, and trailing data is rejected.
+    ///
+    /// Strs aren't checked to be UTF-8 while reading ([`validate_strings`](Self::validate_strings)
+    /// is off), so a bad one doesn't fail the whole load: it's read as [`Obj::RawString`], which
+    /// displays lossily and is validated if it's extracted.
+    ///
+    /// [`Default`] stays permissive, for data you trust, like `.pyc` files you compiled.
+    #[must_use]
+    pub fn untrusted() -> Self {
+        Self {
+            max_depth: 100,
+            max_alloc: Some(64 << 20),
+            max_total_input: Some(64 << 20),
+            max_refs: Some(1 << 20),
+            max_collection_len: Some(1 << 20),
+            require_eof: true,
+            validate_strings: false,
+            max_code_count: Some(PLAUSIBLE_CODE_COUNT),
+            ..Self::default()
+        }
+    }
+
+    /// Tight limits for feeding arbitrary bytes to the reader, e.g. from a fuzzer: any input is
+    /// rejected before it can use more than about a megabyte or recurse deeply.
+    #[must_use]
+    pub fn fuzz_safe() -> Self {
+        Self {
+            max_depth: 100,
+            max_alloc: Some(1 << 20),
+            max_refs: Some(1 << 12),
+            max_code_count: Some(PLAUSIBLE_CODE_COUNT),
+            ..Self::default()
         }
     }
 }
 
 /// # Errors
 /// See [`ErrorKind`].
-pub fn marshal_load_ex(readable: impl Read, opts: MarshalLoadExOptions) -> Result<Obj> {
+pub fn marshal_load_ex<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, Obj<'a>> {
+    marshal_load_tracked(arena, readable, opts).map(|(obj, _)| obj)
+}
+
+/// Like [`marshal_load_ex`], but also returns whether each object in the stream was written
+/// with `FLAG_REF`, if [`track_ref_flags`](MarshalLoadExOptions::track_ref_flags) is set.
+///
+/// There's one entry per type byte, in stream order: nested objects, refs and the `NULL`
+/// terminating a dict all count. This is what a re-serializer needs to reproduce CPython's
+/// choice of which objects to dedupe.
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_load_tracked<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, (Obj<'a>, Option<Vec<bool>>)> {
+    load(arena, readable, never_borrow, None, opts).map(|(obj, ref_flags, _)| (obj, ref_flags))
+}
+
+/// Like [`marshal_load_ex`], but also returns how many bytes the object took up, e.g. to find
+/// where the next of several concatenated objects starts, or to check that a `.pyc` file has
+/// nothing after its code object.
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_load_counted<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, (Obj<'a>, u64)> {
+    load(arena, readable, never_borrow, None, opts).map(|(obj, _, stats)| (obj, stats.consumed))
+}
+
+/// Measurements of a successful load, for choosing limits that real inputs fit in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Deepest nesting reached, counting the top-level object as 1. This is the smallest
+    /// [`max_depth`](MarshalLoadExOptions::max_depth) that accepts the input.
+    pub max_depth: usize,
+    /// Entries in the ref table; compare with [`max_refs`](MarshalLoadExOptions::max_refs).
+    pub refs: usize,
+    /// Bytes counted against [`max_alloc`](MarshalLoadExOptions::max_alloc).
+    pub allocated: usize,
+    /// Bytes of input making up the object, which is also what's counted against
+    /// [`max_total_input`](MarshalLoadExOptions::max_total_input).
+    pub consumed: u64,
+}
+
+/// Like [`marshal_load_ex`], but also returns [`LoadStats`].
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_load_with_stats<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, (Obj<'a>, LoadStats)> {
+    load(arena, readable, never_borrow, None, opts).map(|(obj, _, stats)| (obj, stats))
+}
+
+/// Starts from the ref table in `shared_refs`, if given, and puts it back with the new refs
+/// added, or as it was if there's an error.
+#[allow(clippy::type_complexity)]
+fn load<'a, R: Read>(
+    arena: &'a ObjArena,
+    readable: R,
+    borrow: Borrow<'a, R>,
+    mut shared_refs: Option<&mut Vec<Obj<'a>>>,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, (Obj<'a>, Option<Vec<bool>>, LoadStats)> {
+    let refs = shared_refs.as_deref_mut().map(std::mem::take).unwrap_or_default();
+    let seeded = refs.len();
     let mut rf = RFile {
-        depth: Depth::new(),
+        arena,
+        depth: Depth::new(arena, opts.max_depth),
         readable,
-        refs: Vec::<Obj>::new(),
-        has_posonlyargcount: opts.has_posonlyargcount,
+        borrow,
+        refs,
+        strings: Vec::new(),
+        allocated: 0,
+        consumed: 0,
+        ref_flags: if opts.track_ref_flags { Some(Vec::new()) } else { None },
+        max_depth_reached: 0,
+        recovered: None,
+        opts,
+    };
+    let result = read_top_level(&mut rf);
+    let refs = rf.refs.len();
+    if let Some(shared_refs) = shared_refs {
+        if result.is_err() {
+            rf.refs.truncate(seeded);
+        }
+        *shared_refs = std::mem::take(&mut rf.refs);
+    }
+    let obj = result?;
+    let stats = LoadStats {
+        max_depth: rf.max_depth_reached,
+        refs,
+        allocated: rf.allocated,
+        consumed: rf.consumed,
     };
-    read_object(&mut rf)
+    Ok((obj, rf.ref_flags, stats))
+}
+
+fn read_top_level<'a>(rf: &mut RFile<'a, impl Read>) -> Result<'a, Obj<'a>> {
+    let obj = read_object(rf)?;
+    // Whatever came after an unknown type code is still there, but can't be read
+    if rf.opts.require_eof && rf.recovered.is_none() {
+        // Only as much as max_total_input allows, plus a byte to tell if there's more
+        let budget = rf
+            .opts
+            .max_total_input
+            .map_or(u64::MAX, |max| max.saturating_sub(rf.consumed).saturating_add(1));
+        let remaining = io::copy(&mut (&mut rf.readable).take(budget), &mut io::sink())?;
+        if remaining != 0 {
+            return Err(Error::TrailingData {
+                remaining: usize::try_from(remaining).unwrap_or(usize::MAX),
+            });
+        }
+    }
+    Ok(obj)
+}
+
+/// Like [`marshal_load_ex`], but the ref table starts out as `refs` instead of empty, and the
+/// object's own refs are appended to it, so a `TYPE_REF` can point at an object from an earlier
+/// load that used the same table. This is for custom formats that write several objects
+/// against one ref table, e.g. a bundle whose later entries refer back to constants in the
+/// first. CPython never writes such data: every `marshal.dumps` call and `.pyc` file has a
+/// table of its own, which is what the other loaders assume.
+///
+/// Indices count from the start of `refs`, and [`max_refs`](MarshalLoadExOptions::max_refs)
+/// counts the entries that were already there. On error, `refs` is left as it was.
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_load_shared_refs<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+    refs: &mut Vec<Obj<'a>>,
+) -> Result<'a, Obj<'a>> {
+    load(arena, readable, never_borrow, Some(refs), opts).map(|(obj, _, _)| obj)
+}
+
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_load<'a>(arena: &'a ObjArena, readable: impl Read) -> Result<'a, Obj<'a>> {
+    marshal_load_ex(arena, readable, MarshalLoadExOptions::default())
 }
 
+/// Like [`marshal_load_ex`], but wraps `readable` in a [`BufReader`](io::BufReader) first.
+///
+/// The reader pulls single bytes at a time, so passing an unbuffered source such as a
+/// [`File`](std::fs::File) directly to [`marshal_load`] costs a syscall per byte. Prefer this
+/// (or [`marshal_loads`] on an in-memory buffer) for files. Since the buffer may read ahead,
+/// the position of `readable` afterwards is unspecified.
 /// # Errors
 /// See [`ErrorKind`].
-pub fn marshal_load(readable: impl Read) -> Result<Obj> {
-    marshal_load_ex(readable, MarshalLoadExOptions::default())
+pub fn marshal_load_buffered<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, Obj<'a>> {
+    marshal_load_ex(arena, io::BufReader::with_capacity(64 * 1024, readable), opts)
+}
+
+/// Opens and reads the file at `path`, buffered. The file must contain just the marshalled data;
+/// see [`marshal_load_pyc_path`](crate::pyc::marshal_load_pyc_path) for `.pyc` files.
+/// # Errors
+/// See [`ErrorKind`]. Failing to open the file is an [`Error::Io`].
+pub fn marshal_load_path<'a>(
+    arena: &'a ObjArena,
+    path: impl AsRef<Path>,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, Obj<'a>> {
+    marshal_load_buffered(arena, File::open(path)?, opts)
+}
+
+/// Like [`marshal_load_ex`], for input whose top-level object must be a code object, like the
+/// body of a `.pyc` file.
+/// # Errors
+/// See [`ErrorKind`]. Any other object is an [`Error::TypeError`].
+pub fn marshal_load_code<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, &'a Code<'a>> {
+    marshal_load_ex(arena, readable, opts)?
+        .extract_code()
+        .map_err(Error::TypeError)
+}
+
+/// Like [`marshal_load_ex`], for input whose top-level object must be a dict.
+/// # Errors
+/// See [`ErrorKind`]. Any other object is an [`Error::TypeError`].
+pub fn marshal_load_dict<'a>(
+    arena: &'a ObjArena,
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+) -> Result<'a, &'a [(Obj<'a>, Obj<'a>)]> {
+    marshal_load_ex(arena, readable, opts)?
+        .extract_dict()
+        .map_err(Error::TypeError)
+}
+
+/// Reads one object from the front of `input` and advances it past the consumed bytes, so
+/// objects concatenated in a buffer can be read one after another.
+/// # Errors
+/// See [`ErrorKind`]. On error, `input` is left somewhere inside the offending object.
+pub fn marshal_load_remaining<'a>(
+    arena: &'a ObjArena,
+    input: &mut &[u8],
+    opts: MarshalLoadExOptions,
+) -> Result<'a, Obj<'a>> {
+    marshal_load_ex(arena, input, opts)
 }
 
 /// Allows coercion from array reference to slice.
 /// # Errors
 /// See [`ErrorKind`].
-pub fn marshal_loads(bytes: &[u8]) -> Result<Obj> {
-    marshal_load(bytes)
+pub fn marshal_loads<'a>(arena: &'a ObjArena, bytes: &[u8]) -> Result<'a, Obj<'a>> {
+    marshal_load(arena, bytes)
+}
+
+/// Like [`marshal_load_ex`] on an in-memory buffer, but strings and bytes point into `bytes`
+/// instead of being copied into the arena, which saves memory on large constants, e.g. when
+/// analysing a memory-mapped `.pyc` file. Everything else is still allocated in `arena`.
+///
+/// Strings are still checked to be UTF-8, unless
+/// [`validate_strings`](MarshalLoadExOptions::validate_strings) is off.
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_loads_borrowed<'a>(
+    arena: &'a ObjArena,
+    bytes: &'a [u8],
+    opts: MarshalLoadExOptions,
+) -> Result<'a, Obj<'a>> {
+    load(arena, bytes, borrow_slice, None, opts).map(|(obj, _, _)| obj)
+}
+
+/// Iterator over several marshalled objects written back-to-back, as returned by
+/// [`marshal_load_sequence`].
+pub struct MarshalLoadSequence<'a, R: Read> {
+    arena: &'a ObjArena,
+    readable: R,
+    opts: MarshalLoadExOptions,
+    done: bool,
+}
+impl<'a, R: Read> Iterator for MarshalLoadSequence<'a, R> {
+    type Item = Result<'a, Obj<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        // Only an EOF before the type byte of the next object ends the sequence cleanly.
+        let mut code = [0_u8];
+        loop {
+            match self.readable.read(&mut code) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+        let result = marshal_load_ex(self.arena, (&code[..]).chain(&mut self.readable), self.opts);
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// Reads objects until `readable` is exhausted, each with its own ref table (as if written by
+/// separate `marshal.dumps` calls). Iteration stops after the first error; an EOF in the middle
+/// of an object is reported as an error.
+pub fn marshal_load_sequence<'a, R: Read>(
+    arena: &'a ObjArena,
+    readable: R,
+    opts: MarshalLoadExOptions,
+) -> MarshalLoadSequence<'a, R> {
+    MarshalLoadSequence {
+        arena,
+        readable,
+        opts,
+        done: false,
+    }
+}
+
+/// Parser for input that arrives in chunks, e.g. from a socket, as returned by
+/// [`marshal_load_incremental`].
+///
+/// This is the simple version: chunks are buffered until they hold a whole object, and the
+/// buffer is parsed from the start on every [`push_bytes`](Self::push_bytes). That's quadratic in
+/// the number of chunks an object is split into, so feed it reasonably large chunks.
+pub struct MarshalLoadIncremental<'a> {
+    arena: &'a ObjArena,
+    buf: Vec<u8>,
+    opts: MarshalLoadExOptions,
+}
+impl<'a> MarshalLoadIncremental<'a> {
+    /// Appends `bytes` to the buffer, and reads an object if there's a whole one.
+    ///
+    /// Returns [`Poll::Pending`] if more input is needed. Any bytes after the object are kept, so
+    /// objects written back-to-back can be read by calling this again (with an empty slice if
+    /// there's no new input). After an error, the buffer is left somewhere inside the offending
+    /// object.
+    /// # Errors
+    /// See [`ErrorKind`]. An EOF inside the object isn't an error, just [`Poll::Pending`].
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Poll<Result<'a, Obj<'a>>> {
+        self.buf.extend_from_slice(bytes);
+        if self.buf.is_empty() {
+            return Poll::Pending;
+        }
+        // Check for a whole object in a scratch arena first, so failed attempts don't pile up in
+        // the caller's
+        match marshal_load_remaining(&ObjArena::new(), &mut &self.buf[..], self.opts) {
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Poll::Pending
+            }
+            _ => {}
+        }
+        let mut input = &self.buf[..];
+        let result = marshal_load_remaining(self.arena, &mut input, self.opts);
+        let consumed = self.buf.len() - input.len();
+        self.buf.drain(..consumed);
+        Poll::Ready(result)
+    }
+
+    /// The input received but not yet read.
+    #[must_use]
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// Starts reading chunked input; see [`MarshalLoadIncremental`].
+///
+/// Bytes after an object are kept for the next one, so
+/// [`require_eof`](MarshalLoadExOptions::require_eof) is ignored.
+#[must_use]
+pub fn marshal_load_incremental(
+    arena: &ObjArena,
+    opts: MarshalLoadExOptions,
+) -> MarshalLoadIncremental<'_> {
+    MarshalLoadIncremental {
+        arena,
+        buf: Vec::new(),
+        opts: MarshalLoadExOptions {
+            require_eof: false,
+            ..opts
+        },
+    }
+}
+
+/// One type byte, as found by [`marshal_scan_tags`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TagRecord {
+    /// Position of the type byte in the input.
+    pub offset: u64,
+    /// The type byte without `FLAG_REF`, e.g. `b'c'` for a code object. The last record may not
+    /// be a valid type, if that's what stopped the scan.
+    pub type_tag: u8,
+    pub flag_ref: bool,
+}
+
+/// Lists the type byte of every object in `readable`, in stream order, without building any
+/// objects: lengths are read, but payloads are skipped. This is an outline of the structure,
+/// for finding where a misparse starts, e.g. a code object read with the wrong
+/// [`python_version`](MarshalLoadExOptions::python_version) soon runs into type bytes that
+/// make no sense.
+///
+/// Records are appended to `tags` as they're read, so after an error it still holds
+/// everything up to and including the type byte that couldn't be read, like
+/// [`Read::read_to_end`]. Only the `python_version`, `has_posonlyargcount` and `max_depth`
+/// options are used. Refs aren't resolved, so they're never invalid.
+/// # Errors
+/// See [`ErrorKind`].
+pub fn marshal_scan_tags(
+    readable: impl Read,
+    opts: MarshalLoadExOptions,
+    tags: &mut Vec<TagRecord>,
+) -> Result<'static, ()> {
+    let mut scanner = Scanner {
+        readable,
+        offset: 0,
+        depth: 0,
+        opts,
+        tags,
+    };
+    if scanner.object()? {
+        Ok(())
+    } else {
+        Err(Error::UnexpectedNull)
+    }
+}
+
+struct Scanner<'t, R> {
+    readable: R,
+    /// Bytes consumed so far.
+    offset: u64,
+    depth: usize,
+    opts: MarshalLoadExOptions,
+    tags: &'t mut Vec<TagRecord>,
+}
+impl<R: Read> Scanner<'_, R> {
+    fn read<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.readable.read_exact(&mut buf)?;
+        self.offset += N as u64;
+        Ok(buf)
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(u64::from(u32::from_le_bytes(self.read()?)))
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.readable).take(n), &mut io::sink())?;
+        self.offset += skipped;
+        if skipped == n {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    /// Returns `false` for `TYPE_NULL`.
+    fn object(&mut self) -> Result<'static, bool> {
+        let offset = self.offset;
+        let [code] = self.read()?;
+        let type_tag = code & !Type::FLAG_REF;
+        self.tags.push(TagRecord {
+            offset,
+            type_tag,
+            flag_ref: code & Type::FLAG_REF != 0,
+        });
+        let type_ = Type::from_u8(type_tag).ok_or(Error::InvalidType { spec: type_tag })?;
+        if self.depth >= self.opts.max_depth {
+            return Err(Error::RecursionLimitExceeded);
+        }
+        self.depth += 1;
+        let result = self.payload(type_);
+        self.depth -= 1;
+        result.map(|()| !matches!(type_, Type::Null))
+    }
+
+    fn elements(&mut self, n: u64) -> Result<'static, ()> {
+        for _ in 0..n {
+            if !self.object()? {
+                return Err(Error::UnexpectedNull);
+            }
+        }
+        Ok(())
+    }
+
+    fn payload(&mut self, type_: Type) -> Result<'static, ()> {
+        let py27 = self.opts.python_version == MarshalVersion::Py27;
+        match type_ {
+            Type::Null
+            | Type::None
+            | Type::StopIter
+            | Type::Ellipsis
+            | Type::False
+            | Type::True => {}
+            Type::Int | Type::Ref => self.skip(4)?,
+            Type::StringRef if py27 => self.skip(4)?,
+            Type::Int64 | Type::BinaryFloat => self.skip(8)?,
+            Type::BinaryComplex => self.skip(16)?,
+            Type::Float => {
+                let [n] = self.read()?;
+                self.skip(n.into())?;
+            }
+            Type::Complex => {
+                for _ in 0..2 {
+                    let [n] = self.read()?;
+                    self.skip(n.into())?;
+                }
+            }
+            Type::Long => {
+                let n = i32::from_le_bytes(self.read()?);
+                if n == i32::MIN {
+                    return Err(Error::LongSizeOutOfRange { n });
+                }
+                self.skip(u64::from(n.unsigned_abs()) * 2)?;
+            }
+            Type::String | Type::Interned | Type::Unicode | Type::Ascii | Type::AsciiInterned => {
+                let n = self.size()?;
+                self.skip(n)?;
+            }
+            Type::ShortAscii | Type::ShortAsciiInterned => {
+                let [n] = self.read()?;
+                self.skip(n.into())?;
+            }
+            Type::SmallTuple => {
+                let [n] = self.read()?;
+                self.elements(n.into())?;
+            }
+            Type::Tuple | Type::List | Type::Set | Type::FrozenSet => {
+                let n = self.size()?;
+                self.elements(n)?;
+            }
+            // Like `r_hashmap`, a NULL key or value ends the dict
+            Type::Dict => while self.object()? && self.object()? {},
+            Type::Code if self.opts.python_version >= MarshalVersion::Py311 => {
+                // argcount, [posonlyargcount,] kwonlyargcount, stacksize, flags
+                let longs = if self.opts.has_posonlyargcount { 5 } else { 4 };
+                self.skip(4 * longs)?;
+                // co_code up to co_qualname
+                self.elements(8)?;
+                // co_firstlineno
+                self.skip(4)?;
+                // co_linetable, co_exceptiontable
+                self.elements(2)?;
+            }
+            Type::Code => {
+                // argcount, [posonlyargcount, kwonlyargcount,] nlocals, stacksize, flags
+                let longs = if py27 {
+                    4
+                } else if self.opts.has_posonlyargcount {
+                    6
+                } else {
+                    5
+                };
+                self.skip(4 * longs)?;
+                // co_code up to co_name
+                self.elements(8)?;
+                // co_firstlineno
+                self.skip(4)?;
+                self.elements(1)?;
+            }
+            Type::Unknown | Type::StringRef => {
+                return Err(Error::InvalidType { spec: type_ as u8 })
+            }
+        }
+        Ok(())
+    }
 }
 
 // Ported from <https://github.com/python/cpython/blob/master/Lib/test/test_marshal.py>
 #[cfg(test)]
 mod test {
     use super::{
-        errors, marshal_load, marshal_load_ex, marshal_loads, Code, CodeFlags,
-        MarshalLoadExOptions, Obj, ObjHashable,
-    };
-    use num_bigint::BigInt;
-    use num_traits::Pow;
-    use std::{
-        io::{self, Read},
-        ops::Deref,
-        sync::Arc,
+        errors, marshal_load, marshal_load_buffered, marshal_load_code, marshal_load_counted, marshal_load_dict, marshal_load_ex, marshal_load_incremental, marshal_load_path, marshal_load_sequence,
+        marshal_load_remaining, marshal_load_shared_refs, marshal_load_tracked, marshal_load_with_stats, marshal_loads, marshal_loads_borrowed, marshal_scan_tags, load, borrow_slice, PLAUSIBLE_CODE_COUNT, Code, CodeFlags, MarshalLoadExOptions, MarshalVersion, Obj, ObjArena, TagRecord,
     };
+    use crate::Int;
+    use num_complex::Complex;
+    use num_traits::pow;
+    use std::io::{self, Read};
 
     macro_rules! assert_match {
         ($expr:expr, $pat:pat) => {
@@ -374,22 +1433,68 @@ mod test {
         };
     }
 
-    fn load_unwrap(r: impl Read) -> Obj {
-        marshal_load(r).unwrap()
+    fn load_unwrap<'a>(arena: &'a ObjArena, r: impl Read) -> Obj<'a> {
+        marshal_load(arena, r).unwrap()
     }
 
-    fn loads_unwrap(s: &[u8]) -> Obj {
-        load_unwrap(s)
+    fn loads_unwrap<'a>(arena: &'a ObjArena, s: &[u8]) -> Obj<'a> {
+        load_unwrap(arena, s)
     }
 
     #[test]
     fn test_ints() {
-        assert_eq!(BigInt::parse_bytes(b"85070591730234615847396907784232501249", 10).unwrap(), *loads_unwrap(b"l\t\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\xf0\x7f\xff\x7f\xff\x7f\xff\x7f?\x00").extract_long().unwrap());
+        let arena = ObjArena::new();
+        assert_eq!("85070591730234615847396907784232501249".parse::<Int>().unwrap(), *loads_unwrap(&arena, b"l\t\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\xf0\x7f\xff\x7f\xff\x7f\xff\x7f?\x00").extract_long().unwrap());
+    }
+
+    /// Values around the edge of `TYPE_INT` decode the same from each encoding that can hold
+    /// them, and are written the way CPython writes them: `TYPE_INT` if they fit in 32 bits,
+    /// otherwise `TYPE_LONG`, never `TYPE_INT64`.
+    #[test]
+    fn test_int_boundary() {
+        let arena = ObjArena::new();
+        // The value, and its TYPE_INT (if it fits), TYPE_INT64 and TYPE_LONG encodings
+        type Case<'a> = (i64, Option<&'a [u8]>, &'a [u8], &'a [u8]);
+        let cases: &[Case] = &[
+            (
+                (1 << 31) - 1,
+                Some(b"i\xff\xff\xff\x7f"),
+                b"I\xff\xff\xff\x7f\x00\x00\x00\x00",
+                b"l\x03\x00\x00\x00\xff\x7f\xff\x7f\x01\x00",
+            ),
+            (
+                1 << 31,
+                None,
+                b"I\x00\x00\x00\x80\x00\x00\x00\x00",
+                b"l\x03\x00\x00\x00\x00\x00\x00\x00\x02\x00",
+            ),
+            (
+                -(1 << 31),
+                Some(b"i\x00\x00\x00\x80"),
+                b"I\x00\x00\x00\x80\xff\xff\xff\xff",
+                b"l\xfd\xff\xff\xff\x00\x00\x00\x00\x02\x00",
+            ),
+            (
+                -(1 << 31) - 1,
+                None,
+                b"I\xff\xff\xff\x7f\xff\xff\xff\xff",
+                b"l\xfd\xff\xff\xff\x01\x00\x00\x00\x02\x00",
+            ),
+        ];
+        for &(x, int, int64, long) in cases {
+            let expected = Int::from(x);
+            for input in int.iter().chain(&[int64, long]) {
+                assert_eq!(*loads_unwrap(&arena, input).extract_long().unwrap(), expected, "{:?}", input);
+            }
+            let written = crate::write::marshal_dumps(&Obj::from_i64(&arena, x), 4).unwrap();
+            assert_eq!(written, int.unwrap_or(long), "{}", x);
+        }
     }
 
     #[allow(clippy::unreadable_literal)]
     #[test]
     fn test_int64() {
+        let arena = ObjArena::new();
         for mut base in [i64::MAX, i64::MIN, -i64::MAX, -(i64::MIN >> 1)]
             .iter()
             .copied()
@@ -399,8 +1504,8 @@ mod test {
                 s.push(b'I');
                 s.extend_from_slice(&base.to_le_bytes());
                 assert_eq!(
-                    BigInt::from(base),
-                    *loads_unwrap(&s).extract_long().unwrap()
+                    Int::from(base),
+                    *loads_unwrap(&arena, &s).extract_long().unwrap()
                 );
 
                 if base == -1 {
@@ -412,26 +1517,38 @@ mod test {
         }
 
         assert_eq!(
-            BigInt::from(0x1032547698badcfe_i64),
-            *loads_unwrap(b"I\xfe\xdc\xba\x98\x76\x54\x32\x10")
+            Int::from(0x1032547698badcfe_i64),
+            *loads_unwrap(&arena, b"I\xfe\xdc\xba\x98\x76\x54\x32\x10")
                 .extract_long()
                 .unwrap()
         );
         assert_eq!(
-            BigInt::from(-0x1032547698badcff_i64),
-            *loads_unwrap(b"I\x01\x23\x45\x67\x89\xab\xcd\xef")
+            Int::from(-0x1032547698badcff_i64),
+            *loads_unwrap(&arena, b"I\x01\x23\x45\x67\x89\xab\xcd\xef")
                 .extract_long()
                 .unwrap()
         );
         assert_eq!(
-            BigInt::from(0x7f6e5d4c3b2a1908_i64),
-            *loads_unwrap(b"I\x08\x19\x2a\x3b\x4c\x5d\x6e\x7f")
+            Int::from(0x7f6e5d4c3b2a1908_i64),
+            *loads_unwrap(&arena, b"I\x08\x19\x2a\x3b\x4c\x5d\x6e\x7f")
                 .extract_long()
                 .unwrap()
         );
         assert_eq!(
-            BigInt::from(-0x7f6e5d4c3b2a1909_i64),
-            *loads_unwrap(b"I\xf7\xe6\xd5\xc4\xb3\xa2\x91\x80")
+            Int::from(i64::MIN),
+            *loads_unwrap(&arena, b"I\x00\x00\x00\x00\x00\x00\x00\x80")
+                .extract_long()
+                .unwrap()
+        );
+        assert_eq!(
+            Int::from(-1),
+            *loads_unwrap(&arena, b"I\xff\xff\xff\xff\xff\xff\xff\xff")
+                .extract_long()
+                .unwrap()
+        );
+        assert_eq!(
+            Int::from(-0x7f6e5d4c3b2a1909_i64),
+            *loads_unwrap(&arena, b"I\xf7\xe6\xd5\xc4\xb3\xa2\x91\x80")
                 .extract_long()
                 .unwrap()
         );
@@ -439,58 +1556,148 @@ mod test {
 
     #[test]
     fn test_bool() {
-        assert_eq!(true, loads_unwrap(b"T").extract_bool().unwrap());
-        assert_eq!(false, loads_unwrap(b"F").extract_bool().unwrap());
+        let arena = ObjArena::new();
+        assert!(loads_unwrap(&arena, b"T").extract_bool().unwrap());
+        assert!(!loads_unwrap(&arena, b"F").extract_bool().unwrap());
     }
 
     #[allow(clippy::float_cmp, clippy::cast_precision_loss)]
     #[test]
     fn test_floats() {
+        let arena = ObjArena::new();
         assert_eq!(
             (i64::MAX as f64) * 3.7e250,
-            loads_unwrap(b"g\x11\x9f6\x98\xd2\xab\xe4w")
+            loads_unwrap(&arena, b"g\x11\x9f6\x98\xd2\xab\xe4w")
                 .extract_float()
                 .unwrap()
         );
     }
 
+    #[test]
+    fn test_float_str() {
+        let arena = ObjArena::new();
+        // marshal.dumps(x, 1) for each x
+        assert_eq!(loads_unwrap(&arena, b"f\x041.25").extract_float().unwrap(), 1.25);
+        assert_eq!(loads_unwrap(&arena, b"f\x03inf").extract_float().unwrap(), f64::INFINITY);
+        assert_eq!(
+            loads_unwrap(&arena, b"f\x04-inf").extract_float().unwrap(),
+            -f64::INFINITY
+        );
+        assert!(loads_unwrap(&arena, b"f\x03nan").extract_float().unwrap().is_nan());
+        for (spelling, expected) in &[
+            ("Infinity", f64::INFINITY),
+            ("+infinity", f64::INFINITY),
+            ("-INFINITY", -f64::INFINITY),
+            ("+inf", f64::INFINITY),
+            ("1.#INF", f64::INFINITY),
+            ("-1.#INF", -f64::INFINITY),
+            (" 2.5\n", 2.5),
+        ] {
+            let input = [&[b'f', spelling.len() as u8][..], spelling.as_bytes()].concat();
+            assert_eq!(loads_unwrap(&arena, &input).extract_float().unwrap(), *expected);
+        }
+        for (spelling, negative) in &[
+            ("NaN", false),
+            ("+nan", false),
+            ("-nan", true),
+            ("1.#QNAN", false),
+            ("-1.#IND", true),
+            ("1.#SNAN", false),
+        ] {
+            let input = [&[b'f', spelling.len() as u8][..], spelling.as_bytes()].concat();
+            let x = loads_unwrap(&arena, &input).extract_float().unwrap();
+            assert!(x.is_nan(), "{}", spelling);
+            assert_eq!(x.is_sign_negative(), *negative, "{}", spelling);
+        }
+
+        let err = marshal_loads(&arena, b"f\x041.2x").unwrap_err();
+        assert_eq!(err.to_string(), "Unable to parse float \"1.2x\": invalid float literal");
+        match err {
+            errors::Error::ParseFloat { raw, .. } => assert_eq!(raw, "1.2x"),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_complex() {
+        let arena = ObjArena::new();
+        let expected = Complex::new(1.5, -2.25);
+        // marshal.dumps(1.5-2.25j) and marshal.dumps(1.5-2.25j, 1)
+        assert_eq!(
+            expected,
+            loads_unwrap(&arena, b"\xf9\x00\x00\x00\x00\x00\x00\xf8?\x00\x00\x00\x00\x00\x00\x02\xc0")
+                .extract_complex()
+                .unwrap()
+        );
+        assert_eq!(
+            expected,
+            loads_unwrap(&arena, b"x\x031.5\x05-2.25").extract_complex().unwrap()
+        );
+    }
+
     #[test]
     fn test_unicode() {
-        assert_eq!("", *loads_unwrap(b"\xda\x00").extract_string().unwrap());
+        let arena = ObjArena::new();
+        assert_eq!("", loads_unwrap(&arena, b"\xda\x00").extract_string().unwrap());
         assert_eq!(
             "Andr\u{e8} Previn",
-            *loads_unwrap(b"u\r\x00\x00\x00Andr\xc3\xa8 Previn")
+            loads_unwrap(&arena, b"u\r\x00\x00\x00Andr\xc3\xa8 Previn")
                 .extract_string()
                 .unwrap()
         );
         assert_eq!(
             "abc",
-            *loads_unwrap(b"\xda\x03abc").extract_string().unwrap()
+            loads_unwrap(&arena, b"\xda\x03abc").extract_string().unwrap()
         );
         assert_eq!(
             " ".repeat(10_000),
-            *loads_unwrap(&[b"a\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
+            loads_unwrap(&arena, &[b"a\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
                 .extract_string()
                 .unwrap()
         );
     }
 
+    #[test]
+    fn test_len() {
+        let arena = ObjArena::new();
+        let string = loads_unwrap(&arena, b"u\r\x00\x00\x00Andr\xc3\xa8 Previn");
+        // Code points, like Python, not the 13 bytes of UTF-8
+        assert_eq!(string.len(), Some(12));
+        let opts = MarshalLoadExOptions {
+            validate_strings: false,
+            ..MarshalLoadExOptions::default()
+        };
+        let raw = marshal_load_ex(&arena, &b"u\r\x00\x00\x00Andr\xc3\xa8 Previn"[..], opts).unwrap();
+        assert_eq!(raw.len(), Some(12));
+        // '\ud800' written with surrogatepass is still one code point
+        let surrogate = marshal_load_ex(&arena, &b"u\x04\x00\x00\x00a\xed\xa0\x80"[..], opts).unwrap();
+        assert_eq!(surrogate.len(), Some(2));
+        let bytes = loads_unwrap(&arena, b"\xf3\x0c\x00\x00\x00Andr\xe8 Previn");
+        assert_eq!(bytes.len(), Some(12));
+        assert_eq!(loads_unwrap(&arena, b"{\xda\x01aN0").len(), Some(1));
+        assert_eq!(loads_unwrap(&arena, b")\x02NN").len(), Some(2));
+        assert_eq!(loads_unwrap(&arena, b")\x00").is_empty(), Some(true));
+        assert_eq!(loads_unwrap(&arena, b"N").len(), None);
+        assert_eq!(loads_unwrap(&arena, b"i\x05\x00\x00\x00").len(), None);
+    }
+
     #[test]
     fn test_string() {
-        assert_eq!("", *loads_unwrap(b"\xda\x00").extract_string().unwrap());
+        let arena = ObjArena::new();
+        assert_eq!("", loads_unwrap(&arena, b"\xda\x00").extract_string().unwrap());
         assert_eq!(
             "Andr\u{e8} Previn",
-            *loads_unwrap(b"\xf5\r\x00\x00\x00Andr\xc3\xa8 Previn")
+            loads_unwrap(&arena, b"\xf5\r\x00\x00\x00Andr\xc3\xa8 Previn")
                 .extract_string()
                 .unwrap()
         );
         assert_eq!(
             "abc",
-            *loads_unwrap(b"\xda\x03abc").extract_string().unwrap()
+            loads_unwrap(&arena, b"\xda\x03abc").extract_string().unwrap()
         );
         assert_eq!(
             " ".repeat(10_000),
-            *loads_unwrap(&[b"\xe1\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
+            loads_unwrap(&arena, &[b"\xe1\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
                 .extract_string()
                 .unwrap()
         );
@@ -498,44 +1705,46 @@ mod test {
 
     #[test]
     fn test_bytes() {
+        let arena = ObjArena::new();
         assert_eq!(
             b"",
-            &loads_unwrap(b"\xf3\x00\x00\x00\x00")
+            loads_unwrap(&arena, b"\xf3\x00\x00\x00\x00")
                 .extract_bytes()
-                .unwrap()[..]
+                .unwrap()
         );
         assert_eq!(
             b"Andr\xe8 Previn",
-            &loads_unwrap(b"\xf3\x0c\x00\x00\x00Andr\xe8 Previn")
+            loads_unwrap(&arena, b"\xf3\x0c\x00\x00\x00Andr\xe8 Previn")
                 .extract_bytes()
-                .unwrap()[..]
+                .unwrap()
         );
         assert_eq!(
             b"abc",
-            &loads_unwrap(b"\xf3\x03\x00\x00\x00abc")
+            loads_unwrap(&arena, b"\xf3\x03\x00\x00\x00abc")
                 .extract_bytes()
-                .unwrap()[..]
+                .unwrap()
         );
         assert_eq!(
             b" ".repeat(10_000),
-            &loads_unwrap(&[b"\xf3\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
+            loads_unwrap(&arena, &[b"\xf3\x10'\x00\x00" as &[u8], &[b' '; 10_000]].concat())
                 .extract_bytes()
-                .unwrap()[..]
+                .unwrap()
         );
     }
 
     #[test]
     fn test_exceptions() {
-        loads_unwrap(b"S").extract_stop_iteration().unwrap();
+        let arena = ObjArena::new();
+        loads_unwrap(&arena, b"S").extract_stop_iteration().unwrap();
     }
 
     fn assert_test_exceptions_code_valid(code: &Code) {
         assert_eq!(code.argcount, 1);
         assert!(code.cellvars.is_empty());
-        assert_eq!(*code.code, b"t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00");
+        assert_eq!(code.code, b"t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00");
         assert_eq!(code.consts.len(), 1);
         assert!(code.consts[0].is_none());
-        assert_eq!(*code.filename, "<string>");
+        assert_eq!(code.filename, "<string>");
         assert_eq!(code.firstlineno, 3);
         assert_eq!(
             code.flags,
@@ -543,184 +1752,536 @@ mod test {
         );
         assert!(code.freevars.is_empty());
         assert_eq!(code.kwonlyargcount, 0);
-        assert_eq!(*code.lnotab, b"\x00\x01\x10\x01");
-        assert_eq!(*code.name, "test_exceptions");
-        assert!(code.names.iter().map(Deref::deref).eq(vec![
+        assert_eq!(code.lnotab, b"\x00\x01\x10\x01");
+        assert_eq!(code.name, "test_exceptions");
+        assert!(code.names_iter().eq(vec![
             "marshal",
             "loads",
             "dumps",
             "StopIteration",
             "assertEqual"
-        ]
-        .iter()));
+        ]));
         assert_eq!(code.nlocals, 2);
         assert_eq!(code.stacksize, 5);
-        assert!(code
-            .varnames
-            .iter()
-            .map(Deref::deref)
-            .eq(vec!["self", "new"].iter()));
+        assert!(code.varnames_iter().eq(vec!["self", "new"]));
     }
 
     #[test]
     fn test_code() {
+        let arena = ObjArena::new();
         // ExceptionTestCase.test_exceptions
         // { 'co_argcount': 1, 'co_cellvars': (), 'co_code': b't\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00', 'co_consts': (None,), 'co_filename': '<string>', 'co_firstlineno': 3, 'co_flags': 67, 'co_freevars': (), 'co_kwonlyargcount': 0, 'co_lnotab': b'\x00\x01\x10\x01', 'co_name': 'test_exceptions', 'co_names': ('marshal', 'loads', 'dumps', 'StopIteration', 'assertEqual'), 'co_nlocals': 2, 'co_stacksize': 5, 'co_varnames': ('self', 'new') }
         let mut input: &[u8] = b"\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00C\x00\x00\x00s \x00\x00\x00t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00)\x01N)\x05\xda\x07marshal\xda\x05loads\xda\x05dumps\xda\rStopIteration\xda\x0bassertEqual)\x02\xda\x04self\xda\x03new\xa9\x00r\x08\x00\x00\x00\xda\x08<string>\xda\x0ftest_exceptions\x03\x00\x00\x00s\x04\x00\x00\x00\x00\x01\x10\x01";
-        println!("{}", input.len());
-        let code_result = marshal_load_ex(
+        let code_result = marshal_load_remaining(
+            &arena,
             &mut input,
             MarshalLoadExOptions {
                 has_posonlyargcount: false,
+                ..MarshalLoadExOptions::default()
             },
         );
-        println!("{}", input.len());
+        assert!(input.is_empty());
         let code = code_result.unwrap().extract_code().unwrap();
-        assert_test_exceptions_code_valid(&code);
+        assert_test_exceptions_code_valid(code);
+    }
+
+    #[test]
+    fn test_code_unknown_flags() {
+        // Same as `test_code`, but with co_flags = 0x1c000043 (HAS_DOCSTRING, METHOD and a bit
+        // that isn't modelled at all).
+        let input: &[u8] = b"\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00C\x00\x00\x1cs \x00\x00\x00t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00)\x01N)\x05\xda\x07marshal\xda\x05loads\xda\x05dumps\xda\rStopIteration\xda\x0bassertEqual)\x02\xda\x04self\xda\x03new\xa9\x00r\x08\x00\x00\x00\xda\x08<string>\xda\x0ftest_exceptions\x03\x00\x00\x00s\x04\x00\x00\x00\x00\x01\x10\x01";
+        let arena = ObjArena::new();
+        let code = marshal_load_ex(
+            &arena,
+            input,
+            MarshalLoadExOptions {
+                has_posonlyargcount: false,
+                ..MarshalLoadExOptions::default()
+            },
+        )
+        .unwrap()
+        .extract_code()
+        .unwrap();
+        assert_eq!(code.flags.bits(), 0x1c00_0043);
+        assert!(code
+            .flags
+            .contains(CodeFlags::HAS_DOCSTRING | CodeFlags::METHOD | CodeFlags::OPTIMIZED));
+        assert_eq!(code.flags.unknown_bits(), 0x1000_0000);
+
+        assert_match!(
+            marshal_load_ex(
+                &arena,
+                input,
+                MarshalLoadExOptions {
+                    has_posonlyargcount: false,
+                    reject_unknown_code_flags: true,
+                    ..MarshalLoadExOptions::default()
+                },
+            )
+            .unwrap_err(),
+            errors::Error::UnknownCodeFlags { flags: 0x1c00_0043 }
+        );
+    }
+
+    #[test]
+    fn test_code_py27() {
+        let arena = ObjArena::new();
+        // def f(a): return (a, "hi", u"h\xe9"), compiled by Python 2.7
+        let input: &[u8] = b"c\x01\x00\x00\x00\x01\x00\x00\x00\x03\x00\x00\x00C\x00\x00\x00s\r\x00\x00\x00|\x00\x00d\x01\x00d\x02\x00f\x03\x00S(\x03\x00\x00\x00Nt\x02\x00\x00\x00hiu\x03\x00\x00\x00h\xc3\xa9(\x00\x00\x00\x00(\x01\x00\x00\x00t\x01\x00\x00\x00a(\x00\x00\x00\x00(\x00\x00\x00\x00s\x04\x00\x00\x00x.pyt\x01\x00\x00\x00f\x01\x00\x00\x00s\x02\x00\x00\x00\x00\x01";
+        let opts = MarshalLoadExOptions::for_version(MarshalVersion::Py27);
+        let code = marshal_load_ex(&arena, input, opts).unwrap().extract_code().unwrap();
+        assert_eq!(code.argcount, 1);
+        assert_eq!(code.kwonlyargcount, 0);
+        assert_eq!(code.nlocals, 1);
+        assert_eq!(code.stacksize, 3);
+        assert_eq!(code.flags.bits(), 67);
+        assert_eq!(code.code, b"|\x00\x00d\x01\x00d\x02\x00f\x03\x00S");
+        assert_eq!(
+            code.consts,
+            [Obj::None, Obj::Bytes(b"hi"), Obj::String("h\u{e9}")]
+        );
+        assert!(code.names.is_empty());
+        assert_eq!(code.varnames, ["a"]);
+        assert_eq!(code.filename, "x.py");
+        assert_eq!(code.name, "f");
+        assert_eq!(code.firstlineno, 1);
+        assert_eq!(code.lnotab, b"\x00\x01");
+
+        // The 3.x layout has an extra kwonlyargcount field, so it misreads everything after
+        assert!(marshal_load_ex(&arena, input, MarshalLoadExOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_code_py311() {
+        // marshal.dumps((f.__code__, "end")) of
+        //     def f(a):
+        //         try:
+        //             return g(a)
+        //         except E:
+        //             return lambda: a
+        // compiled by Python 3.11 and 3.12, which lay out code objects the same way
+        let py311: &[u8] = b")\x02\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x03\x00\x00\x00\xf3N\x00\x00\x00\x87\x00\x97\x00\x09\x00t\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x89\x00\xa6\x01\x00\x00\xab\x01\x00\x00\x00\x00\x00\x00\x00\x00S\x00#\x00t\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00$\x00r\x08\x01\x00\x88\x00f\x01d\x01\x84\x08c\x02Y\x00S\x00w\x00x\x03Y\x00w\x01)\x02Nc\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x13\x00\x00\x00\xf3\x08\x00\x00\x00\x95\x01\x97\x00\x89\x00S\x00)\x01N\xa9\x00\xa9\x01\xda\x01as\x01\x00\x00\x00\x80\xfa\x04m.py\xfa\x08<lambda>z\x13f.<locals>.<lambda>\x05\x00\x00\x00s\x07\x00\x00\x00\xf8\x80\x00\x90q\x80\x00\xf3\x00\x00\x00\x00)\x02\xda\x01g\xda\x01Er\x04\x00\x00\x00s\x01\x00\x00\x00`r\x06\x00\x00\x00\xda\x01fr\x0b\x00\x00\x00\x01\x00\x00\x00s@\x00\x00\x00\xf8\x80\x00\xf0\x02\x03\x05\x19\xdd\x0f\x10\x90\x11\x89t\x8ct\x88\x0b\xf8\xdd\x0b\x0c\xf0\x00\x01\x05\x19\xf0\x00\x01\x05\x19\xf0\x00\x01\x05\x19\xd8\x0f\x18\x88y\x88y\x88y\xd0\x08\x18\xd0\x08\x18\xd0\x08\x18\xf0\x03\x01\x05\x19\xf8\xf8\xf8s\x0c\x00\x00\x00\x83\x0e\x12\x00\x92\x0f$\x03\xa3\x01$\x03\xda\x03end";
+        let py312: &[u8] = b")\x02\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x03\x00\x00\x00\xf3D\x00\x00\x00\x87\x00\x97\x00\x09\x00t\x01\x00\x00\x00\x00\x00\x00\x00\x00\x89\x00\xab\x01\x00\x00\x00\x00\x00\x00S\x00#\x00t\x02\x00\x00\x00\x00\x00\x00\x00\x00$\x00r\x08\x01\x00\x88\x00f\x01d\x01\x84\x08c\x02Y\x00S\x00w\x00x\x03Y\x00w\x01)\x02Nc\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x13\x00\x00\x00\xf3\x08\x00\x00\x00\x95\x01\x97\x00\x89\x00S\x00)\x01N\xa9\x00\xa9\x01\xda\x01as\x01\x00\x00\x00\x80\xfa\x04m.py\xfa\x08<lambda>z\x13f.<locals>.<lambda>\x05\x00\x00\x00s\x07\x00\x00\x00\xf8\x80\x00\x90q\x80\x00\xf3\x00\x00\x00\x00)\x02\xda\x01g\xda\x01Er\x04\x00\x00\x00s\x01\x00\x00\x00`r\x06\x00\x00\x00\xda\x01fr\x0b\x00\x00\x00\x01\x00\x00\x00s&\x00\x00\x00\xf8\x80\x00\xf0\x02\x03\x05\x19\xdc\x0f\x10\x90\x11\x8bt\x88\x0b\xf8\xdc\x0b\x0c\xf2\x00\x01\x05\x19\xdb\x0f\x18\xd2\x08\x18\xf0\x03\x01\x05\x19\xfas\x0c\x00\x00\x00\x83\x0a\x0e\x00\x8e\x0e\x1f\x03\x9e\x01\x1f\x03\xda\x03end";
+        let cases = [
+            (MarshalVersion::Py311, py311, &b"\x83\x0e\x12\x00\x92\x0f$\x03\xa3\x01$\x03"[..]),
+            (MarshalVersion::Py312, py312, b"\x83\x0a\x0e\x00\x8e\x0e\x1f\x03\x9e\x01\x1f\x03"),
+        ];
+        for &(version, input, exceptiontable) in &cases {
+            let arena = ObjArena::new();
+            let opts = MarshalLoadExOptions::for_version(version);
+            let tuple = marshal_load_ex(&arena, input, opts).unwrap().extract_tuple().unwrap();
+            // Nothing after the code object is misread
+            assert_eq!(tuple[1], Obj::String("end"));
+            let code = tuple[0].extract_code().unwrap();
+            assert_eq!(code.argcount, 1);
+            assert_eq!(code.stacksize, 4);
+            assert_eq!(code.flags.bits(), 3);
+            assert_eq!(code.names, ["g", "E"]);
+            // `a` is an argument that the lambda closes over
+            assert_eq!(code.varnames, ["a"]);
+            assert_eq!(code.cellvars, ["a"]);
+            assert!(code.freevars.is_empty());
+            assert_eq!(code.nlocals, 1);
+            assert_eq!(code.filename, "m.py");
+            assert_eq!(code.name, "f");
+            assert_eq!(code.qualname, "f");
+            assert_eq!(code.firstlineno, 1);
+            assert_eq!(code.exceptiontable, exceptiontable);
+            let lambda = code.consts[1].extract_code().unwrap();
+            assert_eq!(lambda.freevars, ["a"]);
+            assert!(lambda.varnames.is_empty());
+            assert_eq!(lambda.full_name(), "f.<locals>.<lambda>");
+
+            let mut tags = Vec::new();
+            marshal_scan_tags(input, opts, &mut tags).unwrap();
+            assert_eq!(tags.last().unwrap().offset, input.len() as u64 - 5);
+            // Same for the layout without co_posonlyargcount
+            let opts = MarshalLoadExOptions { has_posonlyargcount: false, ..opts };
+            let dump = |has_posonlyargcount| {
+                let mut out = Vec::new();
+                let dump_opts = crate::write::MarshalDumpExOptions {
+                    python_version: version,
+                    has_posonlyargcount,
+                    ..crate::write::MarshalDumpExOptions::default()
+                };
+                crate::write::marshal_dump_ex(&mut out, &Obj::Tuple(tuple), dump_opts).unwrap();
+                out
+            };
+            let without = dump(false);
+            // One long less in each of the two code objects
+            assert_eq!(without.len(), dump(true).len() - 4 * 2);
+            tags.clear();
+            marshal_scan_tags(&without[..], opts, &mut tags).unwrap();
+            assert_eq!(tags.last().unwrap().offset, without.len() as u64 - 5);
+            assert_eq!(marshal_load_ex(&arena, &without[..], opts).unwrap().extract_tuple().unwrap(), tuple);
+
+            // The 3.10 layout runs into the 3.11 fields
+            assert!(marshal_load_ex(&arena, input, MarshalLoadExOptions::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_stringref_py27() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions::for_version(MarshalVersion::Py27);
+        // s = intern("spam"); t = intern("eggs"); marshal.dumps((s, t, s, t, "spam")) by Python
+        // 2.7: each interned string is written once, then referred to by its index
+        let input: &[u8] = b"(\x05\x00\x00\x00t\x04\x00\x00\x00spamt\x04\x00\x00\x00eggsR\x00\x00\x00\x00R\x01\x00\x00\x00R\x00\x00\x00\x00";
+        let tuple = marshal_load_ex(&arena, input, opts).unwrap();
+        let (spam, eggs) = (Obj::Bytes(b"spam"), Obj::Bytes(b"eggs"));
+        assert_eq!(tuple, Obj::Tuple(&[spam, eggs, spam, eggs, spam]));
+
+        assert_match!(
+            marshal_load_ex(&arena, &b"R\x00\x00\x00\x00"[..], opts).unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        // Python 3 only has the generic ref table
+        assert_match!(
+            marshal_loads(&arena, b"(\x02\x00\x00\x00t\x04\x00\x00\x00spamR\x00\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidType { spec: b'R' }
+        );
+    }
+
+    #[test]
+    fn test_unvalidated_strings() {
+        let arena = ObjArena::new();
+        // marshal.dumps(("a", "\ud800")): the lone surrogate is written with `surrogatepass`
+        let input: &[u8] = b")\x02z\x01au\x03\x00\x00\x00\xed\xa0\x80";
+        assert_match!(marshal_loads(&arena, input).unwrap_err(), errors::Error::Utf8(_));
+
+        let opts = MarshalLoadExOptions {
+            validate_strings: false,
+            ..MarshalLoadExOptions::default()
+        };
+        let tuple = marshal_load_ex(&arena, input, opts).unwrap();
+        let (a, surrogate) = match tuple.extract_tuple().unwrap() {
+            &[a, surrogate] => (a, surrogate),
+            x => panic!("{:?}", x),
+        };
+        assert_eq!(a, Obj::RawString(b"a"));
+        assert_eq!(a, Obj::String("a"));
+        assert_eq!(a.extract_string(), Ok("a"));
+        assert!(surrogate.is_string());
+        assert_eq!(surrogate.type_name(), "str");
+        assert_eq!(surrogate.extract_string(), Err(surrogate));
+        assert_eq!(crate::write::marshal_dumps(&tuple, 4).unwrap(), input);
+    }
+
+    #[test]
+    fn test_loads_borrowed() {
+        let arena = ObjArena::new();
+        let input: &[u8] = b")\x03z\x03abcs\x02\x00\x00\x00\x00\xff\xe9\x01\x00\x00\x00";
+        let obj = marshal_loads_borrowed(&arena, input, MarshalLoadExOptions::default()).unwrap();
+        assert_eq!(obj, marshal_loads(&arena, input).unwrap());
+        let tuple = obj.extract_tuple().unwrap();
+        let string = tuple[0].extract_string().unwrap();
+        let bytes = tuple[1].extract_bytes().unwrap();
+        assert!(std::ptr::eq(string.as_bytes(), &input[4..7]));
+        assert!(std::ptr::eq(bytes, &input[12..14]));
+
+        assert_match!(
+            marshal_loads_borrowed(&arena, &input[..13], MarshalLoadExOptions::default())
+                .unwrap_err(),
+            errors::Error::Io(_)
+        );
+    }
+
+    #[test]
+    fn test_scan_tags() {
+        let record = |offset, type_tag, flag_ref| TagRecord {
+            offset,
+            type_tag,
+            flag_ref,
+        };
+        let mut tags = Vec::new();
+        // ((1, 'a'), {'a': None})
+        let input: &[u8] = b")\x02)\x02\xe9\x01\x00\x00\x00\xda\x01a{r\x01\x00\x00\x00N0";
+        marshal_scan_tags(input, MarshalLoadExOptions::default(), &mut tags).unwrap();
+        assert_eq!(
+            tags,
+            [
+                record(0, b')', false),
+                record(2, b')', false),
+                record(4, b'i', true),
+                record(9, b'Z', true),
+                record(12, b'{', false),
+                record(13, b'r', false),
+                record(18, b'N', false),
+                record(19, b'0', false),
+            ]
+        );
+
+        // The records stop at the byte that made no sense
+        tags.clear();
+        let input: &[u8] = b"[\x02\x00\x00\x00N\x01";
+        let err = marshal_scan_tags(input, MarshalLoadExOptions::default(), &mut tags).unwrap_err();
+        assert_match!(err, errors::Error::InvalidType { spec: 1 });
+        assert_eq!(tags, [record(0, b'[', false), record(5, b'N', false), record(6, 1, false)]);
+
+        // Reading 3.8+ code as 3.7 gets out of step right away
+        let code = crate::write::marshal_dumps(
+            &Obj::Code(&crate::CodeBuilder::new().with_code(b"d\x00S\x00").finish()),
+            4,
+        )
+        .unwrap();
+        tags.clear();
+        marshal_scan_tags(&code[..], MarshalLoadExOptions::default(), &mut tags).unwrap();
+        assert_eq!(tags.len(), 10);
+        tags.clear();
+        let py37 = MarshalLoadExOptions::for_version(MarshalVersion::Py37);
+        assert!(marshal_scan_tags(&code[..], py37, &mut tags).is_err());
     }
 
     #[test]
     fn test_many_codeobjects() {
-        let mut input: &[u8] = &[b"(\x88\x13\x00\x00\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00C\x00\x00\x00s \x00\x00\x00t\x00\xa0\x01t\x00\xa0\x02t\x03\xa1\x01\xa1\x01}\x01|\x00\xa0\x04t\x03|\x01\xa1\x02\x01\x00d\x00S\x00)\x01N)\x05\xda\x07marshal\xda\x05loads\xda\x05dumps\xda\rStopIteration\xda\x0bassertEqual)\x02\xda\x04self\xda\x03new\xa9\x00r\x08\x00\x00\x00\xda\x08<string>\xda\x0ftest_exceptions\x03\x00\x00\x00s\x04\x00\x00\x00\x00\x01\x10\x01" as &[u8], &b"r\x00\x00\x00\x00".repeat(4999)].concat();
+        let arena = ObjArena::new();
+        let input = crate::fixtures::many_codeobjects(5000);
         let result = marshal_load_ex(
-            &mut input,
+            &arena,
+            &input[..],
             MarshalLoadExOptions {
                 has_posonlyargcount: false,
+                ..MarshalLoadExOptions::default()
             },
         );
         let tuple = result.unwrap().extract_tuple().unwrap();
-        for o in &*tuple {
-            assert_test_exceptions_code_valid(&o.clone().extract_code().unwrap());
+        for o in tuple {
+            assert_test_exceptions_code_valid(o.extract_code().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_clone_into() {
+        let input = crate::fixtures::many_codeobjects(3);
+        let opts = MarshalLoadExOptions {
+            has_posonlyargcount: false,
+            ..MarshalLoadExOptions::default()
+        };
+        let target = ObjArena::new();
+        let copy = {
+            let arena = ObjArena::new();
+            let obj = marshal_load_ex(&arena, &input[..], opts).unwrap();
+            let copy = obj.clone_into(&target);
+            assert_eq!(copy, obj);
+            copy
+        };
+        for o in copy.extract_tuple().unwrap() {
+            assert_test_exceptions_code_valid(o.extract_code().unwrap());
         }
     }
 
+    #[test]
+    fn test_leak() {
+        fn load(input: &[u8]) -> Obj<'static> {
+            let arena = ObjArena::new();
+            marshal_load(&arena, input).unwrap().leak()
+        }
+        let obj = load(b")\x02\xe9\x01\x00\x00\x00\xda\x01a");
+        assert_eq!(obj, Obj::Tuple(&[Obj::Long(&Int::from(1)), Obj::String("a")]));
+    }
+
     #[test]
     fn test_different_filenames() {
+        let arena = ObjArena::new();
         let mut input: &[u8] = b")\x02c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00e\x00\x01\x00d\x00S\x00)\x01N)\x01\xda\x01x\xa9\x00r\x01\x00\x00\x00r\x01\x00\x00\x00\xda\x02f1\xda\x08<module>\x01\x00\x00\x00\xf3\x00\x00\x00\x00c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00e\x00\x01\x00d\x00S\x00)\x01N)\x01\xda\x01yr\x01\x00\x00\x00r\x01\x00\x00\x00r\x01\x00\x00\x00\xda\x02f2r\x03\x00\x00\x00\x01\x00\x00\x00r\x04\x00\x00\x00";
-        println!("{}", input.len());
-        let result = marshal_load_ex(
+        let result = marshal_load_remaining(
+            &arena,
             &mut input,
             MarshalLoadExOptions {
                 has_posonlyargcount: false,
+                ..MarshalLoadExOptions::default()
             },
         );
-        println!("{}", input.len());
+        assert!(input.is_empty());
         let tuple = result.unwrap().extract_tuple().unwrap();
         assert_eq!(tuple.len(), 2);
-        assert_eq!(*tuple[0].clone().extract_code().unwrap().filename, "f1");
-        assert_eq!(*tuple[1].clone().extract_code().unwrap().filename, "f2");
+        assert_eq!(tuple[0].extract_code().unwrap().filename, "f1");
+        assert_eq!(tuple[1].extract_code().unwrap().filename, "f2");
+
+        // They load different names (`x` and `y`), so only match once those are the same
+        let (f1, f2) = (tuple[0].extract_code().unwrap(), tuple[1].extract_code().unwrap());
+        assert!(!f1.logic_eq(f2));
+        let f2 = &Code {
+            names: f1.names,
+            ..*f2
+        };
+        assert_ne!(f1, f2);
+        assert!(f1.logic_eq(f2));
+        let nested = |code| Code {
+            consts: arena
+                .alloc_slice_copy(&[Obj::Tuple(arena.alloc_slice_copy(&[Obj::Code(code)]))]),
+            ..*f1
+        };
+        assert!(nested(f1).logic_eq(&nested(f2)));
+        let renamed = Code {
+            names: &["z"],
+            filename: "f2",
+            ..*f1
+        };
+        assert!(!renamed.logic_eq(f2));
+        assert!(!nested(f1).logic_eq(&nested(&renamed)));
     }
 
     #[allow(clippy::float_cmp)]
     #[test]
     fn test_dict() {
+        let arena = ObjArena::new();
         let mut input: &[u8] = b"{\xda\x07astring\xfa\x10foo@bar.baz.spam\xda\x06afloat\xe7H\xe1z\x14ns\xbc@\xda\x05anint\xe9\x00\x00\x10\x00\xda\nashortlong\xe9\x02\x00\x00\x00\xda\x05alist[\x01\x00\x00\x00\xfa\x07.zyx.41\xda\x06atuple\xa9\n\xfa\x07.zyx.41r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00r\x0c\x00\x00\x00\xda\x08abooleanF\xda\x08aunicode\xf5\r\x00\x00\x00Andr\xc3\xa8 Previn0";
-        println!("{}", input.len());
-        let result = marshal_load(&mut input);
-        println!("{}", input.len());
-        let dict_ref = result.unwrap().extract_dict().unwrap();
-        let dict = dict_ref.try_read().unwrap();
-        assert_eq!(dict.len(), 8);
-        assert_eq!(
-            *dict[&ObjHashable::String(Arc::new("astring".to_owned()))]
-                .clone()
+        let result = marshal_load_remaining(&arena, &mut input, MarshalLoadExOptions::default());
+        assert!(input.is_empty());
+        let dict = result.unwrap();
+        assert_eq!(dict.extract_dict().unwrap().len(), 8);
+        assert_eq!(
+            dict.get_item(&Obj::String("astring"))
+                .unwrap()
                 .extract_string()
                 .unwrap(),
             "foo@bar.baz.spam"
         );
         assert_eq!(
-            dict[&ObjHashable::String(Arc::new("afloat".to_owned()))]
-                .clone()
+            dict.get_item(&Obj::String("afloat"))
+                .unwrap()
                 .extract_float()
                 .unwrap(),
             7283.43_f64
         );
         assert_eq!(
-            *dict[&ObjHashable::String(Arc::new("anint".to_owned()))]
-                .clone()
+            *dict.get_item(&Obj::String("anint"))
+                .unwrap()
                 .extract_long()
                 .unwrap(),
-            BigInt::from(2).pow(20_u8)
+            pow(Int::from(2), 20)
         );
         assert_eq!(
-            *dict[&ObjHashable::String(Arc::new("ashortlong".to_owned()))]
-                .clone()
+            *dict.get_item(&Obj::String("ashortlong"))
+                .unwrap()
                 .extract_long()
                 .unwrap(),
-            BigInt::from(2)
+            Int::from(2)
         );
 
-        let list_ref = dict[&ObjHashable::String(Arc::new("alist".to_owned()))]
-            .clone()
+        let list = dict.get_item(&Obj::String("alist"))
+            .unwrap()
             .extract_list()
             .unwrap();
-        let list = list_ref.try_read().unwrap();
         assert_eq!(list.len(), 1);
-        assert_eq!(*list[0].clone().extract_string().unwrap(), ".zyx.41");
+        assert_eq!(list[0].extract_string().unwrap(), ".zyx.41");
 
-        let tuple = dict[&ObjHashable::String(Arc::new("atuple".to_owned()))]
-            .clone()
+        let tuple = dict.get_item(&Obj::String("atuple"))
+            .unwrap()
             .extract_tuple()
             .unwrap();
         assert_eq!(tuple.len(), 10);
-        for o in &*tuple {
-            assert_eq!(*o.clone().extract_string().unwrap(), ".zyx.41");
+        for o in tuple {
+            assert_eq!(o.extract_string().unwrap(), ".zyx.41");
         }
+        assert!(!dict
+            .get_item(&Obj::String("aboolean"))
+            .unwrap()
+            .extract_bool()
+            .unwrap());
         assert_eq!(
-            dict[&ObjHashable::String(Arc::new("aboolean".to_owned()))]
-                .clone()
-                .extract_bool()
-                .unwrap(),
-            false
-        );
-        assert_eq!(
-            *dict[&ObjHashable::String(Arc::new("aunicode".to_owned()))]
-                .clone()
+            dict.get_item(&Obj::String("aunicode"))
+                .unwrap()
                 .extract_string()
                 .unwrap(),
             "Andr\u{e8} Previn"
         );
     }
 
-    /// Tests hash implementation
+    /// Tests tuple keys
     #[test]
     fn test_dict_tuple_key() {
-        let dict = loads_unwrap(b"{\xa9\x02\xda\x01a\xda\x01b\xda\x01c0")
-            .extract_dict()
-            .unwrap();
-        assert_eq!(dict.read().unwrap().len(), 1);
+        let arena = ObjArena::new();
+        let dict = loads_unwrap(&arena, b"{\xa9\x02\xda\x01a\xda\x01b\xda\x01c0");
+        assert_eq!(dict.extract_dict().unwrap().len(), 1);
         assert_eq!(
-            *dict.read().unwrap()[&ObjHashable::Tuple(Arc::new(vec![
-                ObjHashable::String(Arc::new("a".to_owned())),
-                ObjHashable::String(Arc::new("b".to_owned()))
-            ]))]
-                .clone()
+            dict.get_item(&Obj::Tuple(&[Obj::String("a"), Obj::String("b")]))
+                .unwrap()
                 .extract_string()
                 .unwrap(),
             "c"
         );
     }
 
+    #[test]
+    fn test_long_size() {
+        let arena = ObjArena::new();
+        // i32::MIN has no absolute value in an i32
+        assert_match!(
+            marshal_loads(&arena, b"l\x00\x00\x00\x80").unwrap_err(),
+            errors::Error::LongSizeOutOfRange { n: i32::MIN }
+        );
+        // The largest counts are allowed, and fail once the input runs out
+        for input in &[b"l\xff\xff\xff\x7f\x01\x00", b"l\x01\x00\x00\x80\x01\x00"] {
+            match marshal_loads(&arena, *input).unwrap_err() {
+                errors::Error::Io(err) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+                err => panic!("{:?}", err),
+            }
+        }
+    }
+
+    /// `NULL` ends a dict, wherever it appears, but is an error anywhere else.
+    #[test]
+    fn test_null() {
+        let arena = ObjArena::new();
+        // {'a': 1}, then the NULL ending the dict
+        assert_eq!(
+            loads_unwrap(&arena, b"{\xda\x01a\xe9\x01\x00\x00\x000"),
+            Obj::Dict(&[(Obj::String("a"), Obj::from_i64(&arena, 1))])
+        );
+        // A NULL value ends the dict too, and its key is dropped
+        assert_eq!(
+            loads_unwrap(&arena, b"[\x02\x00\x00\x00{\xda\x01a0N"),
+            Obj::List(&[Obj::Dict(&[]), Obj::None])
+        );
+        for input in &[
+            &b"[\x02\x00\x00\x00N0"[..],
+            b"(\x01\x00\x00\x000",
+            b")\x010",
+            b"<\x01\x00\x00\x000",
+            b">\x01\x00\x00\x000",
+        ] {
+            assert_match!(
+                marshal_loads(&arena, input).unwrap_err(),
+                errors::Error::UnexpectedNull
+            );
+        }
+    }
+
     // TODO: test_list and test_tuple
 
     #[test]
     fn test_sets() {
-        let set = loads_unwrap(b"<\x08\x00\x00\x00\xda\x05alist\xda\x08aboolean\xda\x07astring\xda\x08aunicode\xda\x06afloat\xda\x05anint\xda\x06atuple\xda\nashortlong").extract_set().unwrap();
-        assert_eq!(set.read().unwrap().len(), 8);
-        let frozenset = loads_unwrap(b">\x08\x00\x00\x00\xda\x06atuple\xda\x08aunicode\xda\x05anint\xda\x08aboolean\xda\x06afloat\xda\x05alist\xda\nashortlong\xda\x07astring").extract_frozenset().unwrap();
+        let arena = ObjArena::new();
+        let set = loads_unwrap(&arena, b"<\x08\x00\x00\x00\xda\x05alist\xda\x08aboolean\xda\x07astring\xda\x08aunicode\xda\x06afloat\xda\x05anint\xda\x06atuple\xda\nashortlong").extract_set().unwrap();
+        assert_eq!(set.len(), 8);
+        let frozenset = loads_unwrap(&arena, b">\x08\x00\x00\x00\xda\x06atuple\xda\x08aunicode\xda\x05anint\xda\x08aboolean\xda\x06afloat\xda\x05alist\xda\nashortlong\xda\x07astring").extract_frozenset().unwrap();
         assert_eq!(frozenset.len(), 8);
         // TODO: check values
     }
 
-    // TODO: test_bytearray, test_memoryview, test_array
+    #[test]
+    fn test_buffers() {
+        // marshal.dumps(x) for x in (bytearray(b"abc"), memoryview(b"abc"),
+        // array.array("B", b"abc")): anything with the buffer protocol is written as bytes
+        let arena = ObjArena::new();
+        assert_eq!(loads_unwrap(&arena, b"s\x03\x00\x00\x00abc"), Obj::Bytes(b"abc"));
+        // marshal.dumps(array.array("h", [1, -2])): just the raw items, without the typecode
+        assert_eq!(
+            loads_unwrap(&arena, b"s\x04\x00\x00\x00\x01\x00\xfe\xff"),
+            Obj::Bytes(b"\x01\x00\xfe\xff")
+        );
+    }
 
     #[test]
     fn test_patch_873224() {
+        let arena = ObjArena::new();
         assert_match!(
-            marshal_loads(b"0").unwrap_err(),
+            marshal_loads(&arena, b"0").unwrap_err(),
             errors::Error::UnexpectedNull
         );
-        let f_err = marshal_loads(b"f").unwrap_err();
+        let f_err = marshal_loads(&arena, b"f").unwrap_err();
         match f_err {
             errors::Error::Io(io_err) => {
                 assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
@@ -728,7 +2289,7 @@ mod test {
             _ => panic!(),
         }
         let int_err =
-            marshal_loads(b"l\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00 ").unwrap_err();
+            marshal_loads(&arena, b"l\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00 ").unwrap_err();
         match int_err {
             errors::Error::Io(io_err) => {
                 assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
@@ -739,8 +2300,9 @@ mod test {
 
     #[test]
     fn test_fuzz() {
+        let arena = ObjArena::new();
         for i in 0..=u8::MAX {
-            println!("{:?}", marshal_loads(&[i]));
+            println!("{:?}", marshal_loads(&arena, &[i]));
         }
     }
 
@@ -748,65 +2310,771 @@ mod test {
     #[cfg(not(debug_assertions))]
     #[test]
     fn test_loads_recursion() {
-        loads_unwrap(&[&b")\x01".repeat(100)[..], b"N"].concat());
-        loads_unwrap(&[&b"(\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
-        loads_unwrap(&[&b"[\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
-        loads_unwrap(&[&b"{N".repeat(100)[..], b"N", &b"0".repeat(100)[..]].concat());
-        loads_unwrap(&[&b">\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
+        let arena = ObjArena::new();
+        loads_unwrap(&arena, &[&b")\x01".repeat(100)[..], b"N"].concat());
+        loads_unwrap(&arena, &[&b"(\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
+        loads_unwrap(&arena, &[&b"[\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
+        loads_unwrap(&arena, &[&b"{N".repeat(100)[..], b"N", &b"0".repeat(100)[..]].concat());
+        loads_unwrap(&arena, &[&b">\x01\x00\x00\x00".repeat(100)[..], b"N"].concat());
 
         assert_match!(
-            marshal_loads(&[&b")\x01".repeat(1048576)[..], b"N"].concat())
+            marshal_loads(&arena, &[&b")\x01".repeat(1048576)[..], b"N"].concat())
                 .unwrap_err(),
             errors::Error::RecursionLimitExceeded
         );
         assert_match!(
-            marshal_loads(&[&b"(\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
+            marshal_loads(&arena, &[&b"(\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
                 .unwrap_err(),
             errors::Error::RecursionLimitExceeded
         );
         assert_match!(
-            marshal_loads(&[&b"[\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
+            marshal_loads(&arena, &[&b"[\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
                 .unwrap_err(),
             errors::Error::RecursionLimitExceeded
         );
         assert_match!(
-            marshal_loads(
-                &[&b"{N".repeat(1048576)[..], b"N", &b"0".repeat(1048576)[..]].concat()
+            marshal_loads(&arena, &[&b"{N".repeat(1048576)[..], b"N", &b"0".repeat(1048576)[..]].concat()
             )
             .unwrap_err(),
             errors::Error::RecursionLimitExceeded
         );
         assert_match!(
-            marshal_loads(&[&b">\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
+            marshal_loads(&arena, &[&b">\x01\x00\x00\x00".repeat(1048576)[..], b"N"].concat())
+                .unwrap_err(),
+            errors::Error::RecursionLimitExceeded
+        );
+    }
+
+    #[test]
+    fn test_truncated_length() {
+        let arena = ObjArena::new();
+        for input in &[
+            &b"a\xff\xff\xff\x7fabc"[..],
+            b"u\xff\xff\xff\xffabc",
+            b"s\x00\x00\x00\x80abc",
+            // Collections too, without trying to allocate room for all the elements
+            b"(\xff\xff\xff\x7fN",
+            b"[\xff\xff\xff\x7fN",
+            b"<\xff\xff\xff\x7fN",
+            b">\xff\xff\xff\x7fN",
+        ] {
+            match marshal_loads(&arena, input).unwrap_err() {
+                errors::Error::Io(io_err) => {
+                    assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
+                }
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_safe() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions::fuzz_safe();
+        for input in &[
+            &b"a\xff\xff\xff\x7fabc"[..],
+            b"(\xff\xff\xff\x7fN",
+            b"<\xff\xff\xff\x7fN",
+            b"l\xff\xff\xff\x7f\x01\x00",
+        ] {
+            assert_match!(
+                marshal_load_ex(&arena, *input, opts).unwrap_err(),
+                errors::Error::AllocLimitExceeded
+            );
+        }
+        assert_match!(
+            marshal_load_ex(&arena, &[&b")\x01".repeat(200)[..], b"N"].concat()[..], opts)
+                .unwrap_err(),
+            errors::Error::RecursionLimitExceeded
+        );
+        loads_unwrap(&arena, &[&b")\x01".repeat(50)[..], b"N"].concat());
+    }
+
+    #[test]
+    fn test_untrusted() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions::untrusted();
+        assert_match!(
+            marshal_load_ex(&arena, &[&b")\x01".repeat(150)[..], b"N"].concat()[..], opts)
                 .unwrap_err(),
             errors::Error::RecursionLimitExceeded
         );
+        assert_match!(
+            marshal_load_ex(&arena, &b"(\xff\xff\xff\x7fN"[..], opts).unwrap_err(),
+            errors::Error::CollectionTooLong { .. }
+        );
+        assert_match!(
+            marshal_load_ex(&arena, &b"NN"[..], opts).unwrap_err(),
+            errors::Error::TrailingData { remaining: 1 }
+        );
+        // A str that isn't UTF-8 doesn't spoil the rest
+        let obj = marshal_load_ex(&arena, &b")\x02u\x01\x00\x00\x00\xffN"[..], opts).unwrap();
+        assert_eq!(obj, Obj::Tuple(&[Obj::RawString(b"\xff"), Obj::None]));
+        assert_eq!(format!("{:?}", obj), "(\"\u{fffd}\", None)");
+    }
+
+    #[test]
+    fn test_max_lens() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions {
+            max_item_len: Some(3),
+            max_collection_len: Some(2),
+            ..MarshalLoadExOptions::default()
+        };
+        marshal_load_ex(&arena, &b"\xda\x03abc"[..], opts).unwrap();
+        assert_match!(
+            marshal_load_ex(&arena, &b"a\x04\x00\x00\x00abcd"[..], opts).unwrap_err(),
+            errors::Error::ItemTooLong { len: 4, max: 3 }
+        );
+        assert_match!(
+            marshal_load_ex(&arena, &b"u\xff\xff\xff\x7f"[..], opts).unwrap_err(),
+            errors::Error::ItemTooLong { len: 0x7fff_ffff, max: 3 }
+        );
+        marshal_load_ex(&arena, &b")\x02NN"[..], opts).unwrap();
+        for input in &[
+            &b")\x03NNN"[..],
+            b"[\x03\x00\x00\x00NNN",
+            b"<\xff\xff\xff\xff",
+            b"{TNFNNN0",
+        ] {
+            assert_match!(
+                marshal_load_ex(&arena, *input, opts).unwrap_err(),
+                errors::Error::CollectionTooLong { max: 2, .. }
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_refs() {
+        let arena = ObjArena::new();
+        let mut input = b")\x14".to_vec();
+        for i in 0..20_u8 {
+            input.extend_from_slice(&[0xa9, 0x01, b'i', i, 0, 0, 0]);
+        }
+        let opts = |max_refs| MarshalLoadExOptions {
+            max_refs: Some(max_refs),
+            ..MarshalLoadExOptions::default()
+        };
+        assert_match!(
+            marshal_load_ex(&arena, &input[..], opts(10)).unwrap_err(),
+            errors::Error::RefLimitExceeded
+        );
+        let obj = marshal_load_ex(&arena, &input[..], opts(20)).unwrap();
+        assert_eq!(obj.extract_tuple().unwrap().len(), 20);
+    }
+
+    #[test]
+    fn test_max_total_input() {
+        /// Like a peer that announced more than it will ever send.
+        struct Stalled;
+        impl Read for Stalled {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                panic!("read past the length");
+            }
+        }
+        let arena = ObjArena::new();
+        let opts = |max| MarshalLoadExOptions {
+            max_total_input: Some(max),
+            ..MarshalLoadExOptions::default()
+        };
+        let input = (&b"s\xff\xff\xff\x7f"[..]).chain(Stalled);
+        assert_match!(
+            marshal_load_ex(&arena, input, opts(1 << 20)).unwrap_err(),
+            errors::Error::InputTooLarge { max: 0x10_0000 }
+        );
+        assert_eq!(marshal_load_ex(&arena, &b"z\x03abc"[..], opts(5)).unwrap(), Obj::String("abc"));
+        assert_match!(
+            marshal_load_ex(&arena, &b"z\x03abc"[..], opts(4)).unwrap_err(),
+            errors::Error::InputTooLarge { max: 4 }
+        );
+    }
+
+    /// See <https://github.com/sollyucko/py-marshal/issues/2>
+    #[test]
+    fn test_invalid_refs() {
+        let arena = ObjArena::new();
+        // A tuple containing a ref to itself, which is still a placeholder while being filled
+        assert_match!(
+            marshal_loads(&arena, b"\xa9\x01r\x00\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        // Refs to slots that haven't been reserved yet
+        assert_match!(
+            marshal_loads(&arena, b"r\x00\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        assert_match!(
+            marshal_loads(&arena, b")\x02\xe9\x01\x00\x00\x00r\x01\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        // `a = []; a.append(a)` can't be represented without cycles
+        assert_match!(
+            marshal_loads(&arena, b"\xdb\x01\x00\x00\x00r\x00\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        // A backward ref to a finished object is fine
+        let obj = loads_unwrap(&arena, b")\x02\xe9\x01\x00\x00\x00r\x00\x00\x00\x00");
+        assert_eq!(obj.extract_tuple().unwrap()[1], Obj::from_i64(&arena, 1));
+    }
+
+    #[test]
+    fn test_load_shared_refs() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions::default();
+        let mut refs = Vec::new();
+        // A short ascii "a" with FLAG_REF, then a blob that is only a ref to it
+        let first = marshal_load_shared_refs(&arena, &b"\xda\x01a"[..], opts, &mut refs).unwrap();
+        assert_eq!(first, Obj::String("a"));
+        assert_eq!(refs, [Obj::String("a")]);
+        let second = b")\x02r\x00\x00\x00\x00\xe9\x01\x00\x00\x00";
+        assert_match!(marshal_loads(&arena, second).unwrap_err(), errors::Error::InvalidRef);
+        let obj = marshal_load_shared_refs(&arena, &second[..], opts, &mut refs).unwrap();
+        assert_eq!(obj.extract_tuple().unwrap(), [Obj::String("a"), Obj::from_i64(&arena, 1)]);
+        assert_eq!(refs.len(), 2);
+        // A failed load leaves the table as it was
+        assert_match!(
+            marshal_load_shared_refs(&arena, &b")\x02\xe9\x02\x00\x00\x00r\x09\x00\x00\x00"[..], opts, &mut refs)
+                .unwrap_err(),
+            errors::Error::InvalidRef
+        );
+        assert_eq!(refs.len(), 2);
+        // Seeded refs count towards max_refs
+        let opts = MarshalLoadExOptions { max_refs: Some(2), ..opts };
+        assert!(marshal_load_shared_refs(&arena, &b"\xe9\x03\x00\x00\x00"[..], opts, &mut refs).is_err());
+        assert_eq!(refs.len(), 2);
+    }
+
+    #[test]
+    fn test_i128_bounds() {
+        let arena = ObjArena::new();
+        for &x in &[0, 1, -1, i128::from(i64::MIN) - 1, i128::MAX, i128::MIN] {
+            let obj = Obj::from_bigint(&arena, Int::from(x));
+            let bytes = crate::write::marshal_dumps(&obj, 4).unwrap();
+            assert_eq!(loads_unwrap(&arena, &bytes), obj);
+        }
+        // 2**127, one more than i128::MAX: nine 15-bit digits, the last being 2**7
+        let mut input = b"l\x09\x00\x00\x00".to_vec();
+        input.extend_from_slice(&[0; 16]);
+        input.extend_from_slice(b"\x80\x00");
+        let past_max = input.clone();
+        // -2**127 is i128::MIN
+        input[1] = 0xf7;
+        input[2..5].copy_from_slice(b"\xff\xff\xff");
+        assert_eq!(loads_unwrap(&arena, &input), Obj::from_bigint(&arena, Int::from(i128::MIN)));
+        // and -2**127 - 1 is one less
+        input[5] = 1;
+        let past_min = input;
+        // 2**120 - 1 and 2**135 - 1, with every digit set
+        let long = crate::fixtures::big_long(8);
+        assert_eq!(*loads_unwrap(&arena, &long).extract_long().unwrap(), Int::from((1_i128 << 120) - 1));
+        let longer = crate::fixtures::big_long(9);
+        #[cfg(not(feature = "bigint"))]
+        for input in &[past_max, past_min, longer] {
+            assert_match!(marshal_loads(&arena, input).unwrap_err(), errors::Error::IntegerTooLarge);
+        }
+        #[cfg(feature = "bigint")]
+        {
+            assert_eq!(*loads_unwrap(&arena, &past_max).extract_long().unwrap(), Int::from(i128::MAX) + 1);
+            assert_eq!(*loads_unwrap(&arena, &past_min).extract_long().unwrap(), Int::from(i128::MIN) - 1);
+            assert_eq!(*loads_unwrap(&arena, &longer).extract_long().unwrap(), (Int::from(1) << 135) - 1);
+        }
+    }
+
+    #[test]
+    fn test_load_buffered() {
+        let arena = ObjArena::new();
+        let obj = marshal_load_buffered(
+            &arena,
+            &b"\xa9\x02\xda\x03abcr\x01\x00\x00\x00"[..],
+            MarshalLoadExOptions::default(),
+        )
+        .unwrap();
+        let abc = Obj::from_str(&arena, "abc");
+        assert_eq!(obj, Obj::Tuple(&[abc, abc]));
+    }
+
+    #[test]
+    fn test_strict_interning() {
+        let arena = ObjArena::new();
+        let strict = MarshalLoadExOptions {
+            strict_interning: true,
+            ..MarshalLoadExOptions::default()
+        };
+        for input in &[&b"Z\x01a"[..], b"A\x01\x00\x00\x00a", b"t\x01\x00\x00\x00a"] {
+            assert_eq!(loads_unwrap(&arena, input), Obj::String("a"));
+            assert_match!(
+                marshal_load_ex(&arena, *input, strict).unwrap_err(),
+                errors::Error::InconsistentInterning
+            );
+        }
+        // Non-interned strings don't need a ref
+        marshal_load_ex(&arena, &b"z\x01a"[..], strict).unwrap();
+
+        let input = crate::fixtures::many_codeobjects(10);
+        marshal_load_ex(
+            &arena,
+            &input[..],
+            MarshalLoadExOptions {
+                has_posonlyargcount: false,
+                ..strict
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_track_ref_flags() {
+        let arena = ObjArena::new();
+        // marshal.dumps(("ab", "ab", {1: None}))
+        let input = b")\x03\xda\x02abr\x00\x00\x00\x00{\xe9\x01\x00\x00\x00N0";
+        let (obj, ref_flags) = marshal_load_tracked(
+            &arena,
+            &input[..],
+            MarshalLoadExOptions {
+                track_ref_flags: true,
+                ..MarshalLoadExOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(obj, loads_unwrap(&arena, input));
+        assert_eq!(
+            ref_flags.unwrap(),
+            [false, true, false, false, true, false, false]
+        );
+
+        let (_, ref_flags) =
+            marshal_load_tracked(&arena, &input[..], MarshalLoadExOptions::default()).unwrap();
+        assert!(ref_flags.is_none());
+    }
+
+    #[test]
+    fn test_load_remaining() {
+        let arena = ObjArena::new();
+        let mut input: &[u8] = b"\xe9\x01\x00\x00\x00)\x02r\x00\x00\x00\x00NTrailing";
+        let opts = MarshalLoadExOptions::default();
+        let first = marshal_load_remaining(&arena, &mut input, opts).unwrap();
+        assert_eq!(first, Obj::from_i64(&arena, 1));
+        assert_eq!(input.len(), 16);
+        // Each call has its own ref table
+        assert_match!(
+            marshal_load_remaining(&arena, &mut input, opts).unwrap_err(),
+            errors::Error::InvalidRef
+        );
+
+        let mut input: &[u8] = b"N\xda\x01aTrailing";
+        assert!(marshal_load_remaining(&arena, &mut input, opts).unwrap().is_none());
+        assert_eq!(marshal_load_remaining(&arena, &mut input, opts).unwrap(), Obj::String("a"));
+        assert_eq!(input, b"Trailing");
+    }
+
+    #[test]
+    fn test_load_incremental() {
+        use std::task::Poll;
+        // marshal.dumps((1, 'abc')) twice, then [None]
+        let input = b"\xa9\x02\xe9\x01\x00\x00\x00\xda\x03abc\xa9\x02\xe9\x01\x00\x00\x00\xda\x03abc[\x01\x00\x00\x00N";
+        let arena = ObjArena::new();
+        let expected = Obj::Tuple(&[Obj::from_i64(&arena, 1), Obj::String("abc")]);
+        let mut loader = marshal_load_incremental(&arena, MarshalLoadExOptions::default());
+        assert_match!(loader.push_bytes(b""), Poll::Pending);
+        // One byte at a time: nothing until the last byte of the first object
+        for &byte in &input[..11] {
+            assert_match!(loader.push_bytes(&[byte]), Poll::Pending);
+        }
+        match loader.push_bytes(&input[11..12]) {
+            Poll::Ready(Ok(obj)) => assert_eq!(obj, expected),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(loader.buffered(), b"");
+        // Everything else at once: the second object, with the start of the third left over
+        match loader.push_bytes(&input[12..27]) {
+            Poll::Ready(Ok(obj)) => assert_eq!(obj, expected),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(loader.buffered(), b"[\x01\x00");
+        assert_match!(loader.push_bytes(b""), Poll::Pending);
+        match loader.push_bytes(&input[27..]) {
+            Poll::Ready(Ok(obj)) => assert_eq!(obj, Obj::List(&[Obj::None])),
+            other => panic!("{:?}", other),
+        }
+
+        // Errors other than running out of input are reported right away
+        let mut loader = marshal_load_incremental(&arena, MarshalLoadExOptions::default());
+        assert_match!(
+            loader.push_bytes(b"[\x01\x00\x00\x00\xff"),
+            Poll::Ready(Err(errors::Error::InvalidType { spec: 0x7f }))
+        );
+    }
+
+    #[test]
+    fn test_load_sequence() {
+        let arena = ObjArena::new();
+        let objs = marshal_load_sequence(
+            &arena,
+            &b"NT\xe9\x01\x00\x00\x00"[..],
+            MarshalLoadExOptions::default(),
+        )
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(objs.len(), 3);
+        assert!(objs[0].is_none());
+        assert!(objs[1].extract_bool().unwrap());
+        assert_eq!(*objs[2].extract_long().unwrap(), Int::from(1));
+
+        let mut truncated =
+            marshal_load_sequence(&arena, &b"Ni\x01\x00"[..], MarshalLoadExOptions::default());
+        assert!(truncated.next().unwrap().unwrap().is_none());
+        match truncated.next().unwrap().unwrap_err() {
+            errors::Error::Io(io_err) => {
+                assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
+            }
+            _ => panic!(),
+        }
+        assert!(truncated.next().is_none());
+
+        assert!(marshal_load_sequence(&arena, &b""[..], MarshalLoadExOptions::default())
+            .next()
+            .is_none());
     }
 
     #[test]
     fn test_invalid_longs() {
+        let arena = ObjArena::new();
         assert_match!(
-            marshal_loads(b"l\x02\x00\x00\x00\x00\x00\x00\x00")
+            marshal_loads(&arena, b"l\x02\x00\x00\x00\x00\x00\x00\x00")
                 .unwrap_err(),
             errors::Error::UnnormalizedLong
         );
+        // Digits are 15 bits, so 0x8000 would spill into the next one
+        assert_match!(
+            marshal_loads(&arena, b"l\x02\x00\x00\x00\x00\x80\x01\x00").unwrap_err(),
+            errors::Error::DigitOutOfRange { digit: 0x8000 }
+        );
+        assert_eq!(
+            *loads_unwrap(&arena, b"l\x02\x00\x00\x00\xff\x7f\x01\x00").extract_long().unwrap(),
+            Int::from(0xffff)
+        );
     }
     
     // See https://github.com/sollyucko/py-marshal/issues/2
     #[test]
     fn test_issue_2_ref_demarshalling_ordering_previously_broken() {
-        let list_ref = marshal_loads(b"\xdb\x02\x00\x00\x00\xda\x01ar\x01\x00\x00\x00").unwrap().extract_list().unwrap();
-        let list = list_ref.try_read().unwrap();
+        let arena = ObjArena::new();
+        let list = marshal_loads(&arena, b"\xdb\x02\x00\x00\x00\xda\x01ar\x01\x00\x00\x00").unwrap().extract_list().unwrap();
         assert_eq!(list.len(), 2);
-        assert_eq!(*list[0].clone().extract_string().unwrap(), "a");
-        assert_eq!(*list[1].clone().extract_string().unwrap(), "a");
+        assert_eq!(list[0].extract_string().unwrap(), "a");
+        assert_eq!(list[1].extract_string().unwrap(), "a");
     }
     #[test]
     fn test_issue_2_ref_demarshalling_ordering_previously_working() {
-        let list_ref = marshal_loads(b"[\x02\x00\x00\x00\xda\x01ar\x00\x00\x00\x00").unwrap().extract_list().unwrap();
-        let list = list_ref.try_read().unwrap();
+        let arena = ObjArena::new();
+        let list = marshal_loads(&arena, b"[\x02\x00\x00\x00\xda\x01ar\x00\x00\x00\x00").unwrap().extract_list().unwrap();
         assert_eq!(list.len(), 2);
-        assert_eq!(*list[0].clone().extract_string().unwrap(), "a");
-        assert_eq!(*list[1].clone().extract_string().unwrap(), "a");
+        assert_eq!(list[0].extract_string().unwrap(), "a");
+        assert_eq!(list[1].extract_string().unwrap(), "a");
+    }
+
+    #[test]
+    fn test_load_path() {
+        let arena = ObjArena::new();
+        let path = std::env::temp_dir().join("py_marshal_test_load_path.bin");
+        std::fs::write(&path, crate::fixtures::large_dict(100)).unwrap();
+        let result = marshal_load_path(&arena, &path, MarshalLoadExOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap().extract_dict().unwrap().len(), 100);
+        assert_match!(
+            marshal_load_path(&arena, &path, MarshalLoadExOptions::default()).unwrap_err(),
+            errors::Error::Io(_)
+        );
+    }
+
+    #[test]
+    fn test_load_stats() {
+        let arena = ObjArena::new();
+        let input = crate::fixtures::nested_list(10);
+        let (_, stats) =
+            marshal_load_with_stats(&arena, &input[..], MarshalLoadExOptions::default()).unwrap();
+        // Ten lists and the None inside them
+        assert_eq!(stats.max_depth, 11);
+        assert_eq!(stats.refs, 0);
+        let exact = MarshalLoadExOptions {
+            max_depth: stats.max_depth,
+            ..MarshalLoadExOptions::default()
+        };
+        assert!(marshal_load_ex(&arena, &input[..], exact).is_ok());
+        let too_low = MarshalLoadExOptions {
+            max_depth: stats.max_depth - 1,
+            ..MarshalLoadExOptions::default()
+        };
+        assert_match!(
+            marshal_load_ex(&arena, &input[..], too_low).unwrap_err(),
+            errors::Error::RecursionLimitExceeded
+        );
+
+        let input = crate::fixtures::many_codeobjects(3);
+        let opts = MarshalLoadExOptions::for_version(MarshalVersion::Py37);
+        let (_, stats) = marshal_load_with_stats(&arena, &input[..], opts).unwrap();
+        // The tuple, a code object, its names tuple and the strings in it
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!(stats.refs, 11);
+        assert_eq!(stats.consumed, input.len() as u64);
+    }
+
+    #[test]
+    fn test_load_counted() {
+        let arena = ObjArena::new();
+        // Two objects back to back, each counted on its own
+        let input: &[u8] = b")\x02\xe9\x01\x00\x00\x00z\x01as\x03\x00\x00\x00abc";
+        let opts = MarshalLoadExOptions::default();
+        let (obj, n) = marshal_load_counted(&arena, input, opts).unwrap();
+        assert_eq!(obj, Obj::Tuple(&[Obj::from_i64(&arena, 1), Obj::String("a")]));
+        assert_eq!(n, 10);
+        let (obj, n) = marshal_load_counted(&arena, &input[10..], opts).unwrap();
+        assert_eq!(obj, Obj::Bytes(b"abc"));
+        assert_eq!(n, 8);
+        // Strings borrowed from the input count too
+        let (_, _, stats) = load(&arena, input, borrow_slice, None, opts).unwrap();
+        assert_eq!(stats.consumed, 10);
+    }
+
+    #[test]
+    fn test_require_eof() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions {
+            require_eof: true,
+            ..MarshalLoadExOptions::default()
+        };
+        assert!(marshal_load_ex(&arena, &b"N"[..], opts).unwrap().is_none());
+        assert_match!(
+            marshal_load_ex(&arena, &b"i\x01\x00\x00\x00NN"[..], opts).unwrap_err(),
+            errors::Error::TrailingData { remaining: 2 }
+        );
+        // Ignored by default
+        assert!(marshal_loads(&arena, b"NN").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_immutable_refs() {
+        let arena = ObjArena::new();
+        // s = sys.intern("spam"); marshal.dumps((s, s))
+        let tuple = loads_unwrap(&arena, b")\x02\xda\x04spamr\x00\x00\x00\x00")
+            .extract_tuple()
+            .unwrap();
+        let (a, b) = (tuple[0].extract_string().unwrap(), tuple[1].extract_string().unwrap());
+        assert_eq!(b, "spam");
+        assert!(std::ptr::eq(a, b));
+
+        // Ints and floats are flagged too, but read before they're added to the table
+        // y = 12345; x = 10**20; f = 1.5; marshal.dumps((y, x, f, y, x, f))
+        let arena = ObjArena::new();
+        let big = pow(Int::from(10), 20);
+        let expected = [
+            Obj::from_i64(&arena, 12345),
+            Obj::Long(&big),
+            Obj::Float(1.5),
+        ];
+        let tuple = loads_unwrap(
+            &arena,
+            b")\x06\xe990\x00\x00\xec\x05\x00\x00\x00\x00\x00 F\xb5x:^V\x00\xe7\x00\x00\x00\x00\x00\x00\xf8?r\x00\x00\x00\x00r\x01\x00\x00\x00r\x02\x00\x00\x00",
+        )
+        .extract_tuple()
+        .unwrap();
+        assert_eq!(tuple[..3], expected);
+        assert_eq!(tuple[3..], expected);
+    }
+
+    #[test]
+    fn test_set_refs() {
+        // Sets and frozensets both reserve their slot before reading their elements, so the
+        // element is ref 1, and the set is ref 0
+        let arena = ObjArena::new();
+        // f = frozenset({'a'}); marshal.dumps((f, f))
+        let tuple = loads_unwrap(&arena, b")\x02\xbe\x01\x00\x00\x00\xda\x01ar\x00\x00\x00\x00")
+            .extract_tuple()
+            .unwrap();
+        assert_eq!(tuple[0], Obj::FrozenSet(&[Obj::String("a")]));
+        assert!(std::ptr::eq(
+            tuple[0].extract_frozenset().unwrap(),
+            tuple[1].extract_frozenset().unwrap()
+        ));
+        // s = {'b'}; marshal.dumps([s, s])
+        let list = loads_unwrap(&arena, b"[\x02\x00\x00\x00\xbc\x01\x00\x00\x00\xda\x01br\x00\x00\x00\x00")
+            .extract_list()
+            .unwrap();
+        assert_eq!(list[0], Obj::Set(&[Obj::String("b")]));
+        assert!(std::ptr::eq(list[0].extract_set().unwrap(), list[1].extract_set().unwrap()));
+    }
+
+    #[test]
+    fn test_interned_string_refs() {
+        let arena = ObjArena::new();
+        // s = sys.intern('spam'); marshal.dumps((s, s, s))
+        let tuple = loads_unwrap(&arena, b")\x03\xda\x04spamr\x00\x00\x00\x00r\x00\x00\x00\x00")
+            .extract_tuple()
+            .unwrap();
+        assert_eq!(tuple, [Obj::String("spam"); 3]);
+        let first = tuple[0].extract_string().unwrap();
+        for x in &tuple[1..] {
+            assert!(std::ptr::eq(x.extract_string().unwrap(), first));
+        }
+
+        // Unlike containers, a string only gets its slot once it's read, so after a flagged
+        // list (ref 0) and the flagged tuple around the string (ref 1), the string is ref 2.
+        // t = (s,); marshal.dumps([t, s, t]), with the list flagged too
+        let list = loads_unwrap(
+            &arena,
+            b"\xdb\x03\x00\x00\x00\xa9\x01\xda\x04spamr\x02\x00\x00\x00r\x01\x00\x00\x00",
+        )
+        .extract_list()
+        .unwrap();
+        let t = Obj::Tuple(&[Obj::String("spam")]);
+        assert_eq!(list, [t, Obj::String("spam"), t]);
+        assert!(std::ptr::eq(
+            list[0].extract_tuple().unwrap()[0].extract_string().unwrap(),
+            list[1].extract_string().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_empty_collection_refs() {
+        // The slot reserved for a flagged container is filled in once it's read, even when it
+        // turns out to be empty
+        let arena = ObjArena::new();
+        // marshal.dumps(((), ())): the outer tuple is flagged too, so the inner one is ref 1
+        let tuple = loads_unwrap(&arena, b"\xa9\x02\xa9\x00r\x01\x00\x00\x00");
+        assert_eq!(tuple, Obj::Tuple(&[Obj::Tuple(&[]), Obj::Tuple(&[])]));
+
+        // e = frozenset(); marshal.dumps((e, e))
+        let tuple = loads_unwrap(&arena, b")\x02\xbe\x00\x00\x00\x00r\x00\x00\x00\x00");
+        assert_eq!(tuple, Obj::Tuple(&[Obj::FrozenSet(&[]), Obj::FrozenSet(&[])]));
+
+        // d = {}; marshal.dumps([d, d])
+        let list = loads_unwrap(&arena, b"[\x02\x00\x00\x00\xfb0r\x00\x00\x00\x00");
+        assert_eq!(list, Obj::List(&[Obj::Dict(&[]), Obj::Dict(&[])]));
+
+        // A container can't refer to itself while it's still being read
+        assert_match!(
+            marshal_loads(&arena, b"\xa9\x01r\x00\x00\x00\x00").unwrap_err(),
+            errors::Error::InvalidRef
+        );
+    }
+
+    #[test]
+    fn test_code_field_type() {
+        let arena = ObjArena::new();
+        // compile("x = 1", "m.py", "exec") by Python 3.10, with co_code changed to a str
+        let err = marshal_loads(&arena, b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00u\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00N)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00").unwrap_err();
+        assert_match!(
+            err,
+            errors::Error::CodeFieldType { field: "co_code", expected: "bytes", actual: Obj::String(_) }
+        );
+        assert_eq!(err.to_string(), "Expected bytes for co_code, got str");
+        // ... and with co_code changed to the tuple (None,)
+        let err = marshal_loads(&arena, b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00)\x01N)\x02\xe9\x01\x00\x00\x00N)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00").unwrap_err();
+        assert_match!(
+            err,
+            errors::Error::CodeFieldType { field: "co_code", expected: "bytes", actual: Obj::Tuple(&[Obj::None]) }
+        );
+        assert_eq!(err.to_string(), "Expected bytes for co_code, got tuple");
+        // ... and with co_lnotab changed to an empty tuple, which isn't mistaken for co_code
+        let err = marshal_loads(&arena, b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00N)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00)\x00").unwrap_err();
+        assert_eq!(err.to_string(), "Expected bytes for co_lnotab, got tuple");
+        // ... and with the name in co_names changed to an int
+        let err = marshal_loads(&arena, b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00N)\x01\xe9\x02\x00\x00\x00\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00").unwrap_err();
+        assert_eq!(err.to_string(), "Expected tuple of str for co_names, got int");
+    }
+
+    #[test]
+    fn test_likely_version_mismatch() {
+        let arena = ObjArena::new();
+        // A Python 2.7 code object: argcount, nlocals, stacksize, flags, then a 300 byte co_code.
+        // Read as Python 3, the bytes' type code and length end up in co_stacksize.
+        let mut input = b"c\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x2c\x01\x00\x00".to_vec();
+        input.extend_from_slice(&[0; 300]);
+        let opts = MarshalLoadExOptions { max_code_count: Some(PLAUSIBLE_CODE_COUNT), ..MarshalLoadExOptions::default() };
+        let err = marshal_load_ex(&arena, &input[..], opts).unwrap_err();
+        assert_match!(
+            err,
+            errors::Error::LikelyVersionMismatch { version: MarshalVersion::Py310, field: "co_stacksize", value: 0x1_2c73 }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Implausible co_stacksize (76915) in a code object; was it written by a Python version other than Py310?"
+        );
+        // Large counts are fine unless there's a limit
+        let code = crate::CodeBuilder::new().with_stacksize(0x1_0000).finish();
+        let input = crate::write::marshal_dumps(&Obj::Code(&code), 4).unwrap();
+        assert_eq!(loads_unwrap(&arena, &input).extract_code().unwrap().stacksize, 0x1_0000);
+        assert_match!(
+            marshal_load_ex(&arena, &input[..], opts).unwrap_err(),
+            errors::Error::LikelyVersionMismatch { field: "co_stacksize", value: 0x1_0000, .. }
+        );
+        // More positional-only arguments than arguments
+        let input = b"c\x01\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        assert_match!(
+            marshal_loads(&arena, input).unwrap_err(),
+            errors::Error::LikelyVersionMismatch { field: "co_posonlyargcount", value: 2, .. }
+        );
+    }
+
+    #[test]
+    fn test_recovery() {
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions {
+            recovery: true,
+            require_eof: true,
+            ..MarshalLoadExOptions::default()
+        };
+        let load = |input: &[u8]| marshal_load_ex(&arena, input, opts).unwrap();
+        assert_match!(
+            marshal_loads(&arena, b"[\x02\x00\x00\x00N\x01").unwrap_err(),
+            errors::Error::InvalidType { spec: 1 }
+        );
+        // Whatever comes after the unknown byte is left alone, even with require_eof
+        assert_eq!(
+            load(b")\x02[\x03\x00\x00\x00N\x81NN)\x00"),
+            Obj::Tuple(&[Obj::List(&[Obj::None, Obj::Unknown { tag: 0x81 }])])
+        );
+        assert_eq!(load(b"?"), Obj::Unknown { tag: b'?' });
+        assert_eq!(
+            load(b"{N\x01"),
+            Obj::Dict(&[(Obj::None, Obj::Unknown { tag: 1 })])
+        );
+        assert_eq!(
+            load(b"{\x01N"),
+            Obj::Dict(&[(Obj::Unknown { tag: 1 }, Obj::Unknown { tag: 1 })])
+        );
+        assert_eq!(format!("{:?}", load(b"<\x01\x00\x00\x00\x01")), "{<unknown type code 0x01>}");
+
+        // compile("x = 1", "m.py", "exec") by Python 3.10, with the None in co_consts corrupted
+        let code = load(b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00\x01)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00")
+            .extract_code()
+            .unwrap();
+        assert_eq!(code.code, b"d\x00Z\x00d\x01S\x00");
+        assert_eq!(code.consts, [Obj::from_i64(&arena, 1), Obj::Unknown { tag: 1 }]);
+        assert!(code.names.is_empty());
+        assert_eq!(code.filename, "<string>");
+    }
+
+    #[test]
+    fn test_load_expected_type() {
+        // compile("x = 1", "m.py", "exec") and {'a': 1}, by Python 3.10
+        const CODE: &[u8] = b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xe9\x01\x00\x00\x00N)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xfa\x04m.py\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00";
+        const DICT: &[u8] = b"{\xda\x01a\xe9\x01\x00\x00\x000";
+        let arena = ObjArena::new();
+        let opts = MarshalLoadExOptions::default();
+
+        let code = marshal_load_code(&arena, CODE, opts).unwrap();
+        assert_eq!(code.names, ["x"]);
+        assert_match!(
+            marshal_load_dict(&arena, CODE, opts).unwrap_err(),
+            errors::Error::TypeError(Obj::Code(_))
+        );
+
+        let dict = marshal_load_dict(&arena, DICT, opts).unwrap();
+        assert_eq!(dict, [(Obj::String("a"), Obj::from_i64(&arena, 1))]);
+        assert_match!(
+            marshal_load_code(&arena, DICT, opts).unwrap_err(),
+            errors::Error::TypeError(Obj::Dict(_))
+        );
     }
 }
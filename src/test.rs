@@ -1,10 +1,6 @@
-use super::{Code, CodeFlags, Obj, ObjHashable};
-use num_bigint::BigInt;
+use super::{obj_semantic_eq, py_float_eq, Code, Int, CodeBuilder, CodeFlags, Obj, ObjArena, TypeMismatch};
+use std::convert::TryFrom;
 use num_complex::Complex;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, RwLock},
-};
 
 #[test]
 fn test_debug_repr() {
@@ -13,83 +9,117 @@ fn test_debug_repr() {
     assert_eq!(format!("{:?}", Obj::Ellipsis), "Ellipsis");
     assert_eq!(format!("{:?}", Obj::Bool(true)), "True");
     assert_eq!(format!("{:?}", Obj::Bool(false)), "False");
+    assert_eq!(format!("{:?}", Obj::Long(&Int::from(-123))), "-123");
+    assert_eq!(format!("{:?}", Obj::Tuple(&[])), "()");
+    assert_eq!(format!("{:?}", Obj::Tuple(&[Obj::Bool(true)])), "(True,)");
     assert_eq!(
-        format!("{:?}", Obj::Long(Arc::new(BigInt::from(-123)))),
-        "-123"
-    );
-    assert_eq!(format!("{:?}", Obj::Tuple(Arc::new(vec![]))), "()");
-    assert_eq!(
-        format!("{:?}", Obj::Tuple(Arc::new(vec![Obj::Bool(true)]))),
-        "(True,)"
-    );
-    assert_eq!(
-        format!(
-            "{:?}",
-            Obj::Tuple(Arc::new(vec![Obj::Bool(true), Obj::None]))
-        ),
+        format!("{:?}", Obj::Tuple(&[Obj::Bool(true), Obj::None])),
         "(True, None)"
     );
+    assert_eq!(format!("{:?}", Obj::List(&[Obj::Bool(true)])), "[True]");
     assert_eq!(
         format!(
             "{:?}",
-            Obj::List(Arc::new(RwLock::new(vec![Obj::Bool(true)])))
-        ),
-        "[True]"
-    );
-    assert_eq!(
-        format!(
-            "{:?}",
-            Obj::Dict(Arc::new(RwLock::new(
-                vec![(
-                    ObjHashable::Bool(true),
-                    Obj::Bytes(Arc::new(Vec::from(b"a" as &[u8])))
-                )]
-                .into_iter()
-                .collect::<HashMap<_, _>>()
-            )))
+            Obj::Dict(&[(Obj::Bool(true), Obj::Bytes(b"a"))])
         ),
         "{True: b\"a\"}"
     );
+    assert_eq!(format!("{:?}", Obj::Set(&[Obj::Bool(true)])), "{True}");
     assert_eq!(
-        format!(
-            "{:?}",
-            Obj::Set(Arc::new(RwLock::new(
-                vec![ObjHashable::Bool(true)]
-                    .into_iter()
-                    .collect::<HashSet<_>>()
-            )))
-        ),
-        "{True}"
-    );
-    assert_eq!(
-        format!(
-            "{:?}",
-            Obj::FrozenSet(Arc::new(
-                vec![ObjHashable::Bool(true)]
-                    .into_iter()
-                    .collect::<HashSet<_>>()
-            ))
-        ),
+        format!("{:?}", Obj::FrozenSet(&[Obj::Bool(true)])),
         "frozenset({True})"
     );
-    assert_eq!(format!("{:?}", Obj::Code(Arc::new(Code {
+    assert_eq!(format!("{:?}", Obj::Code(&Code {
         argcount: 0,
         posonlyargcount: 1,
         kwonlyargcount: 2,
         nlocals: 3,
         stacksize: 4,
         flags: CodeFlags::NESTED | CodeFlags::COROUTINE,
-        code: Arc::new(Vec::from(b"abc" as &[u8])),
-        consts: Arc::new(vec![Obj::Bool(true)]),
-        names: vec![],
-        varnames: vec![Arc::new("a".to_owned())],
-        freevars: vec![Arc::new("b".to_owned()), Arc::new("c".to_owned())],
-        cellvars: vec![Arc::new("de".to_owned())],
-        filename: Arc::new("xyz.py".to_owned()),
-        name: Arc::new("fgh".to_owned()),
+        code: b"abc",
+        consts: &[Obj::Bool(true)],
+        names: &[],
+        varnames: &["a"],
+        freevars: &["b", "c"],
+        cellvars: &["de"],
+        filename: "xyz.py",
+        name: "fgh",
+        qualname: "",
         firstlineno: 5,
-        lnotab: Arc::new(vec![255, 0, 45, 127, 0, 73]),
-    }))), "code(argcount=0, posonlyargcount=1, kwonlyargcount=2, nlocals=3, stacksize=4, flags=NESTED | COROUTINE, code=b\"abc\", consts=[True], names=[], varnames=[\"a\"], freevars=[\"b\", \"c\"], cellvars=[\"de\"], filename=\"xyz.py\", name=\"fgh\", firstlineno=5, lnotab=bytes([255, 0, 45, 127, 0, 73]))");
+        lnotab: &[255, 0, 45, 127, 0, 73],
+        exceptiontable: &[],
+    })), "code(argcount=0, posonlyargcount=1, kwonlyargcount=2, nlocals=3, stacksize=4, flags=NESTED | COROUTINE, code=b\"abc\", consts=[True], names=[], varnames=[\"a\"], freevars=[\"b\", \"c\"], cellvars=[\"de\"], filename=\"xyz.py\", name=\"fgh\", qualname=\"\", firstlineno=5, lnotab=bytes([255, 0, 45, 127, 0, 73]), exceptiontable=bytes([]))");
+}
+
+#[test]
+fn test_pretty() {
+    let inner = [Obj::Bool(true), Obj::None];
+    let obj = Obj::List(&[Obj::Tuple(&inner), Obj::Dict(&[(Obj::String("k"), Obj::Tuple(&[]))])]);
+    assert_eq!(
+        obj.pretty(),
+        "[\n    (\n        True,\n        None,\n    ),\n    {\n        \"k\": (),\n    },\n]"
+    );
+    let arena = ObjArena::new();
+    let consts = [Obj::Tuple(&inner)];
+    let code = CodeBuilder::new()
+        .with_stacksize(1)
+        .with_flags(CodeFlags::NOFREE)
+        .with_code(b"d\x00S\x00")
+        .with_consts(&consts)
+        .with_lnotab(&[2, 1])
+        .build(&arena);
+    assert_eq!(
+        Obj::Code(code).pretty(),
+        r#"code(
+    argcount=0,
+    posonlyargcount=0,
+    kwonlyargcount=0,
+    nlocals=0,
+    stacksize=1,
+    flags=NOFREE,
+    code=b"d\x00S\x00",
+    consts=[
+        (
+            True,
+            None,
+        ),
+    ],
+    names=[],
+    varnames=[],
+    freevars=[],
+    cellvars=[],
+    filename="<string>",
+    name="<module>",
+    qualname="",
+    firstlineno=1,
+    lnotab=bytes([2, 1]),
+    exceptiontable=bytes([]),
+)"#
+    );
+}
+
+#[test]
+fn test_debug_repr_py311() {
+    // The lambda in `def f(a): return lambda: a`, as read from Python 3.11
+    let code = CodeBuilder::new()
+        .with_stacksize(1)
+        .with_flags(CodeFlags::OPTIMIZED | CodeFlags::NEWLOCALS | CodeFlags::NESTED)
+        .with_code(b"\x95\x01\x97\x00\x89\x00S\x00")
+        .with_freevars(&["a"])
+        .with_filename("m.py")
+        .with_name("<lambda>")
+        .with_qualname("f.<locals>.<lambda>")
+        .with_exceptiontable(b"\x83\x0e")
+        .finish();
+    assert_eq!(format!("{:?}", Obj::Code(&code)), "code(argcount=0, posonlyargcount=0, kwonlyargcount=0, nlocals=0, stacksize=1, flags=OPTIMIZED | NEWLOCALS | NESTED, code=b\"\\x95\\x01\\x97\\x00\\x89\\x00S\\x00\", consts=[], names=[], varnames=[], freevars=[\"a\"], cellvars=[], filename=\"m.py\", name=\"<lambda>\", qualname=\"f.<locals>.<lambda>\", firstlineno=1, lnotab=bytes([]), exceptiontable=bytes([131, 14]))");
+    let pretty = Obj::Code(&code).pretty();
+    assert!(pretty.contains("\n    qualname=\"f.<locals>.<lambda>\",\n"), "{}", pretty);
+    assert!(pretty.contains("\n    exceptiontable=bytes([131, 14]),\n)"), "{}", pretty);
+    // Code objects that differ only there don't print the same
+    let other = Code { qualname: "g.<locals>.<lambda>", ..code };
+    assert_ne!(format!("{:?}", Obj::Code(&other)), format!("{:?}", Obj::Code(&code)));
+    let other = Code { exceptiontable: b"", ..code };
+    assert_ne!(Obj::Code(&other).pretty(), pretty);
 }
 
 #[test]
@@ -156,16 +186,607 @@ fn test_complex_debug_repr() {
         format!("{:?}", Obj::Complex(Complex { re: -0., im: -1. })),
         "(-0-1j)"
     );
+    // Checked against CPython's repr
+    let (inf, nan) = (f64::INFINITY, f64::NAN);
+    for &(re, im, expected) in &[
+        (0., -0., "-0j"),
+        (-0., -0., "(-0-0j)"),
+        (1e300, 1e300, "(1e+300+1e+300j)"),
+        (inf, nan, "(inf+nanj)"),
+        (nan, inf, "(nan+infj)"),
+        (0., nan, "nanj"),
+        (0., -nan, "nanj"),
+        (1., -nan, "(1+nanj)"),
+        (-nan, 1., "(nan+1j)"),
+        (-inf, -inf, "(-inf-infj)"),
+        (1.5, -2., "(1.5-2j)"),
+        (1e16, 1., "(1e+16+1j)"),
+        (1e15, 1e16, "(1000000000000000+1e+16j)"),
+        (0., 1e-5, "1e-05j"),
+        (1e-7, 2.5e-10, "(1e-07+2.5e-10j)"),
+        (123_456_789_012_345_678., 0.0001, "(1.2345678901234568e+17+0.0001j)"),
+        (0.1, 1. / 3., "(0.1+0.3333333333333333j)"),
+        (5e-324, 0., "(5e-324+0j)"),
+    ] {
+        assert_eq!(format!("{:?}", Obj::Complex(Complex { re, im })), expected);
+    }
 }
 
 #[test]
 fn test_bytes_string_debug_repr() {
-    assert_eq!(format!("{:?}", Obj::Bytes(Arc::new(Vec::from(
-                        b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\t\n\x0b\x0c\r\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\x7f\x80\x81\x82\x83\x84\x85\x86\x87\x88\x89\x8a\x8b\x8c\x8d\x8e\x8f\x90\x91\x92\x93\x94\x95\x96\x97\x98\x99\x9a\x9b\x9c\x9d\x9e\x9f\xa0\xa1\xa2\xa3\xa4\xa5\xa6\xa7\xa8\xa9\xaa\xab\xac\xad\xae\xaf\xb0\xb1\xb2\xb3\xb4\xb5\xb6\xb7\xb8\xb9\xba\xbb\xbc\xbd\xbe\xbf\xc0\xc1\xc2\xc3\xc4\xc5\xc6\xc7\xc8\xc9\xca\xcb\xcc\xcd\xce\xcf\xd0\xd1\xd2\xd3\xd4\xd5\xd6\xd7\xd8\xd9\xda\xdb\xdc\xdd\xde\xdf\xe0\xe1\xe2\xe3\xe4\xe5\xe6\xe7\xe8\xe9\xea\xeb\xec\xed\xee\xef\xf0\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xfb\xfc\xfd\xfe" as &[u8]
-                        )))),
+    assert_eq!(format!("{:?}", Obj::Bytes(
+                        b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\t\n\x0b\x0c\r\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\x7f\x80\x81\x82\x83\x84\x85\x86\x87\x88\x89\x8a\x8b\x8c\x8d\x8e\x8f\x90\x91\x92\x93\x94\x95\x96\x97\x98\x99\x9a\x9b\x9c\x9d\x9e\x9f\xa0\xa1\xa2\xa3\xa4\xa5\xa6\xa7\xa8\xa9\xaa\xab\xac\xad\xae\xaf\xb0\xb1\xb2\xb3\xb4\xb5\xb6\xb7\xb8\xb9\xba\xbb\xbc\xbd\xbe\xbf\xc0\xc1\xc2\xc3\xc4\xc5\xc6\xc7\xc8\xc9\xca\xcb\xcc\xcd\xce\xcf\xd0\xd1\xd2\xd3\xd4\xd5\xd6\xd7\xd8\xd9\xda\xdb\xdc\xdd\xde\xdf\xe0\xe1\xe2\xe3\xe4\xe5\xe6\xe7\xe8\xe9\xea\xeb\xec\xed\xee\xef\xf0\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xfb\xfc\xfd\xfe"
+                        )),
     "b\"\\x00\\x01\\x02\\x03\\x04\\x05\\x06\\x07\\x08\\t\\n\\x0b\\x0c\\r\\x0e\\x0f\\x10\\x11\\x12\\x13\\x14\\x15\\x16\\x17\\x18\\x19\\x1a\\x1b\\x1c\\x1d\\x1e\\x1f !\\\"#$%&\\\'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\\x7f\\x80\\x81\\x82\\x83\\x84\\x85\\x86\\x87\\x88\\x89\\x8a\\x8b\\x8c\\x8d\\x8e\\x8f\\x90\\x91\\x92\\x93\\x94\\x95\\x96\\x97\\x98\\x99\\x9a\\x9b\\x9c\\x9d\\x9e\\x9f\\xa0\\xa1\\xa2\\xa3\\xa4\\xa5\\xa6\\xa7\\xa8\\xa9\\xaa\\xab\\xac\\xad\\xae\\xaf\\xb0\\xb1\\xb2\\xb3\\xb4\\xb5\\xb6\\xb7\\xb8\\xb9\\xba\\xbb\\xbc\\xbd\\xbe\\xbf\\xc0\\xc1\\xc2\\xc3\\xc4\\xc5\\xc6\\xc7\\xc8\\xc9\\xca\\xcb\\xcc\\xcd\\xce\\xcf\\xd0\\xd1\\xd2\\xd3\\xd4\\xd5\\xd6\\xd7\\xd8\\xd9\\xda\\xdb\\xdc\\xdd\\xde\\xdf\\xe0\\xe1\\xe2\\xe3\\xe4\\xe5\\xe6\\xe7\\xe8\\xe9\\xea\\xeb\\xec\\xed\\xee\\xef\\xf0\\xf1\\xf2\\xf3\\xf4\\xf5\\xf6\\xf7\\xf8\\xf9\\xfa\\xfb\\xfc\\xfd\\xfe\""
     );
-    assert_eq!(format!("{:?}", Obj::String(Arc::new(String::from(
-                        "\x00\x01\x02\x03\x04\x05\x06\x07\x08\t\n\x0b\x0c\r\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\x7f")))),
+    assert_eq!(format!("{:?}", Obj::String(
+                        "\x00\x01\x02\x03\x04\x05\x06\x07\x08\t\n\x0b\x0c\r\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\x7f")),
                         "\"\\x00\\x01\\x02\\x03\\x04\\x05\\x06\\x07\\x08\\t\\n\\x0b\\x0c\\r\\x0e\\x0f\\x10\\x11\\x12\\x13\\x14\\x15\\x16\\x17\\x18\\x19\\x1a\\x1b\\x1c\\x1d\\x1e\\x1f !\\\"#$%&\\\'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\\x7f\"");
 }
+
+#[test]
+fn test_get_item_index() {
+    let one = Int::from(1);
+    let dict = Obj::Dict(&[
+        (Obj::String("a"), Obj::Bool(true)),
+        (Obj::Long(&one), Obj::String("b")),
+    ]);
+    assert_eq!(dict.get_item(&Obj::String("a")), Some(Obj::Bool(true)));
+    assert_eq!(dict.get_item(&Obj::Long(&one)), Some(Obj::String("b")));
+    assert_eq!(dict.get_item(&Obj::String("c")), None);
+    assert_eq!(dict.get_index(0), None);
+
+    let tuple = Obj::Tuple(&[Obj::None, Obj::Bool(false)]);
+    assert_eq!(tuple.get_index(1), Some(Obj::Bool(false)));
+    assert_eq!(tuple.get_index(2), None);
+    assert_eq!(tuple.get_item(&Obj::None), None);
+    assert_eq!(Obj::List(&[Obj::Ellipsis]).get_index(0), Some(Obj::Ellipsis));
+}
+
+#[test]
+fn test_from_constructors() {
+    let arena = ObjArena::new();
+    assert_eq!(*Obj::from_i64(&arena, -5).extract_long().unwrap(), Int::from(-5));
+    assert_eq!(
+        *Obj::from_u64(&arena, u64::MAX).extract_long().unwrap(),
+        Int::from(u64::MAX)
+    );
+    assert_eq!(
+        *Obj::from_bigint(&arena, Int::from(7)).extract_long().unwrap(),
+        Int::from(7)
+    );
+    assert_eq!(Obj::from_str(&arena, "abc"), Obj::String("abc"));
+    assert_eq!(Obj::from_bytes(&arena, b"\x00\xff"), Obj::Bytes(b"\x00\xff"));
+}
+
+#[test]
+fn test_arena_alloc() {
+    let arena = ObjArena::new();
+    let name = arena.alloc_str("name");
+    let big = arena.alloc_bigint(Int::from(u64::MAX) * 2);
+    let elems = arena.alloc_slice_copy(&[Obj::String(name), Obj::Long(big)]);
+    let tuple = *arena.alloc(Obj::Tuple(elems));
+    assert_eq!(tuple.get_index(0), Some(Obj::String("name")));
+    assert_eq!(
+        *tuple.get_index(1).unwrap().extract_long().unwrap(),
+        Int::from(u64::MAX) * 2
+    );
+}
+
+#[test]
+fn test_iter() {
+    let elems = [Obj::None, Obj::Bool(true)];
+    for obj in &[
+        Obj::Tuple(&elems),
+        Obj::List(&elems),
+        Obj::Set(&elems),
+        Obj::FrozenSet(&elems),
+    ] {
+        assert!(obj.iter().unwrap().eq(elems.iter()));
+    }
+    assert!(Obj::Dict(&[]).iter().is_none());
+    assert!(Obj::String("abc").iter().is_none());
+}
+
+#[test]
+fn test_dict_entries() {
+    let dict = Obj::Dict(&[
+        (Obj::String("a"), Obj::Bool(true)),
+        (Obj::String("b"), Obj::None),
+    ]);
+    assert!(dict.dict_entries().unwrap().eq(vec![
+        (&Obj::String("a"), &Obj::Bool(true)),
+        (&Obj::String("b"), &Obj::None),
+    ]));
+    assert!(Obj::Tuple(&[]).dict_entries().is_none());
+}
+
+#[test]
+fn test_walk_consts() {
+    fn code<'a>(name: &'a str, consts: &'a [Obj<'a>]) -> Code<'a> {
+        Code {
+            argcount: 0,
+            posonlyargcount: 0,
+            kwonlyargcount: 0,
+            nlocals: 0,
+            stacksize: 0,
+            flags: CodeFlags::empty(),
+            code: b"",
+            consts,
+            names: &[],
+            varnames: &[],
+            freevars: &[],
+            cellvars: &[],
+            filename: "<test>",
+            name,
+            qualname: "",
+            firstlineno: 1,
+            lnotab: b"",
+            exceptiontable: b"",
+        }
+    }
+    let inner = code("inner", &[Obj::String("a"), Obj::None]);
+    let nested_consts = [Obj::Code(&inner), Obj::Tuple(&[Obj::String("b")])];
+    let nested = Obj::Tuple(&nested_consts);
+    let outer_consts = [Obj::Bytes(b"c"), nested];
+    let outer = code("outer", &outer_consts);
+    assert_eq!(outer.all_consts(), vec![
+        &Obj::Bytes(b"c"),
+        &nested,
+        &Obj::Code(&inner),
+        &Obj::String("a"),
+        &Obj::None,
+        &Obj::Tuple(&[Obj::String("b")]),
+        &Obj::String("b"),
+    ]);
+    let mut strings = Vec::new();
+    outer.walk_consts(|x| strings.extend(x.extract_string().ok()));
+    assert_eq!(strings, ["a", "b"]);
+}
+
+#[test]
+fn test_float_eq() {
+    assert!(py_float_eq(0.0, -0.0));
+    assert!(py_float_eq(f64::NAN, f64::NAN));
+    assert!(py_float_eq(f64::NAN, -f64::NAN));
+    assert!(!py_float_eq(f64::NAN, 1.0));
+    assert!(!py_float_eq(f64::INFINITY, f64::NEG_INFINITY));
+
+    assert_eq!(Obj::Float(0.0), Obj::Float(-0.0));
+    assert_eq!(Obj::Float(f64::NAN), Obj::Float(f64::NAN));
+    assert_eq!(
+        Obj::Complex(Complex::new(f64::NAN, -0.0)),
+        Obj::Complex(Complex::new(f64::NAN, 0.0))
+    );
+    assert_ne!(Obj::Float(1.0), Obj::Long(&Int::from(1)));
+    let dict = Obj::Dict(&[(Obj::Float(f64::NAN), Obj::Bool(true))]);
+    assert_eq!(dict.get_item(&Obj::Float(f64::NAN)), Some(Obj::Bool(true)));
+    assert_eq!(Obj::Tuple(&[Obj::Float(-0.0)]), Obj::Tuple(&[Obj::Float(0.0)]));
+}
+
+#[test]
+fn test_as_bigint() {
+    let arena = ObjArena::new();
+    assert_eq!(Obj::Bool(true).as_bigint(), Some(Int::from(1)));
+    assert_eq!(Obj::Bool(false).as_bigint(), Some(Int::from(0)));
+    assert_eq!(Obj::from_i64(&arena, -7).as_bigint(), Some(Int::from(-7)));
+    assert_eq!(Obj::Float(1.0).as_bigint(), None);
+    assert_eq!(Obj::None.as_bigint(), None);
+    assert!(Obj::Bool(true).extract_long().is_err());
+}
+
+#[test]
+fn test_as_primitive() {
+    let arena = ObjArena::new();
+    assert_eq!(Obj::from_i64(&arena, -7).as_i64(), Some(-7));
+    assert_eq!(Obj::from_i64(&arena, -7).as_u32(), None);
+    assert_eq!(Obj::from_u64(&arena, u64::MAX).as_i64(), None);
+    assert_eq!(Obj::from_u64(&arena, u64::from(u32::MAX)).as_u32(), Some(u32::MAX));
+    assert_eq!(Obj::from_u64(&arena, u64::from(u32::MAX) + 1).as_u32(), None);
+    assert_eq!(Obj::from_i64(&arena, 3).as_usize(), Some(3));
+    assert_eq!(Obj::Bool(true).as_usize(), Some(1));
+    assert_eq!(Obj::String("3").as_i64(), None);
+    // Also at the ends of an i128, the whole range without the bigint feature
+    assert_eq!(Obj::from_i64(&arena, i64::MIN).as_i64(), Some(i64::MIN));
+    assert_eq!(Obj::from_bigint(&arena, Int::from(i64::MIN) - 1).as_i64(), None);
+    assert_eq!(Obj::from_bigint(&arena, Int::from(i128::MAX)).as_i64(), None);
+    assert_eq!(Obj::from_bigint(&arena, Int::from(i128::MIN)).as_usize(), None);
+}
+
+#[test]
+fn test_with_const() {
+    use crate::{read::marshal_loads, write::marshal_dumps};
+    let arena = ObjArena::new();
+    let consts = [Obj::String("old"), Obj::None];
+    let code = CodeBuilder::new()
+        .with_code(b"d\x00S\x00")
+        .with_consts(&consts)
+        .build(&arena);
+    let patched = code.with_const(&arena, 0, Obj::String("new")).unwrap();
+    assert_eq!(patched.consts, [Obj::String("new"), Obj::None]);
+    assert_eq!(Code { consts: &consts, ..patched }, *code);
+    // The original is untouched
+    assert_eq!(code.consts, consts);
+    assert!(code.with_const(&arena, 2, Obj::None).is_none());
+
+    let bytes = marshal_dumps(&Obj::Code(&patched), 4).unwrap();
+    let reloaded = marshal_loads(&arena, &bytes).unwrap();
+    assert_eq!(reloaded.extract_code().unwrap().consts, patched.consts);
+}
+
+#[test]
+fn test_walk() {
+    use std::ops::ControlFlow;
+    let arena = ObjArena::new();
+    let inner_consts = [Obj::None, Obj::String("inner")];
+    let inner = CodeBuilder::new()
+        .with_consts(&inner_consts)
+        .with_name("g")
+        .build(&arena);
+    let consts = [Obj::Code(inner), Obj::String("g")];
+    let outer = CodeBuilder::new().with_consts(&consts).with_name("f").build(&arena);
+    let (a, b) = (Obj::String("a"), Obj::String("b"));
+    let obj = Obj::Dict(&[
+        (a, Obj::List(&[Obj::Tuple(&[b]), Obj::Code(outer)])),
+        (Obj::FrozenSet(&[b]), Obj::Set(&[a])),
+    ]);
+
+    // Strings stand for themselves, everything else for its type
+    let mut visited = Vec::new();
+    assert_eq!(
+        obj.walk(|x| {
+            visited.push(x.try_as_str().unwrap_or_else(|| x.type_name()));
+            ControlFlow::Continue(())
+        }),
+        ControlFlow::Continue(())
+    );
+    assert_eq!(
+        visited,
+        [
+            "dict", "a", "list", "tuple", "b", "code", "code", "NoneType", "inner", "g",
+            "frozenset", "b", "set", "a",
+        ]
+    );
+
+    // Find a code object by name, without looking any further
+    let mut found = None;
+    let mut count = 0;
+    let result = obj.walk(|x| {
+        count += 1;
+        match x.extract_code() {
+            Ok(code) if code.name == "g" => {
+                found = Some(code);
+                ControlFlow::Break(())
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    });
+    assert_eq!(result, ControlFlow::Break(()));
+    assert!(std::ptr::eq(found.unwrap(), inner));
+    assert_eq!(count, 7);
+}
+
+#[test]
+fn test_walk_shared() {
+    use crate::read::{errors::Error, marshal_loads};
+    use std::ops::ControlFlow;
+    let arena = ObjArena::new();
+    // a = []; a.append(a) is marshalled as a list containing a ref to itself, which can't be
+    // represented, so reading it fails instead of building something a walk would loop over
+    assert!(matches!(
+        marshal_loads(&arena, b"\xdb\x01\x00\x00\x00r\x00\x00\x00\x00"),
+        Err(Error::InvalidRef)
+    ));
+
+    // x = []; for _ in range(64): x = [x, x]
+    // That's 2**64 paths to the innermost list, but only 65 lists.
+    let mut input = b"\xdb\x02\x00\x00\x00".repeat(64);
+    input.extend_from_slice(b"\xdb\x00\x00\x00\x00");
+    for i in (1..=64_u32).rev() {
+        input.push(b'r');
+        input.extend_from_slice(&i.to_le_bytes());
+    }
+    let obj = marshal_loads(&arena, &input).unwrap();
+    let mut visits = 0;
+    let _ = obj.walk(|_| {
+        visits += 1;
+        ControlFlow::Continue(())
+    });
+    // The root, then each list twice: once walked into, once only visited
+    assert_eq!(visits, 1 + 2 * 64);
+}
+
+#[test]
+fn test_docstring() {
+    use crate::read::marshal_loads;
+    let arena = ObjArena::new();
+    // compile('"mod doc"\nclass C:\n  "cdoc"\ndef f():\n  return "r"\ndef g():\n  "gdoc"\n', "m", "exec")
+    let module = marshal_loads(&arena, b"\xe3\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00@\x00\x00\x00s&\x00\x00\x00d\x00Z\x00G\x00d\x01d\x02\x84\x00d\x02\x83\x02Z\x01d\x03d\x04\x84\x00Z\x02d\x05d\x06\x84\x00Z\x03d\x07S\x00)\x08z\x07mod docc\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x10\x00\x00\x00e\x00Z\x01d\x00Z\x02d\x01Z\x03d\x02S\x00)\x03\xda\x01CZ\x04cdocN)\x04\xda\x08__name__\xda\n__module__\xda\x0c__qualname__\xda\x07__doc__\xa9\x00r\x06\x00\x00\x00r\x06\x00\x00\x00\xda\x01mr\x01\x00\x00\x00\x02\x00\x00\x00s\x04\x00\x00\x00\x08\x00\x08\x01r\x01\x00\x00\x00c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00C\x00\x00\x00\xf3\x04\x00\x00\x00d\x01S\x00)\x02N\xda\x01rr\x06\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x07\x00\x00\x00\xda\x01f\x04\x00\x00\x00s\x02\x00\x00\x00\x04\x01r\n\x00\x00\x00c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00C\x00\x00\x00r\x08\x00\x00\x00)\x02Z\x04gdocNr\x06\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x07\x00\x00\x00\xda\x01g\x06\x00\x00\x00s\x02\x00\x00\x00\x04\x00r\x0b\x00\x00\x00N)\x04r\x05\x00\x00\x00r\x01\x00\x00\x00r\n\x00\x00\x00r\x0b\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x06\x00\x00\x00r\x07\x00\x00\x00\xda\x08<module>\x01\x00\x00\x00s\x08\x00\x00\x00\x04\x00\x0e\x01\x08\x02\x0c\x02").unwrap();
+    let module = module.extract_code().unwrap();
+    assert_eq!(module.docstring(), Some("mod doc"));
+    let code = |name| {
+        module
+            .consts
+            .iter()
+            .find_map(|x| x.extract_code().ok().filter(|x| x.name == name))
+            .unwrap()
+    };
+    // Class bodies start with their qualified name
+    assert_eq!(code("C").consts[0], Obj::String("C"));
+    assert_eq!(code("C").docstring(), None);
+    // The first constant of a function without a docstring is None, even if it uses strings
+    assert_eq!(code("f").docstring(), None);
+    assert_eq!(code("g").docstring(), Some("gdoc"));
+
+    // compile('x = "s"', "m", "exec"): a string used first isn't a docstring
+    let module = marshal_loads(&arena, b"c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00@\x00\x00\x00s\x08\x00\x00\x00d\x00Z\x00d\x01S\x00)\x02\xda\x01sN)\x01\xda\x01x\xa9\x00r\x02\x00\x00\x00r\x02\x00\x00\x00\xda\x01m\xda\x08<module>\x01\x00\x00\x00s\x02\x00\x00\x00\x08\x00").unwrap();
+    assert_eq!(module.extract_code().unwrap().consts[0], Obj::String("s"));
+    assert_eq!(module.extract_code().unwrap().docstring(), None);
+    assert_eq!(CodeBuilder::new().finish().docstring(), None);
+
+    // The <listcomp> of compile('["a" for x in y]', "m", "exec") by Python 3.10, whose first
+    // constant is the "a" it uses
+    let listcomp = marshal_loads(&arena, b"\xe3\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x03\x00\x00\x00C\x00\x00\x00s\x10\x00\x00\x00g\x00|\x00]\x04}\x01d\x00\x91\x02q\x02S\x00)\x01\xda\x01a\xa9\x00)\x02\xda\x02.0\xda\x01xr\x02\x00\x00\x00r\x02\x00\x00\x00\xda\x01m\xda\n<listcomp>\x01\x00\x00\x00s\x02\x00\x00\x00\x10\x00").unwrap();
+    let listcomp = listcomp.extract_code().unwrap();
+    assert!(listcomp.flags.contains(CodeFlags::OPTIMIZED));
+    assert_eq!(listcomp.consts[0], Obj::String("a"));
+    assert_eq!(listcomp.docstring(), None);
+}
+
+#[test]
+fn test_try_as_str() {
+    assert_eq!(Obj::String("abc").try_as_str(), Some("abc"));
+    assert_eq!(Obj::Bytes(b"abc").try_as_str(), Some("abc"));
+    assert_eq!(Obj::Bytes("\u{e9}".as_bytes()).try_as_str(), Some("\u{e9}"));
+    assert_eq!(Obj::Bytes(b"\xff\x00").try_as_str(), None);
+    assert_eq!(Obj::Tuple(&[Obj::String("abc")]).try_as_str(), None);
+}
+
+#[test]
+fn test_try_from() {
+    let arena = ObjArena::new();
+    let int = Obj::from_i64(&arena, -5);
+    assert_eq!(i64::try_from(int), Ok(-5));
+    assert_eq!(i64::try_from(&Obj::Bool(true)), Ok(1));
+    assert_eq!(f64::try_from(Obj::Float(0.5)), Ok(0.5));
+    assert_eq!(bool::try_from(Obj::Bool(false)), Ok(false));
+    assert_eq!(String::try_from(Obj::String("abc")), Ok("abc".to_owned()));
+    assert_eq!(Vec::<u8>::try_from(&Obj::Bytes(b"\x00")), Ok(vec![0]));
+
+    let err = String::try_from(Obj::Bytes(b"abc")).unwrap_err();
+    assert_eq!(
+        err,
+        TypeMismatch {
+            expected: "str",
+            actual: "bytes"
+        }
+    );
+    assert_eq!(err.to_string(), "Expected str, got bytes");
+    // Ints aren't floats, and floats aren't ints
+    assert_eq!(f64::try_from(int).unwrap_err().actual, "int");
+    assert!(i64::try_from(Obj::Float(1.0)).is_err());
+    let big = Obj::from_u64(&arena, u64::MAX);
+    assert_eq!(i64::try_from(big).unwrap_err().actual, "int");
+}
+
+#[test]
+fn test_hashable() {
+    let arena = ObjArena::new();
+    let code = CodeBuilder::new().build(&arena);
+    for x in &[
+        Obj::None,
+        Obj::Ellipsis,
+        Obj::Bool(true),
+        Obj::from_i64(&arena, 1),
+        Obj::Float(f64::NAN),
+        Obj::Bytes(b""),
+        Obj::String("a"),
+        Obj::Tuple(&[Obj::Tuple(&[Obj::String("a")])]),
+        Obj::FrozenSet(&[Obj::None]),
+        Obj::Code(code),
+    ] {
+        assert!(x.is_hashable(), "{:?}", x);
+        assert!(x.is_immutable(), "{:?}", x);
+    }
+    for x in &[Obj::List(&[]), Obj::Set(&[]), Obj::Dict(&[])] {
+        assert!(!x.is_hashable(), "{:?}", x);
+        assert!(!x.is_immutable(), "{:?}", x);
+    }
+    // A tuple can't be changed, but the list in it can
+    let tuple = Obj::Tuple(&[Obj::None, Obj::List(&[])]);
+    assert!(!tuple.is_hashable());
+    assert!(tuple.is_immutable());
+}
+
+#[test]
+fn test_semantic_eq() {
+    use crate::read::marshal_loads;
+    let arena = ObjArena::new();
+    // ({'k': frozenset({'a', 'bc'}), 1: 'a'}, 'bc'), written by marshal.dumps with version 2
+    // (no refs) and version 4, then version 2 again with the dict and frozenset reordered
+    let v2 = marshal_loads(&arena, b"(\x02\x00\x00\x00{u\x01\x00\x00\x00k>\x02\x00\x00\x00u\x01\x00\x00\x00au\x02\x00\x00\x00bci\x01\x00\x00\x00u\x01\x00\x00\x00a0u\x02\x00\x00\x00bc").unwrap();
+    let v4 = marshal_loads(&arena, b"\xa9\x02{\xda\x01k>\x02\x00\x00\x00\xda\x01a\xda\x02bc\xe9\x01\x00\x00\x00r\x02\x00\x00\x000r\x03\x00\x00\x00").unwrap();
+    let reordered = marshal_loads(&arena, b"(\x02\x00\x00\x00{i\x01\x00\x00\x00u\x01\x00\x00\x00au\x01\x00\x00\x00k>\x02\x00\x00\x00u\x02\x00\x00\x00bcu\x01\x00\x00\x00a0u\x02\x00\x00\x00bc").unwrap();
+    assert_eq!(v2, v4);
+    assert!(obj_semantic_eq(&v2, &v4));
+    assert_ne!(v2, reordered);
+    assert!(obj_semantic_eq(&v2, &reordered));
+
+    // Order still matters for tuples and lists, and each element is only matched once
+    let (a, b) = (Obj::String("a"), Obj::String("b"));
+    assert!(!obj_semantic_eq(&Obj::Tuple(&[a, b]), &Obj::Tuple(&[b, a])));
+    assert!(!obj_semantic_eq(&Obj::List(&[a, b]), &Obj::List(&[b, a])));
+    assert!(!obj_semantic_eq(&Obj::Set(&[a, a]), &Obj::Set(&[a, b])));
+    assert!(!obj_semantic_eq(&Obj::Set(&[a, b]), &Obj::FrozenSet(&[b, a])));
+    assert!(!obj_semantic_eq(&Obj::Dict(&[(a, b)]), &Obj::Dict(&[(b, a)])));
+
+    // Constants of code objects are compared the same way
+    let code = |a: &[Obj], b: &[Obj]| {
+        obj_semantic_eq(
+            &Obj::Code(&CodeBuilder::new().with_consts(a).finish()),
+            &Obj::Code(&CodeBuilder::new().with_consts(b).finish()),
+        )
+    };
+    assert!(code(&[Obj::FrozenSet(&[a, b])], &[Obj::FrozenSet(&[b, a])]));
+    assert!(!code(&[Obj::Tuple(&[a, b])], &[Obj::Tuple(&[b, a])]));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_serialize() {
+    use serde_json::json;
+    let arena = ObjArena::new();
+    let consts = [Obj::None, Obj::from_i64(&arena, -2), Obj::Tuple(&[Obj::String("a")])];
+    let code = CodeBuilder::new()
+        .with_argcount(1)
+        .with_flags(CodeFlags::OPTIMIZED | CodeFlags::NEWLOCALS)
+        .with_code(b"d\x00S\x00")
+        .with_consts(&consts)
+        .with_varnames(&["x"])
+        .with_name("f")
+        .build(&arena);
+    // A BigInt serializes as its sign and u32 digits, and an i128 as a plain number
+    #[cfg(feature = "bigint")]
+    let long = json!({"Long": [-1, [2]]});
+    #[cfg(not(feature = "bigint"))]
+    let long = json!({"Long": -2});
+    // Everything is borrowed from the arena, so nothing needs to be cloned to serialize it
+    assert_eq!(
+        serde_json::to_value(Obj::Code(code)).unwrap(),
+        json!({"Code": {
+            "argcount": 1,
+            "posonlyargcount": 0,
+            "kwonlyargcount": 0,
+            "nlocals": 0,
+            "stacksize": 0,
+            "flags": {"bits": 3},
+            "code": [100, 0, 83, 0],
+            "consts": ["None", long, {"Tuple": [{"String": "a"}]}],
+            "names": [],
+            "varnames": ["x"],
+            "freevars": [],
+            "cellvars": [],
+            "filename": "<string>",
+            "name": "f",
+            "qualname": "",
+            "firstlineno": 1,
+            "lnotab": [],
+            "exceptiontable": [],
+        }})
+    );
+}
+
+#[test]
+fn test_extract_through_ref() {
+    let arena = ObjArena::new();
+    let tuple = Obj::Tuple(arena.alloc_slice_copy(&[Obj::String("a")]));
+    // The extracted slice outlives the reference it was extracted through
+    let elems = {
+        let borrowed: &Obj = &tuple;
+        borrowed.extract_tuple().unwrap()
+    };
+    assert_eq!(elems[0].extract_string(), Ok("a"));
+    assert_eq!(tuple.extract_list(), Err(tuple));
+}
+
+#[test]
+fn test_location() {
+    let code = CodeBuilder::new()
+        .with_filename("m.py")
+        .with_name("f")
+        .with_firstlineno(3)
+        .finish();
+    assert_eq!(code.location(), ("m.py", "f", 3));
+    assert_eq!(code.full_name(), "f");
+}
+
+#[test]
+fn test_dict_from_pairs() {
+    use crate::read::errors::Error;
+    let arena = ObjArena::new();
+    let dict = Obj::dict_from_pairs(&arena, vec![(Obj::String("a"), Obj::List(&[]))]).unwrap();
+    assert_eq!(dict, Obj::Dict(&[(Obj::String("a"), Obj::List(&[]))]));
+    let key = Obj::Tuple(&[Obj::List(&[])]);
+    match Obj::dict_from_pairs(&arena, vec![(Obj::None, Obj::None), (key, Obj::None)]) {
+        Err(Error::Unhashable(x)) => assert_eq!(x, key),
+        x => panic!("{:?}", x),
+    }
+}
+
+#[test]
+fn test_py_obj() {
+    let arena = ObjArena::new();
+    let one = Obj::from_i64(&arena, 1);
+    let obj = py_obj!(
+        &arena;
+        (1, -2, "a", b"b", [None, True, Ellipsis], {"k": -2.5, 3: ()}, {1}, (one,))
+    );
+    let expected = Obj::Tuple(&[
+        one,
+        Obj::from_i64(&arena, -2),
+        Obj::String("a"),
+        Obj::Bytes(b"b"),
+        Obj::List(&[Obj::None, Obj::Bool(true), Obj::Ellipsis]),
+        Obj::Dict(&[
+            (Obj::String("k"), Obj::Float(-2.5)),
+            (Obj::from_i64(&arena, 3), Obj::Tuple(&[])),
+        ]),
+        Obj::Set(&[one]),
+        Obj::Tuple(&[one]),
+    ]);
+    assert_eq!(obj, expected);
+    assert_eq!(py_obj!(&arena; (1)), one);
+    assert_eq!(py_obj!(&arena; {}), Obj::Dict(&[]));
+    assert_eq!(py_obj!(&arena; []), Obj::List(&[]));
+    assert_eq!(Obj::from("a"), Obj::String("a"));
+    assert_eq!(Obj::from(1.5), Obj::Float(1.5));
+}
+
+#[test]
+fn test_sorted_set_elements() {
+    let arena = ObjArena::new();
+    let set = py_obj!(&arena; {(2, "b"), "b", 10, (2, "a"), None, b"z", 1.5, "a", 2, True});
+    assert_eq!(
+        set.sorted_set_elements().unwrap(),
+        vec![
+            Obj::None,
+            Obj::Bool(true),
+            Obj::Bytes(b"z"),
+            Obj::String("a"),
+            Obj::String("b"),
+            Obj::from_i64(&arena, 2),
+            Obj::from_i64(&arena, 10),
+            Obj::Float(1.5),
+            py_obj!(&arena; (2, "a")),
+            py_obj!(&arena; (2, "b")),
+        ]
+    );
+    assert_eq!(Obj::FrozenSet(&[]).sorted_set_elements(), Some(vec![]));
+    assert_eq!(Obj::List(&[Obj::None]).sorted_set_elements(), None);
+}
+
+#[test]
+fn test_obj_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    let arena = ObjArena::new();
+    let one = Obj::from_i64(&arena, 1);
+    let obj = Obj::List(arena.alloc_slice_copy(&[one]));
+    assert_send_sync(&obj);
+    std::thread::scope(|s| {
+        s.spawn(|| assert_eq!(obj.get_index(0), Some(one)));
+    });
+}
+
+#[test]
+fn test_magic_to_marshal_version() {
+    use super::{magic_to_marshal_version, MarshalVersion};
+    assert_eq!(magic_to_marshal_version(62211), Some(MarshalVersion::Py27));
+    assert_eq!(magic_to_marshal_version(3413), Some(MarshalVersion::Py38));
+    assert_eq!(magic_to_marshal_version(3531), Some(MarshalVersion::Py312));
+    // importlib.util.MAGIC_NUMBER in Python 3.11, b"\xa7\r\r\n"
+    assert_eq!(magic_to_marshal_version(0x0a0d_0da7), Some(MarshalVersion::Py311));
+    // Between releases, and Python 3.13
+    assert_eq!(magic_to_marshal_version(3440), None);
+    assert_eq!(magic_to_marshal_version(3571), None);
+    assert_eq!(magic_to_marshal_version(0x1_0da7), None);
+}
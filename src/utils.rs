@@ -1,24 +1,74 @@
+use crate::Int;
+use std::borrow::Borrow;
+#[cfg(feature = "bigint")]
 use num_bigint::{BigUint, Sign};
-use num_traits::Zero;
-use std::cmp::Ordering;
 
-/// Based on `_PyLong_AsByteArray` in <https://github.com/python/cpython/blob/master/Objects/longobject.c>
+/// Combines the 15-bit digits of a `TYPE_LONG`, least significant first, into an [`Int`].
+/// Returns `None` if it doesn't fit, which only happens without the `bigint` feature.
+///
+/// Takes the digits one at a time, so a huge int read from a stream never has all of its
+/// digits in memory as well as the result.
+#[cfg(feature = "bigint")]
+#[allow(clippy::unnecessary_wraps)]
+pub fn int_from_pylong_digits<D: Borrow<u16>>(negative: bool, digits: impl IntoIterator<Item = D>) -> Option<Int> {
+    let sign = if negative { Sign::Minus } else { Sign::Plus };
+    Some(Int::from_biguint(sign, biguint_from_pylong_digits(digits)))
+}
+#[cfg(not(feature = "bigint"))]
+pub fn int_from_pylong_digits<D: Borrow<u16>>(negative: bool, digits: impl IntoIterator<Item = D>) -> Option<Int> {
+    let mut magnitude: u128 = 0;
+    let mut shift = 0_u32;
+    let mut overflow = false;
+    // Keeps going after an overflow, so the caller still sees every digit
+    for digit in digits {
+        let digit = u128::from(*digit.borrow());
+        if digit != 0 && (shift >= 128 || (digit << shift) >> shift != digit) {
+            overflow = true;
+        } else if shift < 128 {
+            magnitude |= digit << shift;
+        }
+        shift = shift.saturating_add(15);
+    }
+    if overflow {
+        None
+    } else if negative {
+        0_i128.checked_sub_unsigned(magnitude)
+    } else {
+        std::convert::TryFrom::try_from(magnitude).ok()
+    }
+}
+
+/// The inverse of [`int_from_pylong_digits`]: whether `x` is negative, and the 15-bit digits of
+/// its absolute value.
+#[cfg(feature = "bigint")]
+pub fn pylong_digits_from_int(x: &Int) -> (bool, Vec<u16>) {
+    let (sign, magnitude) = x.to_bytes_le();
+    let digits = pylong_digits_from_biguint(&BigUint::from_bytes_le(&magnitude));
+    (sign == Sign::Minus, digits)
+}
+#[cfg(not(feature = "bigint"))]
 #[allow(clippy::cast_possible_truncation)]
-pub fn biguint_from_pylong_digits(digits: &[u16]) -> BigUint {
-    if digits.is_empty() {
-        return BigUint::zero();
-    };
-    assert!(digits[digits.len() - 1] != 0);
+pub fn pylong_digits_from_int(x: &Int) -> (bool, Vec<u16>) {
+    let mut magnitude = x.unsigned_abs();
+    let mut digits = Vec::new();
+    while magnitude != 0 {
+        digits.push((magnitude & 0x7fff) as u16);
+        magnitude >>= 15;
+    }
+    (*x < 0, digits)
+}
+
+/// Based on `_PyLong_AsByteArray` in <https://github.com/python/cpython/blob/master/Objects/longobject.c>,
+/// but taking the digits one at a time. Trailing zero digits are allowed, and ignored.
+#[cfg(feature = "bigint")]
+#[allow(clippy::cast_possible_truncation)]
+pub fn biguint_from_pylong_digits<D: Borrow<u16>>(digits: impl IntoIterator<Item = D>) -> BigUint {
     let mut accum: u64 = 0;
     let mut accumbits: u8 = 0;
     let mut p = Vec::<u32>::new();
-    for (i, &thisdigit) in digits.iter().enumerate() {
-        accum |= u64::from(thisdigit) << accumbits;
-        accumbits += if i == digits.len() - 1 {
-            16 - (thisdigit.leading_zeros() as u8)
-        } else {
-            15
-        };
+    for thisdigit in digits {
+        accum |= u64::from(*thisdigit.borrow()) << accumbits;
+        accumbits += 15;
 
         // Modified to get u32s instead of u8s.
         while accumbits >= 32 {
@@ -27,31 +77,78 @@ pub fn biguint_from_pylong_digits(digits: &[u16]) -> BigUint {
             accum >>= 32;
         }
     }
-    assert!(accumbits < 32);
-    if accumbits > 0 {
+    // Unlike CPython, which works out how many bits the last digit has, leave any leftover
+    // zeros for `BigUint::new` to strip
+    if accum != 0 {
         p.push(accum as u32);
     }
     BigUint::new(p)
 }
 
-pub fn sign_of<T: Ord + Zero>(x: &T) -> Sign {
-    match x.cmp(&T::zero()) {
-        Ordering::Less => Sign::Minus,
-        Ordering::Equal => Sign::NoSign,
-        Ordering::Greater => Sign::Plus,
+/// The inverse of [`biguint_from_pylong_digits`]: splits `x` into 15-bit digits, least
+/// significant first, without trailing zero digits.
+#[cfg(feature = "bigint")]
+#[allow(clippy::cast_possible_truncation)]
+pub fn pylong_digits_from_biguint(x: &BigUint) -> Vec<u16> {
+    let mut digits = Vec::new();
+    let mut accum: u32 = 0;
+    let mut accumbits: u8 = 0;
+    for byte in x.to_bytes_le() {
+        accum |= u32::from(byte) << accumbits;
+        accumbits += 8;
+        if accumbits >= 15 {
+            digits.push((accum & 0x7fff) as u16);
+            accum >>= 15;
+            accumbits -= 15;
+        }
     }
+    if accum != 0 {
+        digits.push(accum as u16);
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits
 }
 
 #[cfg(test)]
 mod test {
-    use super::biguint_from_pylong_digits;
+    use super::{int_from_pylong_digits, pylong_digits_from_int};
+    #[cfg(feature = "bigint")]
+    use super::{biguint_from_pylong_digits, pylong_digits_from_biguint};
+    use crate::Int;
+    #[cfg(feature = "bigint")]
     use num_bigint::BigUint;
 
-    #[allow(clippy::inconsistent_digit_grouping)]
+    #[test]
+    fn test_int_from_pylong_digits() {
+        for &x in &[0, 1, -1, 0x7fff, -0x8000, i128::from(i64::MIN) - 1, i128::MAX, i128::MIN] {
+            let (negative, digits) = pylong_digits_from_int(&Int::from(x));
+            assert_eq!(int_from_pylong_digits(negative, &digits), Some(Int::from(x)), "{}", x);
+        }
+        // Zero digits past 128 bits don't overflow
+        assert_eq!(int_from_pylong_digits(false, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]), Some(Int::from(1)));
+        // 2**127 and -2**127 - 1, just past the ends of an i128
+        let past_max = [0, 0, 0, 0, 0, 0, 0, 0, 0x80];
+        let past_min = [1, 0, 0, 0, 0, 0, 0, 0, 0x80];
+        #[cfg(not(feature = "bigint"))]
+        {
+            assert_eq!(int_from_pylong_digits(false, past_max), None);
+            assert_eq!(int_from_pylong_digits(true, past_min), None);
+        }
+        #[cfg(feature = "bigint")]
+        {
+            assert_eq!(int_from_pylong_digits(false, past_max), Some(Int::from(i128::MAX) + 1));
+            assert_eq!(int_from_pylong_digits(true, past_min), Some(Int::from(i128::MIN) - 1));
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[allow(clippy::inconsistent_digit_grouping, clippy::unusual_byte_groupings)]
     #[test]
     fn test_biguint_from_pylong_digits() {
         assert_eq!(
-            biguint_from_pylong_digits(&[
+            biguint_from_pylong_digits([
                 0b000_1101_1100_0100,
                 0b110_1101_0010_0100,
                 0b001_0000_1001_1101
@@ -59,4 +156,25 @@ mod test {
             BigUint::from(0b001_0000_1001_1101_110_1101_0010_0100_000_1101_1100_0100_u64)
         );
     }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_pylong_digits_roundtrip() {
+        assert!(pylong_digits_from_biguint(&BigUint::from(0_u8)).is_empty());
+        assert_eq!(pylong_digits_from_biguint(&BigUint::from(0x7fff_u16)), [0x7fff]);
+        assert_eq!(pylong_digits_from_biguint(&BigUint::from(0x8000_u16)), [0, 1]);
+        let x = BigUint::from(u64::MAX) * BigUint::from(u64::MAX - 12345);
+        assert_eq!(biguint_from_pylong_digits(pylong_digits_from_biguint(&x)), x);
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_biguint_from_pylong_digit_iter() {
+        let x = BigUint::from(u64::MAX) * BigUint::from(u64::MAX - 12345);
+        let digits = pylong_digits_from_biguint(&x);
+        assert_eq!(biguint_from_pylong_digits(digits.iter().copied()), x);
+        // Trailing zero digits, which TYPE_LONG doesn't allow, don't change the value
+        assert_eq!(biguint_from_pylong_digits(digits.iter().chain(&[0, 0, 0])), x);
+        assert_eq!(biguint_from_pylong_digits(&[] as &[u16]), BigUint::from(0_u8));
+    }
 }
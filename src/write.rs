@@ -0,0 +1,758 @@
+//! Serialization of [`Obj`] trees, following `w_object` in
+//! <https://github.com/python/cpython/blob/master/Python/marshal.c>.
+//!
+//! From version 3 on, objects that appear more than once (by identity, i.e. pointing to the same
+//! storage) are written once with `FLAG_REF` and referred back to after that. Objects read from
+//! marshal data share storage wherever the input used refs; use [`Obj::dedup`] to also share
+//! equal objects that were built separately.
+use crate::{
+    dedup::{identity, names_identity, Identity},
+    read::{CO_FAST_CELL, CO_FAST_FREE, CO_FAST_LOCAL},
+    utils, Code, Int, MarshalVersion, Obj, Type,
+};
+use num_traits::ToPrimitive;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+pub mod errors {
+    use crate::MarshalVersion;
+
+    #[derive(thiserror::Error, Debug)]
+    pub enum Error {
+        /// Code objects for `version` have a field that [`Code`](crate::Code) doesn't model, so
+        /// they can't be written.
+        #[error("{version:?} code objects need {field}, which Code doesn't have")]
+        MissingFieldForVersion {
+            field: &'static str,
+            version: MarshalVersion
+        },
+        /// The field is set, but code objects for `version` have nowhere to put it.
+        #[error("{version:?} code objects have no {field}, but it's set")]
+        UnsupportedFieldForVersion {
+            field: &'static str,
+            version: MarshalVersion
+        },
+        #[error("Unknown .pyc magic number: {magic}")]
+        UnknownMagic {
+            magic: u16
+        },
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+    }
+
+    pub type Result<T> = std::result::Result<T, Error>;
+}
+
+/// The newest marshal format, as written by `marshal.dumps` by default.
+pub const VERSION: u8 = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct MarshalDumpExOptions {
+    /// Marshal format version, as in `marshal.dumps(value, version)`.
+    pub version: u8,
+    /// Python version whose code object layout is written. Apart from `has_posonlyargcount`,
+    /// only [`MarshalVersion::Py27`] makes a difference, leaving out `co_kwonlyargcount`, and
+    /// 3.11+, which use the 3.11 layout with `co_qualname` and `co_exceptiontable`.
+    pub python_version: MarshalVersion,
+    pub has_posonlyargcount: bool,
+}
+/// Writes data for Python 3.8 through 3.10, in the newest marshal format. The Python version is
+/// a compatibility default, kept when newer ones were added: the 3.11 code object layout isn't
+/// readable by older versions, and needs a `qualname`. Set
+/// [`python_version`](Self::python_version) to write for anything else.
+impl Default for MarshalDumpExOptions {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            python_version: MarshalVersion::Py310,
+            has_posonlyargcount: true,
+        }
+    }
+}
+impl MarshalDumpExOptions {
+    /// Writes what `marshal.dumps` in Python `version` would by default.
+    #[must_use]
+    pub fn for_version(version: MarshalVersion) -> Self {
+        Self {
+            // Python 2.7 has neither refs nor the short string types
+            version: if version == MarshalVersion::Py27 { 2 } else { VERSION },
+            python_version: version,
+            has_posonlyargcount: version >= MarshalVersion::Py38,
+        }
+    }
+}
+
+struct WFile<W: Write> {
+    writable: W,
+    opts: MarshalDumpExOptions,
+    /// `FLAG_REF`, if the next type byte written is for an object that's referred to later
+    flag: u8,
+    /// Objects that appear more than once
+    shared: HashSet<Identity>,
+    /// Ref indices of the shared objects written so far
+    refs: HashMap<Identity, u32>,
+}
+
+fn w_byte(p: &mut WFile<impl Write>, x: u8) -> io::Result<()> {
+    p.writable.write_all(&[x])
+}
+
+fn w_type(p: &mut WFile<impl Write>, type_: Type) -> io::Result<()> {
+    let flag = std::mem::take(&mut p.flag);
+    w_byte(p, type_ as u8 | flag)
+}
+
+/// Like `w_ref`: writes a ref and returns `true` if the object was already written. Otherwise
+/// flags it, if it'll be referred to later.
+fn w_ref(p: &mut WFile<impl Write>, id: Identity) -> io::Result<bool> {
+    if !p.shared.contains(&id) {
+        return Ok(false);
+    }
+    if let Some(&index) = p.refs.get(&id) {
+        w_type(p, Type::Ref)?;
+        w_long(p, index)?;
+        return Ok(true);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    p.refs.insert(id, p.refs.len() as u32);
+    p.flag = Type::FLAG_REF;
+    Ok(false)
+}
+
+/// Returns whether `id` hasn't been seen before, and marks it as shared if it has.
+fn first_visit(id: Identity, seen: &mut HashSet<Identity>, shared: &mut HashSet<Identity>) -> bool {
+    if seen.insert(id) {
+        true
+    } else {
+        shared.insert(id);
+        false
+    }
+}
+
+/// Finds the objects under `x` that are reachable more than once, when code objects are laid out
+/// for `version`.
+fn find_shared(
+    x: &Obj,
+    version: MarshalVersion,
+    seen: &mut HashSet<Identity>,
+    shared: &mut HashSet<Identity>,
+) {
+    match *x {
+        // Stored inline, so there's nothing to share
+        Obj::None
+        | Obj::StopIteration
+        | Obj::Ellipsis
+        | Obj::Bool(_)
+        | Obj::Float(_)
+        | Obj::Complex(_)
+        | Obj::Unknown { .. } => {}
+        // Empty slices don't own any storage, so these may not really be the same object, and
+        // unlike tuples, it would matter if they were
+        Obj::List(&[]) | Obj::Set(&[]) | Obj::Dict(&[]) => {}
+        Obj::Long(_) | Obj::Bytes(_) | Obj::String(_) | Obj::RawString(_) => {
+            first_visit(identity(x), seen, shared);
+        }
+        Obj::Tuple(elems) | Obj::List(elems) | Obj::Set(elems) | Obj::FrozenSet(elems) => {
+            if first_visit(identity(x), seen, shared) {
+                for elem in elems {
+                    find_shared(elem, version, seen, shared);
+                }
+            }
+        }
+        Obj::Dict(entries) => {
+            if first_visit(identity(x), seen, shared) {
+                for (key, value) in entries {
+                    find_shared(key, version, seen, shared);
+                    find_shared(value, version, seen, shared);
+                }
+            }
+        }
+        Obj::Code(code) => {
+            if first_visit(identity(x), seen, shared) {
+                find_shared(&Obj::Bytes(code.code), version, seen, shared);
+                find_shared(&Obj::Tuple(code.consts), version, seen, shared);
+                if version >= MarshalVersion::Py311 {
+                    if first_visit(names_identity(code.names), seen, shared) {
+                        for name in code.names {
+                            find_shared(&Obj::String(name), version, seen, shared);
+                        }
+                    }
+                    // co_localsplusnames is built while writing, so only its names can be shared
+                    for name in localsplus(code) {
+                        find_shared(&Obj::String(name), version, seen, shared);
+                    }
+                    find_shared(&Obj::String(code.filename), version, seen, shared);
+                    find_shared(&Obj::String(code.name), version, seen, shared);
+                    find_shared(&Obj::String(code.qualname), version, seen, shared);
+                    find_shared(&Obj::Bytes(code.lnotab), version, seen, shared);
+                    find_shared(&Obj::Bytes(code.exceptiontable), version, seen, shared);
+                    return;
+                }
+                for &names in &[code.names, code.varnames, code.freevars, code.cellvars] {
+                    if first_visit(names_identity(names), seen, shared) {
+                        for name in names {
+                            find_shared(&Obj::String(name), version, seen, shared);
+                        }
+                    }
+                }
+                find_shared(&Obj::String(code.filename), version, seen, shared);
+                find_shared(&Obj::String(code.name), version, seen, shared);
+                find_shared(&Obj::Bytes(code.lnotab), version, seen, shared);
+            }
+        }
+    }
+}
+
+fn w_long(p: &mut WFile<impl Write>, x: u32) -> io::Result<()> {
+    p.writable.write_all(&x.to_le_bytes())
+}
+
+/// Lengths are written as a C `int`, like `W_SIZE`, so anything longer can't be marshalled.
+fn w_size(p: &mut WFile<impl Write>, n: usize) -> io::Result<()> {
+    match i32::try_from(n) {
+        #[allow(clippy::cast_sign_loss)]
+        Ok(n) => w_long(p, n as u32),
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "unmarshallable object")),
+    }
+}
+
+fn w_pstring(p: &mut WFile<impl Write>, x: &[u8]) -> io::Result<()> {
+    w_size(p, x.len())?;
+    p.writable.write_all(x)
+}
+
+fn w_float(p: &mut WFile<impl Write>, x: f64) -> io::Result<()> {
+    if p.opts.version > 1 {
+        p.writable.write_all(&x.to_le_bytes())
+    } else {
+        // Rust's shortest round-trip representation is also accepted by Python's float()
+        let s = format!("{:?}", x);
+        #[allow(clippy::cast_possible_truncation)]
+        w_byte(p, s.len() as u8)?;
+        p.writable.write_all(s.as_bytes())
+    }
+}
+
+/// Mirrors `all_name_chars` in codeobject.c: string constants that look like identifiers are
+/// interned when a code object is created.
+fn is_identifier_like(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+fn w_str(p: &mut WFile<impl Write>, x: &str, interned: bool) -> io::Result<()> {
+    if p.opts.version >= 4 && x.is_ascii() {
+        if let Ok(n) = u8::try_from(x.len()) {
+            w_type(p, if interned { Type::ShortAsciiInterned } else { Type::ShortAscii })?;
+            w_byte(p, n)?;
+            return p.writable.write_all(x.as_bytes());
+        }
+        w_type(p, if interned { Type::AsciiInterned } else { Type::Ascii })?;
+    } else if interned && p.opts.version >= 1 {
+        w_type(p, Type::Interned)?;
+    } else {
+        w_type(p, Type::Unicode)?;
+    }
+    w_pstring(p, x.as_bytes())
+}
+
+/// Like CPython, uses `TYPE_INT` for ints that fit in 32 bits and `TYPE_LONG` for the rest.
+/// `TYPE_INT64` is only ever read.
+fn w_long_obj(p: &mut WFile<impl Write>, x: &Int) -> io::Result<()> {
+    if let Some(x) = x.to_i32() {
+        w_type(p, Type::Int)?;
+        #[allow(clippy::cast_sign_loss)]
+        return w_long(p, x as u32);
+    }
+    let (negative, digits) = utils::pylong_digits_from_int(x);
+    let n = i32::try_from(digits.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "int too large to marshal"))?;
+    w_type(p, Type::Long)?;
+    #[allow(clippy::cast_sign_loss)]
+    w_long(p, if negative { -n } else { n } as u32)?;
+    for digit in digits {
+        p.writable.write_all(&digit.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn w_sequence(
+    p: &mut WFile<impl Write>,
+    type_: Type,
+    x: &[Obj],
+    in_consts: bool,
+) -> io::Result<()> {
+    match u8::try_from(x.len()) {
+        Ok(n) if matches!(type_, Type::Tuple) && p.opts.version >= 4 => {
+            w_type(p, Type::SmallTuple)?;
+            w_byte(p, n)?;
+        }
+        _ => {
+            w_type(p, type_)?;
+            w_size(p, x.len())?;
+        }
+    }
+    x.iter().try_for_each(|x| w_object(p, x, in_consts))
+}
+
+fn w_name(p: &mut WFile<impl Write>, x: &str) -> io::Result<()> {
+    if w_ref(p, identity(&Obj::String(x)))? {
+        return Ok(());
+    }
+    w_str(p, x, true)
+}
+
+fn w_names(p: &mut WFile<impl Write>, x: &[&str]) -> io::Result<()> {
+    if w_ref(p, names_identity(x))? {
+        return Ok(());
+    }
+    w_names_tuple(p, x)
+}
+
+fn w_names_tuple(p: &mut WFile<impl Write>, x: &[&str]) -> io::Result<()> {
+    match u8::try_from(x.len()) {
+        Ok(n) if p.opts.version >= 4 => {
+            w_type(p, Type::SmallTuple)?;
+            w_byte(p, n)?;
+        }
+        _ => {
+            w_type(p, Type::Tuple)?;
+            w_size(p, x.len())?;
+        }
+    }
+    x.iter().try_for_each(|x| w_name(p, x))
+}
+
+/// Python 3.11's `co_localsplusnames`: the locals, then the cells that aren't also locals, then
+/// the free variables.
+fn localsplus<'a>(x: &Code<'a>) -> impl Iterator<Item = &'a str> + 'a {
+    let (varnames, cellvars) = (x.varnames, x.cellvars);
+    let cells = cellvars.iter().filter(move |x| !varnames.contains(x));
+    varnames.iter().chain(cells).chain(x.freevars).copied()
+}
+
+/// The `CO_FAST_*` flags for each of [`localsplus`]. Python 3.12's `CO_FAST_HIDDEN`, for the
+/// variables of inlined comprehensions, isn't kept in [`Code`], so it's never set.
+fn localspluskinds(x: &Code) -> Vec<u8> {
+    let kind = |names: &[&str], name, flag| if names.contains(&name) { flag } else { 0 };
+    localsplus(x)
+        .map(|name| {
+            kind(x.varnames, name, CO_FAST_LOCAL)
+                | kind(x.cellvars, name, CO_FAST_CELL)
+                | kind(x.freevars, name, CO_FAST_FREE)
+        })
+        .collect()
+}
+
+/// Python 3.11 reordered the fields and merged the tuples of variable names.
+fn w_code_py311(p: &mut WFile<impl Write>, x: &Code) -> io::Result<()> {
+    w_long(p, x.argcount)?;
+    if p.opts.has_posonlyargcount {
+        w_long(p, x.posonlyargcount)?;
+    }
+    w_long(p, x.kwonlyargcount)?;
+    w_long(p, x.stacksize)?;
+    w_long(p, x.flags.bits())?;
+    w_object(p, &Obj::Bytes(x.code), false)?;
+    w_object(p, &Obj::Tuple(x.consts), true)?;
+    w_names(p, x.names)?;
+    w_names_tuple(p, &localsplus(x).collect::<Vec<_>>())?;
+    w_object(p, &Obj::Bytes(&localspluskinds(x)), false)?;
+    w_name(p, x.filename)?;
+    w_name(p, x.name)?;
+    w_name(p, x.qualname)?;
+    w_long(p, x.firstlineno)?;
+    w_object(p, &Obj::Bytes(x.lnotab), false)?;
+    w_object(p, &Obj::Bytes(x.exceptiontable), false)
+}
+
+fn w_code(p: &mut WFile<impl Write>, x: &Code) -> io::Result<()> {
+    w_type(p, Type::Code)?;
+    if p.opts.python_version >= MarshalVersion::Py311 {
+        return w_code_py311(p, x);
+    }
+    w_long(p, x.argcount)?;
+    // Python 2.7 has neither keyword-only nor positional-only arguments
+    if p.opts.python_version != MarshalVersion::Py27 {
+        if p.opts.has_posonlyargcount {
+            w_long(p, x.posonlyargcount)?;
+        }
+        w_long(p, x.kwonlyargcount)?;
+    }
+    w_long(p, x.nlocals)?;
+    w_long(p, x.stacksize)?;
+    w_long(p, x.flags.bits())?;
+    w_object(p, &Obj::Bytes(x.code), false)?;
+    // Python 2 interned strings are `str`, which would change the type of `unicode` constants
+    w_object(p, &Obj::Tuple(x.consts), p.opts.python_version != MarshalVersion::Py27)?;
+    w_names(p, x.names)?;
+    w_names(p, x.varnames)?;
+    w_names(p, x.freevars)?;
+    w_names(p, x.cellvars)?;
+    w_name(p, x.filename)?;
+    w_name(p, x.name)?;
+    w_long(p, x.firstlineno)?;
+    w_object(p, &Obj::Bytes(x.lnotab), false)
+}
+
+/// `in_consts` is set inside a code object's constants, where identifier-like strings are
+/// written as interned.
+fn w_object(p: &mut WFile<impl Write>, x: &Obj, in_consts: bool) -> io::Result<()> {
+    if w_ref(p, identity(x))? {
+        return Ok(());
+    }
+    match *x {
+        Obj::None => w_type(p, Type::None),
+        Obj::StopIteration => w_type(p, Type::StopIter),
+        Obj::Ellipsis => w_type(p, Type::Ellipsis),
+        Obj::Bool(false) => w_type(p, Type::False),
+        Obj::Bool(true) => w_type(p, Type::True),
+        Obj::Long(x) => w_long_obj(p, x),
+        Obj::Float(x) => {
+            w_type(p, if p.opts.version > 1 { Type::BinaryFloat } else { Type::Float })?;
+            w_float(p, x)
+        }
+        Obj::Complex(x) => {
+            w_type(p, if p.opts.version > 1 { Type::BinaryComplex } else { Type::Complex })?;
+            w_float(p, x.re)?;
+            w_float(p, x.im)
+        }
+        Obj::Bytes(x) => {
+            w_type(p, Type::String)?;
+            w_pstring(p, x)
+        }
+        Obj::String(x) => w_str(p, x, in_consts && is_identifier_like(x)),
+        Obj::RawString(x) => match std::str::from_utf8(x) {
+            Ok(x) => w_str(p, x, in_consts && is_identifier_like(x)),
+            // Written back as it was read, e.g. with lone surrogates from `surrogatepass`
+            Err(_) => {
+                w_type(p, Type::Unicode)?;
+                w_pstring(p, x)
+            }
+        },
+        Obj::Tuple(x) => w_sequence(p, Type::Tuple, x, in_consts),
+        Obj::List(x) => w_sequence(p, Type::List, x, false),
+        Obj::Dict(x) => {
+            w_type(p, Type::Dict)?;
+            for (key, value) in x {
+                w_object(p, key, false)?;
+                w_object(p, value, false)?;
+            }
+            w_type(p, Type::Null)
+        }
+        Obj::Set(x) => w_sequence(p, Type::Set, x, false),
+        Obj::FrozenSet(x) => w_sequence(p, Type::FrozenSet, x, in_consts),
+        Obj::Code(x) => w_code(p, x),
+        Obj::Unknown { .. } => Err(io::Error::new(io::ErrorKind::InvalidInput, "unmarshallable object")),
+    }
+}
+
+/// # Errors
+/// Returns any error from `writable`, or [`io::ErrorKind::InvalidInput`] for objects too large
+/// for the format.
+pub fn marshal_dump_ex(
+    writable: impl Write,
+    obj: &Obj,
+    opts: MarshalDumpExOptions,
+) -> io::Result<()> {
+    let mut shared = HashSet::new();
+    // Refs were added in version 3
+    if opts.version >= 3 {
+        find_shared(obj, opts.python_version, &mut HashSet::new(), &mut shared);
+    }
+    let mut wf = WFile {
+        writable,
+        opts,
+        flag: 0,
+        shared,
+        refs: HashMap::new(),
+    };
+    w_object(&mut wf, obj, false)
+}
+
+/// # Errors
+/// See [`marshal_dump_ex`].
+pub fn marshal_dump(writable: impl Write, obj: &Obj) -> io::Result<()> {
+    marshal_dump_ex(writable, obj, MarshalDumpExOptions::default())
+}
+
+/// Like Python's `marshal.dumps(obj, version)`.
+/// # Errors
+/// See [`marshal_dump_ex`].
+pub fn marshal_dumps(obj: &Obj, version: u8) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    marshal_dump_ex(
+        &mut buf,
+        obj,
+        MarshalDumpExOptions {
+            version,
+            ..MarshalDumpExOptions::default()
+        },
+    )?;
+    Ok(buf)
+}
+
+/// Writes a code object the way Python `version` lays them out, so that it can be loaded by that
+/// interpreter, e.g. from a `.pyc`.
+/// # Errors
+/// [`Error::MissingFieldForVersion`](errors::Error::MissingFieldForVersion) if `qualname` is
+/// empty for Python 3.11 and later, which always have one, and
+/// [`Error::UnsupportedFieldForVersion`](errors::Error::UnsupportedFieldForVersion) if `code`
+/// uses argument kinds or fields that `version` doesn't have. Otherwise, see
+/// [`marshal_dump_ex`].
+pub fn marshal_dump_code(
+    code: &Code,
+    version: MarshalVersion,
+    writable: impl Write,
+) -> errors::Result<()> {
+    use errors::Error;
+    if version >= MarshalVersion::Py311 && code.qualname.is_empty() {
+        return Err(Error::MissingFieldForVersion {
+            field: "co_qualname",
+            version,
+        });
+    }
+    let unsupported = |field| Err(Error::UnsupportedFieldForVersion { field, version });
+    if version < MarshalVersion::Py311 {
+        if !code.qualname.is_empty() {
+            return unsupported("co_qualname");
+        }
+        if !code.exceptiontable.is_empty() {
+            return unsupported("co_exceptiontable");
+        }
+    }
+    if code.posonlyargcount != 0 && version < MarshalVersion::Py38 {
+        return unsupported("co_posonlyargcount");
+    }
+    if code.kwonlyargcount != 0 && version == MarshalVersion::Py27 {
+        return unsupported("co_kwonlyargcount");
+    }
+    marshal_dump_ex(writable, &Obj::Code(code), MarshalDumpExOptions::for_version(version))?;
+    Ok(())
+}
+
+/// Discards everything written to it, keeping only the count.
+struct ByteCounter(usize);
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The length of `marshal_dumps(obj, version)`, refs included, without keeping the output.
+///
+/// Useful for preallocating a buffer, or for rejecting an object that's too large before
+/// serializing it.
+/// # Errors
+/// See [`marshal_dump_ex`].
+pub fn marshal_size(obj: &Obj, version: u8) -> io::Result<usize> {
+    let mut counter = ByteCounter(0);
+    marshal_dump_ex(
+        &mut counter,
+        obj,
+        MarshalDumpExOptions {
+            version,
+            ..MarshalDumpExOptions::default()
+        },
+    )?;
+    Ok(counter.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{errors::Error, marshal_dump_code, marshal_dump_ex, marshal_dumps, MarshalDumpExOptions};
+    use crate::{
+        read::{marshal_load_ex, marshal_loads, MarshalLoadExOptions},
+        CodeBuilder, MarshalVersion, Obj, ObjArena,
+    };
+    use crate::Int;
+    use num_complex::Complex;
+    use num_traits::pow;
+
+    #[test]
+    fn test_roundtrip() {
+        let arena = ObjArena::new();
+        // Several digits long, but small enough for an i128 without the bigint feature
+        let big = Obj::from_bigint(&arena, -pow(Int::from(3), 80));
+        let long_str = "x".repeat(300);
+        let elems = [
+            Obj::None,
+            Obj::StopIteration,
+            Obj::Ellipsis,
+            Obj::Bool(true),
+            Obj::from_i64(&arena, -1),
+            Obj::from_i64(&arena, 1 << 40),
+            big,
+            Obj::Float(1.5e300),
+            Obj::Complex(Complex::new(-0.5, f64::INFINITY)),
+            Obj::Bytes(b"\x00\xff"),
+            Obj::String("Andr\u{e8}"),
+            Obj::String(&long_str),
+            Obj::List(&[Obj::Tuple(&[])]),
+            Obj::Dict(&[(Obj::String("k"), Obj::Set(&[Obj::Bool(false)]))]),
+            Obj::FrozenSet(&[Obj::None]),
+        ];
+        let obj = Obj::Tuple(&elems);
+        for version in 0..=4 {
+            let bytes = marshal_dumps(&obj, version).unwrap();
+            assert_eq!(marshal_loads(&arena, &bytes).unwrap(), obj, "version {}", version);
+            assert_eq!(obj.marshal_size(version).unwrap(), bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_code_roundtrip() {
+        let arena = ObjArena::new();
+        let input = crate::fixtures::many_codeobjects(3);
+        let load_opts = MarshalLoadExOptions {
+            has_posonlyargcount: false,
+            ..MarshalLoadExOptions::default()
+        };
+        let dump_opts = MarshalDumpExOptions {
+            has_posonlyargcount: false,
+            ..MarshalDumpExOptions::default()
+        };
+        let obj = marshal_load_ex(&arena, &input[..], load_opts).unwrap();
+        let mut bytes = Vec::new();
+        marshal_dump_ex(&mut bytes, &obj, dump_opts).unwrap();
+        assert_eq!(marshal_load_ex(&arena, &bytes[..], load_opts).unwrap(), obj);
+        // The copies were refs in the input, so they are in the output too
+        assert!(bytes.ends_with(b"r\x00\x00\x00\x00r\x00\x00\x00\x00"));
+    }
+
+    #[test]
+    fn test_dedup() {
+        let arena = ObjArena::new();
+        let elems: Vec<_> = (0..100).map(|_| Obj::from_str(&arena, "spam")).collect();
+        let obj = Obj::Tuple(&elems);
+        // Allocated separately, so nothing is shared yet
+        assert_eq!(
+            marshal_dumps(&obj, 4).unwrap(),
+            [&b")\x64"[..], &b"z\x04spam".repeat(100)].concat()
+        );
+        let deduped = obj.dedup(&arena);
+        assert_eq!(deduped, obj);
+        let expected = [&b")\x64\xfa\x04spam"[..], &b"r\x00\x00\x00\x00".repeat(99)].concat();
+        assert_eq!(marshal_dumps(&deduped, 4).unwrap(), expected);
+        assert_eq!(deduped.marshal_size(4).unwrap(), expected.len());
+        assert_eq!(marshal_loads(&arena, &expected).unwrap(), obj);
+        // Version 2 predates refs
+        assert_eq!(marshal_dumps(&deduped, 2).unwrap(), marshal_dumps(&obj, 2).unwrap());
+
+        // Equal tuples are merged too, but lists aren't
+        let one = Obj::from_i64(&arena, 1);
+        let pair = |x| Obj::Tuple(arena.alloc_slice_copy(&[x, Obj::from_i64(&arena, 1)]));
+        let list = || Obj::List(arena.alloc_slice_copy(&[one]));
+        let elems = [pair(one), pair(one), list(), list()];
+        assert_eq!(
+            marshal_dumps(&Obj::Tuple(&elems).dedup(&arena), 4).unwrap(),
+            &b")\x04\xa9\x02\xe9\x01\x00\x00\x00r\x01\x00\x00\x00r\x00\x00\x00\x00[\x01\x00\x00\x00r\x01\x00\x00\x00[\x01\x00\x00\x00r\x01\x00\x00\x00"[..]
+        );
+    }
+
+    #[test]
+    fn test_short_ascii() {
+        assert_eq!(marshal_dumps(&Obj::String("abc"), 4).unwrap(), b"z\x03abc");
+        assert_eq!(marshal_dumps(&Obj::String("abc"), 3).unwrap(), b"u\x03\x00\x00\x00abc");
+        assert_eq!(marshal_dumps(&Obj::String("\u{e8}"), 4).unwrap(), b"u\x02\x00\x00\x00\xc3\xa8");
+        let long = "a".repeat(256);
+        assert_eq!(
+            marshal_dumps(&Obj::String(&long), 4).unwrap(),
+            [&b"a\x00\x01\x00\x00"[..], long.as_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn test_code_interning() {
+        let consts = [Obj::String("name_like"), Obj::String("not an identifier")];
+        let code = CodeBuilder::new()
+            .with_consts(&consts)
+            .with_names(&["x"])
+            .with_filename("f")
+            .with_name("g")
+            .finish();
+        let contains = |haystack: &[u8], needle: &[u8]| {
+            haystack.windows(needle.len()).any(|window| window == needle)
+        };
+        // Identifier-like constants and names are interned, as CPython does when creating code
+        let v4 = marshal_dumps(&Obj::Code(&code), 4).unwrap();
+        assert!(contains(&v4, b")\x02Z\x09name_likez\x11not an identifier)\x01Z\x01x"));
+        assert!(contains(&v4, b"Z\x01fZ\x01g"));
+        let v3 = marshal_dumps(&Obj::Code(&code), 3).unwrap();
+        assert!(contains(&v3, b"t\x01\x00\x00\x00x"));
+        // Version 0 predates interning
+        let v0 = marshal_dumps(&Obj::Code(&code), 0).unwrap();
+        assert!(contains(&v0, b"u\x01\x00\x00\x00x"));
+        assert!(!contains(&v0, b"t\x01\x00\x00\x00x"));
+    }
+
+    #[test]
+    fn test_dump_code() {
+        let arena = ObjArena::new();
+        let consts = [Obj::String("doc"), Obj::None];
+        let builder = CodeBuilder::new()
+            .with_argcount(2)
+            .with_code(b"d\x00S\x00")
+            .with_consts(&consts)
+            .with_names(&["x"])
+            .with_filename("m.py")
+            .with_name("f");
+        let code = builder.finish();
+        for &version in &[MarshalVersion::Py27, MarshalVersion::Py37, MarshalVersion::Py38, MarshalVersion::Py310] {
+            let mut out = Vec::new();
+            marshal_dump_code(&code, version, &mut out).unwrap();
+            let opts = MarshalLoadExOptions::for_version(version);
+            let read = marshal_load_ex(&arena, &out[..], opts).unwrap();
+            assert_eq!(read, Obj::Code(&code), "{:?}", version);
+        }
+        // Python 3.7 leaves out co_posonlyargcount
+        let size = |version| {
+            let mut out = Vec::new();
+            marshal_dump_code(&code, version, &mut out).unwrap();
+            out.len()
+        };
+        assert_eq!(size(MarshalVersion::Py37) + 4, size(MarshalVersion::Py38));
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            marshal_dump_code(&code, MarshalVersion::Py311, &mut out),
+            Err(Error::MissingFieldForVersion { field: "co_qualname", version: MarshalVersion::Py311 })
+        ));
+        // Python 3.11 merges the variable names, listing `a` once as a local and a cell
+        let code = builder
+            .with_nlocals(2)
+            .with_varnames(&["a", "b"])
+            .with_cellvars(&["a", "c"])
+            .with_freevars(&["d"])
+            .with_qualname("C.f")
+            .with_exceptiontable(b"\x83\x0a\x0e\x00")
+            .finish();
+        for &version in &[MarshalVersion::Py311, MarshalVersion::Py312] {
+            let mut out = Vec::new();
+            marshal_dump_code(&code, version, &mut out).unwrap();
+            let opts = MarshalLoadExOptions::for_version(version);
+            let read = marshal_load_ex(&arena, &out[..], opts).unwrap();
+            assert_eq!(read, Obj::Code(&code), "{:?}", version);
+        }
+        assert!(matches!(
+            marshal_dump_code(&code, MarshalVersion::Py310, &mut out),
+            Err(Error::UnsupportedFieldForVersion { field: "co_qualname", .. })
+        ));
+        let posonly = builder.with_posonlyargcount(1).finish();
+        assert!(matches!(
+            marshal_dump_code(&posonly, MarshalVersion::Py37, &mut out),
+            Err(Error::UnsupportedFieldForVersion { field: "co_posonlyargcount", .. })
+        ));
+        marshal_dump_code(&posonly, MarshalVersion::Py38, &mut out).unwrap();
+        let kwonly = builder.with_kwonlyargcount(1).finish();
+        let err = marshal_dump_code(&kwonly, MarshalVersion::Py27, &mut out).unwrap_err();
+        assert_eq!(err.to_string(), "Py27 code objects have no co_kwonlyargcount, but it's set");
+    }
+}
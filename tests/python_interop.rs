@@ -0,0 +1,119 @@
+//! Compares `marshal_loads` against the bytes CPython's own `marshal.dumps` produces.
+//!
+//! Run with `cargo test --features python-interop`; skipped when there's no `python3` on `PATH`.
+#![cfg(feature = "python-interop")]
+
+use num_bigint::BigInt;
+use num_complex::Complex;
+use num_traits::Pow;
+use py_marshal::{read::marshal_loads, Obj, ObjArena};
+use std::process::Command;
+
+/// Returns `marshal.dumps(expr, version)`, or `None` if `python3` can't be run.
+fn python_dumps(expr: &str, version: u8) -> Option<Vec<u8>> {
+    let script = format!(
+        "import marshal, sys; sys.stdout.buffer.write(marshal.dumps({}, {}))",
+        expr, version
+    );
+    let output = Command::new("python3").args(["-c", &script]).output().ok()?;
+    assert!(
+        output.status.success(),
+        "python3 failed on {:?}: {}",
+        expr,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(output.stdout)
+}
+
+fn python_version() -> Option<String> {
+    let output = Command::new("python3").arg("--version").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+#[allow(clippy::type_complexity)]
+fn cases() -> Vec<(&'static str, Box<dyn for<'a> Fn(&'a ObjArena) -> Obj<'a>>)> {
+    vec![
+        ("None", Box::new(|_| Obj::None)),
+        ("StopIteration", Box::new(|_| Obj::StopIteration)),
+        ("...", Box::new(|_| Obj::Ellipsis)),
+        ("True", Box::new(|_| Obj::Bool(true))),
+        ("False", Box::new(|_| Obj::Bool(false))),
+        ("0", Box::new(|a| Obj::from_i64(a, 0))),
+        ("-1", Box::new(|a| Obj::from_i64(a, -1))),
+        ("2**31 - 1", Box::new(|a| Obj::from_i64(a, i64::from(i32::MAX)))),
+        ("-2**31", Box::new(|a| Obj::from_i64(a, i64::from(i32::MIN)))),
+        ("2**31", Box::new(|a| Obj::from_i64(a, 1 << 31))),
+        ("2**63", Box::new(|a| Obj::from_u64(a, 1 << 63))),
+        ("-2**100", Box::new(|a| Obj::from_bigint(a, -BigInt::from(2).pow(100_u32)))),
+        ("1.5", Box::new(|_| Obj::Float(1.5))),
+        ("-0.0", Box::new(|_| Obj::Float(-0.0))),
+        ("float('inf')", Box::new(|_| Obj::Float(f64::INFINITY))),
+        ("1.5-2.25j", Box::new(|_| Obj::Complex(Complex::new(1.5, -2.25)))),
+        ("b''", Box::new(|a| Obj::from_bytes(a, b""))),
+        ("b'a\\x00b'", Box::new(|a| Obj::from_bytes(a, b"a\0b"))),
+        ("''", Box::new(|a| Obj::from_str(a, ""))),
+        ("'abc'", Box::new(|a| Obj::from_str(a, "abc"))),
+        ("'Andr\\xe8 \\U0001f600'", Box::new(|a| Obj::from_str(a, "Andr\u{e8} \u{1f600}"))),
+        ("'x' * 300", Box::new(|a| Obj::from_str(a, &"x".repeat(300)))),
+        ("()", Box::new(|_| Obj::Tuple(&[]))),
+        ("(1, 'a')", Box::new(|a| Obj::Tuple(a.alloc_slice_copy(&[Obj::from_i64(a, 1), Obj::from_str(a, "a")])))),
+        ("[]", Box::new(|_| Obj::List(&[]))),
+        ("[1, [2]]", Box::new(|a| {
+            let inner = Obj::List(a.alloc_slice_copy(&[Obj::from_i64(a, 2)]));
+            Obj::List(a.alloc_slice_copy(&[Obj::from_i64(a, 1), inner]))
+        })),
+        ("{}", Box::new(|_| Obj::Dict(&[]))),
+        ("{'a': (1,)}", Box::new(|a| {
+            let value = Obj::Tuple(a.alloc_slice_copy(&[Obj::from_i64(a, 1)]));
+            Obj::Dict(a.alloc_slice_copy(&[(Obj::from_str(a, "a"), value)]))
+        })),
+        ("{1}", Box::new(|a| Obj::Set(a.alloc_slice_copy(&[Obj::from_i64(a, 1)])))),
+        ("frozenset()", Box::new(|_| Obj::FrozenSet(&[]))),
+        ("('ab', 'ab')", Box::new(|a| {
+            let ab = Obj::from_str(a, "ab");
+            Obj::Tuple(a.alloc_slice_copy(&[ab, ab]))
+        })),
+    ]
+}
+
+#[test]
+fn test_python_values() {
+    if python_version().is_none() {
+        eprintln!("python3 not found, skipping");
+        return;
+    }
+    let arena = ObjArena::new();
+    for (expr, expected) in cases() {
+        for version in 0..=4 {
+            let bytes = python_dumps(expr, version).unwrap();
+            let obj = marshal_loads(&arena, &bytes)
+                .unwrap_or_else(|err| panic!("{} (version {}): {}", expr, version, err));
+            assert_eq!(obj, expected(&arena), "{} (version {})", expr, version);
+        }
+    }
+}
+
+#[test]
+fn test_python_code() {
+    let version = match python_version() {
+        Some(version) => version,
+        None => {
+            eprintln!("python3 not found, skipping");
+            return;
+        }
+    };
+    // Code objects are only readable for the layout the reader implements
+    if !["Python 3.8", "Python 3.9", "Python 3.10"]
+        .iter()
+        .any(|prefix| version.starts_with(prefix))
+    {
+        eprintln!("{} writes an unsupported code object layout, skipping", version);
+        return;
+    }
+    let arena = ObjArena::new();
+    let bytes = python_dumps("compile('x = 1', '<interop>', 'exec')", 4).unwrap();
+    let code = marshal_loads(&arena, &bytes).unwrap().extract_code().unwrap();
+    assert_eq!(code.filename, "<interop>");
+    assert_eq!(code.name, "<module>");
+    assert_eq!(code.names, ["x"]);
+}
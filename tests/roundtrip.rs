@@ -0,0 +1,160 @@
+//! Property tests: anything the serializer writes, the reader reads back unchanged.
+#![cfg(feature = "bigint")]
+use num_bigint::{BigInt, Sign};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use py_marshal::{read::marshal_loads, write::marshal_dumps, CodeBuilder, Obj, ObjArena};
+
+/// An owned stand-in for [`Obj`], since strategies can't borrow from an arena.
+#[derive(Clone, Debug)]
+enum Value {
+    None,
+    StopIteration,
+    Ellipsis,
+    Bool(bool),
+    Long(BigInt),
+    Float(f64),
+    Complex(f64, f64),
+    Bytes(Vec<u8>),
+    String(String),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    FrozenSet(Vec<Value>),
+    Code {
+        argcount: u32,
+        code: Vec<u8>,
+        consts: Vec<Value>,
+        names: Vec<String>,
+        name: String,
+    },
+}
+
+impl Value {
+    fn to_obj<'a>(&self, arena: &'a ObjArena) -> Obj<'a> {
+        let objs = |x: &[Value]| -> &'a [Obj<'a>] {
+            arena.alloc_slice_copy(&x.iter().map(|x| x.to_obj(arena)).collect::<Vec<_>>())
+        };
+        match self {
+            Value::None => Obj::None,
+            Value::StopIteration => Obj::StopIteration,
+            Value::Ellipsis => Obj::Ellipsis,
+            Value::Bool(x) => Obj::Bool(*x),
+            Value::Long(x) => Obj::from_bigint(arena, x.clone()),
+            Value::Float(x) => Obj::Float(*x),
+            Value::Complex(re, im) => Obj::Complex(num_complex::Complex::new(*re, *im)),
+            Value::Bytes(x) => Obj::from_bytes(arena, x),
+            Value::String(x) => Obj::from_str(arena, x),
+            Value::Tuple(x) => Obj::Tuple(objs(x)),
+            Value::List(x) => Obj::List(objs(x)),
+            Value::Set(x) => Obj::Set(objs(x)),
+            Value::FrozenSet(x) => Obj::FrozenSet(objs(x)),
+            Value::Dict(x) => Obj::Dict(
+                arena.alloc_slice_copy(
+                    &x.iter()
+                        .map(|(k, v)| (k.to_obj(arena), v.to_obj(arena)))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            Value::Code {
+                argcount,
+                code,
+                consts,
+                names,
+                name,
+            } => {
+                let names: Vec<&str> = names.iter().map(|x| &*arena.alloc_str(x)).collect();
+                Obj::Code(
+                    CodeBuilder::new()
+                        .with_argcount(*argcount)
+                        .with_code(arena.alloc_slice_copy(code))
+                        .with_consts(objs(consts))
+                        .with_names(arena.alloc_slice_copy(&names))
+                        .with_name(arena.alloc_str(name))
+                        .build(arena),
+                )
+            }
+        }
+    }
+}
+
+fn long() -> impl Strategy<Value = BigInt> {
+    prop_oneof![
+        any::<i64>().prop_map(BigInt::from),
+        (any::<bool>(), vec(any::<u32>(), 0..6)).prop_map(|(negative, digits)| {
+            BigInt::from_slice(if negative { Sign::Minus } else { Sign::Plus }, &digits)
+        }),
+    ]
+}
+
+fn leaf() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::None),
+        Just(Value::StopIteration),
+        Just(Value::Ellipsis),
+        any::<bool>().prop_map(Value::Bool),
+        long().prop_map(Value::Long),
+        any::<f64>().prop_map(Value::Float),
+        (any::<f64>(), any::<f64>()).prop_map(|(re, im)| Value::Complex(re, im)),
+        vec(any::<u8>(), 0..300).prop_map(Value::Bytes),
+        prop_oneof!["[a-z_]{0,10}", any::<String>()].prop_map(Value::String),
+    ]
+}
+
+/// Values that can be dict keys and set elements.
+fn hashable() -> impl Strategy<Value = Value> {
+    leaf().prop_recursive(2, 16, 4, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..4).prop_map(Value::Tuple),
+            vec(inner, 0..4).prop_map(Value::FrozenSet),
+        ]
+    })
+}
+
+fn value() -> impl Strategy<Value = Value> {
+    leaf().prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..8).prop_map(Value::Tuple),
+            vec(inner.clone(), 0..8).prop_map(Value::List),
+            vec(hashable(), 0..8).prop_map(Value::Set),
+            vec(hashable(), 0..8).prop_map(Value::FrozenSet),
+            vec((hashable(), inner.clone()), 0..8).prop_map(Value::Dict),
+            (
+                // Larger counts are rejected as a likely version mismatch
+                0_u32..=0xffff,
+                vec(any::<u8>(), 0..32),
+                vec(inner, 0..4),
+                vec("[a-z_]{1,8}", 0..4),
+                "[a-z_<>]{1,10}",
+            )
+                .prop_map(|(argcount, code, consts, names, name)| Value::Code {
+                    argcount,
+                    code,
+                    consts,
+                    names,
+                    name,
+                }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn roundtrip(value in value(), version in 0_u8..=4) {
+        let arena = ObjArena::new();
+        let obj = value.to_obj(&arena);
+        let bytes = marshal_dumps(&obj, version).unwrap();
+        prop_assert_eq!(marshal_loads(&arena, &bytes).unwrap(), obj);
+        prop_assert_eq!(obj.marshal_size(version).unwrap(), bytes.len());
+    }
+
+    /// Sharing equal objects changes which refs are written, but not what's read back.
+    #[test]
+    fn roundtrip_dedup(value in value()) {
+        let arena = ObjArena::new();
+        let obj = value.to_obj(&arena);
+        let bytes = marshal_dumps(&obj.dedup(&arena), 4).unwrap();
+        prop_assert_eq!(marshal_loads(&arena, &bytes).unwrap(), obj);
+    }
+}